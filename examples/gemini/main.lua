@@ -110,44 +110,59 @@ local function send_message()
 		return
 	end
 
-	local url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent"
-	local headers = {
-		["Content-Type"] = "application/json",
-		["X-goog-api-key"] = api_key,
-	}
-	local body = app.json.stringify({
-		contents = {
-			{
-				parts = {
-					{ text = message_text },
-				},
-			},
-		},
-	})
+	local url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:streamGenerateContent"
+	local answer = ""
+	local answer_widget = nil
 
-	fetch_async("POST", url, headers, body, function(response)
+	local function stop_indicator()
 		stop_thinking_animation = true
-		indicator_widget:destroy()
+		if indicator_widget then
+			indicator_widget:destroy()
+			indicator_widget = nil
+		end
+	end
 
-		local response_text_to_add = nil
+	Http.stream({
+		method = "POST",
+		url = url,
+		query = { alt = "sse" },
+		headers = { ["X-goog-api-key"] = api_key },
+		json = {
+			contents = {
+				{
+					parts = {
+						{ text = message_text },
+					},
+				},
+			},
+		},
+	}, function(event)
+		local data = app.json.parse(event.data)
+		local candidate = data and data.candidates and data.candidates[1]
+		local text = candidate and candidate.content and candidate.content.parts[1].text
+		if not text then
+			return
+		end
 
-		if response.err then
-			response_text_to_add = "Error: " .. response.err
+		answer = answer .. text
+		if not answer_widget then
+			stop_indicator()
+			answer_widget = add_message(answer, "gemini")
 		else
-			local data = app.json.parse(response.ok)
-
-			if data and data.candidates and #data.candidates > 0 then
-				response_text_to_add = data.candidates[1].content.parts[1].text
-			else
-				response_text_to_add = "Error: Invalid response from Gemini API."
-			end
+			answer_widget:set_property("label", app.markdown_to_pango(answer))
+			scroll_to_bottom()
 		end
-
-		add_message(response_text_to_add, "gemini")
-		scroll_to_bottom()
-
+	end, function(response, err)
+		stop_indicator()
 		entry_input:set_property("sensitive", true)
 		send_button:set_property("sensitive", true)
+		if err then
+			add_message("Error: " .. err, "gemini")
+		elseif not response.ok then
+			add_message("Error: Gemini API returned HTTP " .. response.status, "gemini")
+		elseif answer == "" then
+			add_message("Error: Invalid response from Gemini API.", "gemini")
+		end
 	end)
 end
 
@@ -1,11 +1,15 @@
 use crate::core::context::AppContext;
+use crate::core::control::ControlServer;
 use crate::core::error::{self, AppError};
 use crate::scripting::lua_driver::LuaWrapper;
 use crate::scripting::traits::ScriptValue;
 use crate::ui::builder::UiBuilder;
+use crate::ui::lifecycle;
+use crate::ui::monitors;
+use crate::ui::schema::{ConfigSchema, MonitorSpec, WindowSchema, join_errors};
 use crate::ui::strategy::WindowStrategy;
 
-use gtk4::gdk::Display;
+use gtk4::gdk::{self, Display};
 use gtk4::{Application, CssProvider, prelude::*};
 use mlua::{Function, Lua};
 use std::cell::RefCell;
@@ -51,6 +55,20 @@ impl App {
         let context_clone_for_reload = self.context.clone();
         let ui_builder_clone_for_reload = self.ui_builder.clone();
 
+        let control_server: Rc<RefCell<Option<ControlServer>>> = Rc::new(RefCell::new(None));
+        let control_lua = self.lua.clone();
+        let control_instance = self.context.instance.clone();
+        let control_slot = control_server.clone();
+        self.app.connect_startup(move |app| {
+            match ControlServer::start(app, control_lua.clone(), &control_instance) {
+                Ok(server) => *control_slot.borrow_mut() = Some(server),
+                Err(e) => eprintln!("Failed to start control server: {}", e),
+            }
+        });
+        self.app.connect_shutdown(move |_| {
+            control_server.borrow_mut().take();
+        });
+
         self.app.connect_activate(move |app| {
             if let Err(e) =
                 Self::load_and_build_ui(app, &lua, &context, windowed, ui_builder.clone())
@@ -64,6 +82,7 @@ impl App {
                 "reload",
                 self.lua.create_function(move |_, ()| {
                     for window in app_clone_for_reload.windows() {
+                        lifecycle::destroy_tree(window.upcast_ref());
                         window.destroy();
                     }
                     if let Err(e) = Self::load_and_build_ui(
@@ -98,11 +117,14 @@ impl App {
 
         match Self::load_and_execute_lua_config(app, lua, &context.main_file_path) {
             Ok(table) => {
-                if let Err(e) = Self::validate_lua_config_table(app, &table) {
-                    let app_err = AppError::AppSetupError(e);
-                    error::handle_error(app, "Invalid Configuration", &app_err);
-                    return Err(app_err);
-                }
+                let schema = match Self::validate_lua_config_table(app, &table) {
+                    Ok(schema) => schema,
+                    Err(e) => {
+                        let app_err = AppError::AppSetupError(e);
+                        error::handle_error(app, "Invalid Configuration", &app_err);
+                        return Err(app_err);
+                    }
+                };
 
                 Self::setup_lua_actions(app, lua, &table).map_err(AppError::LuaError)?;
 
@@ -111,9 +133,16 @@ impl App {
                     app.set_menubar(Some(&menubar));
                 }
 
-                Self::load_app_css(main_file_path, &table)?;
+                Self::load_app_css(main_file_path, &schema)?;
 
-                Self::build_ui_windows(app, lua, &config_dir, windowed, ui_builder, &table)?;
+                Self::build_ui_windows(
+                    app,
+                    lua,
+                    &config_dir,
+                    windowed,
+                    ui_builder,
+                    schema.windows,
+                )?;
 
 
                 match lua.globals().get::<mlua::Table>("app") {
@@ -162,32 +191,12 @@ impl App {
         }
     }
 
-    fn validate_lua_config_table(_app: &Application, table: &mlua::Table) -> Result<(), String> {
-        let valid_keys = [
-            "type",
-            "window_mode",
-            "layer",
-            "anchors",
-            "css_path",
-            "css",
-            "properties",
-            "children",
-            "signals",
-            "keymaps",
-            "margins",
-            "auto_exclusive_zone",
-            "keyboard_mode",
-            "actions",
-            "menu",
-            "id",
-            "realize",
-        ];
-        for (key, _) in table.pairs::<String, mlua::Value>().flatten() {
-            if !valid_keys.contains(&key.as_str()) && key.parse::<i32>().is_err() {
-                return Err(format!("Unknown configuration property: '{}'", key));
-            }
-        }
-        Ok(())
+    fn validate_lua_config_table(
+        _app: &Application,
+        table: &mlua::Table,
+    ) -> Result<ConfigSchema<LuaWrapper>, String> {
+        ConfigSchema::from_script(&LuaWrapper(mlua::Value::Table(table.clone())))
+            .map_err(|errors| join_errors(&errors))
     }
 
     fn setup_lua_actions(
@@ -218,7 +227,10 @@ impl App {
         Ok(())
     }
 
-    fn load_app_css(main_file_path: &Path, table: &mlua::Table) -> Result<(), AppError> {
+    fn load_app_css(
+        main_file_path: &Path,
+        schema: &ConfigSchema<LuaWrapper>,
+    ) -> Result<(), AppError> {
         let load_provider = |p: &CssProvider| {
             if let Some(display) = Display::default() {
                 gtk4::style_context_add_provider_for_display(
@@ -229,7 +241,7 @@ impl App {
             }
         };
 
-        if let Ok(rel_path) = table.get::<String>("css_path") {
+        if let Some(rel_path) = &schema.css_path {
             let mut css_file = main_file_path
                 .parent()
                 .ok_or_else(|| {
@@ -252,9 +264,9 @@ impl App {
                 eprintln!("Warn: CSS file not found at {:?}", css_file);
             }
         }
-        if let Ok(css_content) = table.get::<String>("css") {
+        if let Some(css_content) = &schema.css {
             let provider = CssProvider::new();
-            provider.load_from_data(&css_content);
+            provider.load_from_data(css_content);
             load_provider(&provider);
         }
         Ok(())
@@ -267,19 +279,8 @@ impl App {
         config_dir: &Path,
         windowed: bool,
         ui_builder: Rc<RefCell<UiBuilder>>,
-        table: &mlua::Table,
+        window_configs: Vec<LuaWrapper>,
     ) -> Result<(), AppError> {
-        let first_item = table.raw_get(1);
-        let window_configs = if let Ok(mlua::Value::Table(_)) = first_item {
-            table
-                .sequence_values::<mlua::Table>()
-                .filter_map(Result::ok)
-                .map(mlua::Value::Table)
-                .collect()
-        } else {
-            vec![mlua::Value::Table(table.clone())]
-        };
-
         let app_global: mlua::Table = if let Ok(table) = lua.globals().get("app") {
             table
         } else {
@@ -290,56 +291,57 @@ impl App {
             table
         };
 
-        let windows_table = lua.create_table().map_err(AppError::LuaError)?;
+        MONITOR_HANDLERS.with_borrow_mut(|handlers| {
+            for (model, handler) in handlers.drain(..) {
+                model.disconnect(handler);
+            }
+        });
 
-        for config in window_configs {
-            let wrapped = LuaWrapper(config);
+        let factory = WindowFactory {
+            app: app.clone(),
+            config_dir: config_dir.to_path_buf(),
+            windowed,
+            ui_builder,
+            windows_table: lua.create_table().map_err(AppError::LuaError)?,
+        };
 
-            let mut builder = ui_builder.borrow_mut();
-            builder
-                .register_behavior(
-                    "GtkApplicationWindow",
-                    Box::new(WindowStrategy::new(windowed)),
-                )
-                .register_behavior("GtkWindow", Box::new(WindowStrategy::new(windowed)));
-            match builder.build(&wrapped, config_dir) {
-                Ok(root) => {
-                    if let Some(w) = root.downcast_ref::<gtk4::Window>() {
-                        w.set_application(Some(app));
-
-                        let title_str_opt = if let Some(title_prop) = wrapped.get_property("title")
-                            && let Some(title_str) = title_prop.as_string()
-                        {
-                            Some(title_str)
-                        } else if let Some(props_wrapper) = wrapped.get_property("properties")
-                            && let Some(title_prop_from_props) = props_wrapper.get_property("title")
-                            && let Some(title_str_from_props) = title_prop_from_props.as_string()
-                        {
-                            Some(title_str_from_props)
-                        } else {
-                            None
-                        };
-
-                        if let Some(title_str) = title_str_opt {
-                            windows_table
-                                .set(
-                                    title_str,
-                                    crate::scripting::widget_wrapper::LuaWidget(root.clone()),
-                                )
-                                .map_err(AppError::LuaError)?;
-                        }
-                    }
-                }
-                Err(e) => {
-                    let app_err = AppError::GtkError(format!("UI Builder Error: {}", e));
-                    error::handle_error(app, "UI Builder Error", &app_err);
-                    return Err(app_err);
-                }
+        let mut per_monitor = Vec::new();
+        for wrapped in window_configs {
+            let on_all = match &wrapped.0 {
+                mlua::Value::Function(_) => true,
+                _ => WindowSchema::from_script(&wrapped)
+                    .is_ok_and(|schema| schema.monitor == Some(MonitorSpec::All)),
+            };
+            if on_all {
+                per_monitor.push(PerMonitor {
+                    definition: wrapped.0,
+                    copies: Vec::new(),
+                });
+            } else {
+                factory.build(&wrapped, None)?;
             }
         }
 
+        if !per_monitor.is_empty()
+            && let Some(display) = Display::default()
+        {
+            for windows in &mut per_monitor {
+                windows.sync(lua, &factory, &display);
+            }
+            let model = display.monitors();
+            let lua = lua.clone();
+            let factory = factory.clone();
+            let per_monitor = RefCell::new(per_monitor);
+            let handler = model.connect_items_changed(move |_, _, _, _| {
+                for windows in per_monitor.borrow_mut().iter_mut() {
+                    windows.sync(&lua, &factory, &display);
+                }
+            });
+            MONITOR_HANDLERS.with_borrow_mut(|handlers| handlers.push((model, handler)));
+        }
+
         app_global
-            .set("windows", windows_table)
+            .set("windows", factory.windows_table)
             .map_err(AppError::LuaError)?;
 
         if let Err(e) = UiBuilder::register_get_widget_by_id_lua_function(lua, &app_global) {
@@ -358,26 +360,7 @@ impl App {
         lua: &Rc<Lua>,
         main_file_path: &PathBuf,
     ) -> mlua::Result<mlua::Table> {
-        let config_dir = main_file_path
-            .parent()
-            .unwrap_or_else(|| Path::new("."))
-            .to_path_buf();
-
-        if let Some(main_file_str) = main_file_path.to_str() {
-            lua.globals()
-                .set("INK_MAIN_FILE_PATH", main_file_str)
-                .map_err(AppError::LuaError)?;
-        }
-
-        let package: mlua::Table = lua.globals().get("package").map_err(AppError::LuaError)?;
-        let old_path: String = package.get("path").map_err(AppError::LuaError)?;
-        let new_path = format!(
-            "{}/?.lua;{}/?/init.lua;{}",
-            config_dir.to_string_lossy(),
-            config_dir.to_string_lossy(),
-            old_path
-        );
-        package.set("path", new_path).map_err(AppError::LuaError)?;
+        prepare_lua_environment(lua, main_file_path)?;
 
         if !main_file_path.exists() {
             let app_err = AppError::IoError(std::io::Error::new(
@@ -398,6 +381,199 @@ impl App {
         })
     }
 }
+thread_local! {
+    /// Hotplug handlers of the per-monitor windows, replaced on every build.
+    static MONITOR_HANDLERS: RefCell<Vec<(gio::ListModel, glib::SignalHandlerId)>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// What building a window definition needs, kept for windows created later
+/// on monitor hotplug.
+#[derive(Clone)]
+struct WindowFactory {
+    app: Application,
+    config_dir: PathBuf,
+    windowed: bool,
+    ui_builder: Rc<RefCell<UiBuilder>>,
+    windows_table: mlua::Table,
+}
+
+impl WindowFactory {
+    /// Builds a window and adds it to `app.windows` under its title, followed
+    /// by `:suffix` for per-monitor copies.
+    fn build(
+        &self,
+        wrapped: &LuaWrapper,
+        suffix: Option<&str>,
+    ) -> Result<(Option<gtk4::Window>, Option<String>), AppError> {
+        let mut builder = self.ui_builder.borrow_mut();
+        builder
+            .register_behavior(
+                "GtkApplicationWindow",
+                Box::new(WindowStrategy::new(self.windowed)),
+            )
+            .register_behavior("GtkWindow", Box::new(WindowStrategy::new(self.windowed)));
+        let root = match builder.build(wrapped, &self.config_dir) {
+            Ok(root) => root,
+            Err(e) => {
+                let app_err = AppError::GtkError(format!("UI Builder Error: {}", e));
+                error::handle_error(&self.app, "UI Builder Error", &app_err);
+                return Err(app_err);
+            }
+        };
+        let Some(w) = root.downcast_ref::<gtk4::Window>() else {
+            return Ok((None, None));
+        };
+        w.set_application(Some(&self.app));
+
+        let title_str_opt = if let Some(title_prop) = wrapped.get_property("title")
+            && let Some(title_str) = title_prop.as_string()
+        {
+            Some(title_str)
+        } else if let Some(props_wrapper) = wrapped.get_property("properties")
+            && let Some(title_prop_from_props) = props_wrapper.get_property("title")
+            && let Some(title_str_from_props) = title_prop_from_props.as_string()
+        {
+            Some(title_str_from_props)
+        } else {
+            None
+        };
+
+        let key = title_str_opt.map(|title| match suffix {
+            Some(suffix) => format!("{}:{}", title, suffix),
+            None => title,
+        });
+        if let Some(key) = &key {
+            self.windows_table
+                .set(
+                    key.as_str(),
+                    crate::scripting::widget_wrapper::LuaWidget(root.clone()),
+                )
+                .map_err(AppError::LuaError)?;
+        }
+        Ok((Some(w.clone()), key))
+    }
+}
+
+struct MonitorCopy {
+    monitor: gdk::Monitor,
+    window: gtk4::Window,
+    key: Option<String>,
+}
+
+/// A window definition with one copy per monitor: a table with
+/// `monitor = "all"`, or a function called with each monitor's info that
+/// returns a definition or nil to skip that monitor.
+struct PerMonitor {
+    definition: mlua::Value,
+    copies: Vec<MonitorCopy>,
+}
+
+impl PerMonitor {
+    /// Destroys the copies on removed monitors and creates the missing ones.
+    fn sync(&mut self, lua: &Lua, factory: &WindowFactory, display: &gdk::Display) {
+        let current = monitors::list(display);
+        self.copies.retain(|copy| {
+            if current.contains(&copy.monitor) {
+                return true;
+            }
+            lifecycle::destroy_tree(copy.window.upcast_ref());
+            copy.window.destroy();
+            if let Some(key) = &copy.key {
+                let _ = factory.windows_table.set(key.as_str(), mlua::Value::Nil);
+            }
+            false
+        });
+        for (i, monitor) in current.iter().enumerate() {
+            if self.copies.iter().any(|copy| copy.monitor == *monitor) {
+                continue;
+            }
+            match self.instantiate(lua, factory, i + 1, monitor) {
+                Ok(Some(copy)) => self.copies.push(copy),
+                Ok(None) => {}
+                Err(e) => eprintln!("Error creating window for monitor {}: {}", i + 1, e),
+            }
+        }
+    }
+
+    fn instantiate(
+        &self,
+        lua: &Lua,
+        factory: &WindowFactory,
+        index: usize,
+        monitor: &gdk::Monitor,
+    ) -> Result<Option<MonitorCopy>, AppError> {
+        let source = match &self.definition {
+            mlua::Value::Function(build) => {
+                let info = monitors::info(lua, index, monitor).map_err(AppError::LuaError)?;
+                match build
+                    .call::<mlua::Value>(info)
+                    .map_err(AppError::LuaError)?
+                {
+                    mlua::Value::Table(table) => table,
+                    mlua::Value::Nil => return Ok(None),
+                    other => {
+                        return Err(AppError::LuaError(mlua::Error::runtime(format!(
+                            "Window function must return a table or nil, got {}",
+                            other.type_name()
+                        ))));
+                    }
+                }
+            }
+            mlua::Value::Table(table) => table.clone(),
+            _ => return Ok(None),
+        };
+
+        // A shallow copy that targets this monitor, so the definition itself
+        // can be instantiated again.
+        let definition = lua.create_table().map_err(AppError::LuaError)?;
+        for pair in source.pairs::<mlua::Value, mlua::Value>() {
+            let (k, v) = pair.map_err(AppError::LuaError)?;
+            definition.raw_set(k, v).map_err(AppError::LuaError)?;
+        }
+        let connector = monitor.connector().map(|c| c.to_string());
+        let target = match &connector {
+            Some(name) => mlua::Value::String(lua.create_string(name).map_err(AppError::LuaError)?),
+            None => mlua::Value::Integer(index as i64),
+        };
+        definition
+            .set("monitor", target)
+            .map_err(AppError::LuaError)?;
+
+        let suffix = connector.unwrap_or_else(|| index.to_string());
+        let wrapped = LuaWrapper(mlua::Value::Table(definition));
+        let (window, key) = factory.build(&wrapped, Some(&suffix))?;
+        Ok(window.map(|window| MonitorCopy {
+            monitor: monitor.clone(),
+            window,
+            key,
+        }))
+    }
+}
+
+pub fn prepare_lua_environment(lua: &Lua, main_file_path: &Path) -> mlua::Result<()> {
+    let config_dir = main_file_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .to_path_buf();
+
+    if let Some(main_file_str) = main_file_path.to_str() {
+        lua.globals()
+            .set("INK_MAIN_FILE_PATH", main_file_str)
+            .map_err(AppError::LuaError)?;
+    }
+
+    let package: mlua::Table = lua.globals().get("package").map_err(AppError::LuaError)?;
+    let old_path: String = package.get("path").map_err(AppError::LuaError)?;
+    let new_path = format!(
+        "{}/?.lua;{}/?/init.lua;{}",
+        config_dir.to_string_lossy(),
+        config_dir.to_string_lossy(),
+        old_path
+    );
+    package.set("path", new_path).map_err(AppError::LuaError)?;
+    Ok(())
+}
 fn load_lua_script(lua: &Lua, path: &Path) -> mlua::Result<Function> {
     let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
     let cache_dir = PathBuf::from(home).join(".cache/ink/bytecode");
@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 #[derive(Parser, Debug, Clone)]
 #[command(name = "ink", author, version, about)]
@@ -10,6 +11,10 @@ pub struct Config {
 
     #[arg(long, global = true)]
     pub windowed: bool,
+
+    /// Name of the instance, used for the application id and control socket
+    #[arg(long, global = true, default_value = "ui")]
+    pub instance: String,
 }
 #[derive(Subcommand, Debug, Clone)]
 pub enum Commands {
@@ -17,6 +22,37 @@ pub enum Commands {
         #[arg(short, long)]
         dir: Option<PathBuf>,
     },
+    /// Validate a config without opening any windows
+    Check,
+    /// Open an interactive Lua prompt in a running instance
+    Repl,
+    /// Send a command to a running instance
+    Msg {
+        #[command(subcommand)]
+        message: Message,
+    },
+}
+#[derive(Subcommand, Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Message {
+    /// Reload the configuration
+    Reload,
+    /// Toggle the visibility of a window by id or title
+    Toggle { target: String },
+    /// Show a window by id or title
+    Show { target: String },
+    /// Hide a window by id or title
+    Hide { target: String },
+    /// Activate an application action
+    Action { name: String },
+    /// Evaluate a Lua snippet and print the result
+    Eval { code: String },
+    /// List the open windows
+    Windows,
+    #[command(hide = true)]
+    Repl { code: String },
+    #[command(hide = true)]
+    Complete { line: String },
 }
 impl Config {
     pub fn parse() -> Self {
@@ -2,9 +2,13 @@ use std::path::PathBuf;
 #[derive(Debug, Clone)]
 pub struct AppContext {
     pub main_file_path: PathBuf,
+    pub instance: String,
 }
 impl AppContext {
-    pub fn new(main_file_path: PathBuf) -> Self {
-        Self { main_file_path }
+    pub fn new(main_file_path: PathBuf, instance: String) -> Self {
+        Self {
+            main_file_path,
+            instance,
+        }
     }
 }
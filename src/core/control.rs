@@ -0,0 +1,506 @@
+use crate::core::config::Message;
+use crate::scripting::globals::get_core_context;
+use gio::prelude::*;
+use gtk4::Application;
+use gtk4::prelude::*;
+use mlua::{Function, Lua, MultiValue, Table};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value as JsonValue, json};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const INSPECT_MAX_DEPTH: usize = 4;
+
+#[derive(Debug)]
+pub enum ControlError {
+    NotRunning(String),
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    GlibError(glib::Error),
+    Remote(String),
+    InsecureDir(PathBuf),
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::NotRunning(instance) => {
+                write!(f, "No running instance named '{}'", instance)
+            }
+            ControlError::IoError(e) => write!(f, "I/O error: {}", e),
+            ControlError::JsonError(e) => write!(f, "Invalid message: {}", e),
+            ControlError::GlibError(e) => write!(f, "Socket error: {}", e),
+            ControlError::Remote(e) => write!(f, "{}", e),
+            ControlError::InsecureDir(path) => write!(
+                f,
+                "Refusing to use '{}': it is not a private directory of the current user",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+impl From<std::io::Error> for ControlError {
+    fn from(err: std::io::Error) -> Self {
+        ControlError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for ControlError {
+    fn from(err: serde_json::Error) -> Self {
+        ControlError::JsonError(err)
+    }
+}
+
+impl From<glib::Error> for ControlError {
+    fn from(err: glib::Error) -> Self {
+        ControlError::GlibError(err)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<JsonValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<Result<Option<JsonValue>, String>> for Response {
+    fn from(result: Result<Option<JsonValue>, String>) -> Self {
+        match result {
+            Ok(result) => Self {
+                ok: true,
+                result,
+                error: None,
+            },
+            Err(error) => Self {
+                ok: false,
+                result: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+pub fn application_id(instance: &str) -> String {
+    format!("dev.ink.{}", instance)
+}
+
+/// The directory of the control sockets. Without `XDG_RUNTIME_DIR` it is a
+/// per-user directory in the shared temp dir, which has to be checked before
+/// use since the sockets evaluate arbitrary Lua.
+fn socket_dir() -> (PathBuf, bool) {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => (PathBuf::from(dir).join("ink"), false),
+        Err(_) => {
+            let uid = unsafe { libc::getuid() };
+            (std::env::temp_dir().join(format!("ink-{}", uid)), true)
+        }
+    }
+}
+
+/// Returns the socket path of `instance`, making sure its directory belongs
+/// to the current user and, in the shared temp dir, is private. With
+/// `create`, a missing directory is created first.
+fn checked_socket_path(instance: &str, create: bool) -> Result<PathBuf, ControlError> {
+    let (dir, shared) = socket_dir();
+    if create {
+        let created = if shared {
+            std::fs::DirBuilder::new().mode(0o700).create(&dir)
+        } else {
+            std::fs::create_dir_all(&dir)
+        };
+        match created {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e.into()),
+            _ => {}
+        }
+    }
+    let metadata = std::fs::symlink_metadata(&dir).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => ControlError::NotRunning(instance.to_string()),
+        _ => ControlError::IoError(e),
+    })?;
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || (shared && metadata.mode() & 0o077 != 0) {
+        return Err(ControlError::InsecureDir(dir));
+    }
+    Ok(dir.join(format!("{}.sock", instance)))
+}
+
+pub fn socket_path(instance: &str) -> Result<PathBuf, ControlError> {
+    checked_socket_path(instance, false)
+}
+
+pub struct ControlClient {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl ControlClient {
+    pub fn connect(instance: &str) -> Result<Self, ControlError> {
+        let stream = UnixStream::connect(socket_path(instance)?).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused => {
+                ControlError::NotRunning(instance.to_string())
+            }
+            _ => ControlError::IoError(e),
+        })?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Self { stream, reader })
+    }
+
+    pub fn request(&mut self, message: &Message) -> Result<Option<JsonValue>, ControlError> {
+        let mut request = serde_json::to_string(message)?;
+        request.push('\n');
+        self.stream.write_all(request.as_bytes())?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ControlError::IoError(
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ));
+        }
+        let response: Response = serde_json::from_str(&line)?;
+        if !response.ok {
+            return Err(ControlError::Remote(response.error.unwrap_or_default()));
+        }
+        Ok(response.result.filter(|r| !r.is_null()))
+    }
+}
+
+/// Sends `message` to a running instance and returns the printable result.
+pub fn send(instance: &str, message: &Message) -> Result<Option<String>, ControlError> {
+    Ok(match ControlClient::connect(instance)?.request(message)? {
+        None => None,
+        Some(JsonValue::String(s)) => Some(s),
+        Some(value) => Some(serde_json::to_string_pretty(&value)?),
+    })
+}
+
+/// Returns the identifier chain at the end of `line` that completion applies to.
+pub fn completion_word(line: &str) -> &str {
+    let start = line
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.' || c == ':'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    &line[start..]
+}
+
+pub struct ControlServer {
+    service: gio::SocketService,
+    path: PathBuf,
+}
+
+impl ControlServer {
+    pub fn start(app: &Application, lua: Rc<Lua>, instance: &str) -> Result<Self, ControlError> {
+        let path = checked_socket_path(instance, true)?;
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let service = gio::SocketService::new();
+        service.add_address(
+            &gio::UnixSocketAddress::new(&path),
+            gio::SocketType::Stream,
+            gio::SocketProtocol::Default,
+            None::<&glib::Object>,
+        )?;
+
+        let app = app.clone();
+        service.connect_incoming(move |_, connection, _| {
+            let app = app.clone();
+            let lua = lua.clone();
+            let connection = connection.clone();
+            glib::MainContext::default().spawn_local(async move {
+                if let Err(e) = serve_connection(&app, &lua, &connection).await {
+                    eprintln!("Control connection error: {}", e);
+                }
+            });
+            false
+        });
+        service.start();
+
+        Ok(Self { service, path })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.service.stop();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn serve_connection(
+    app: &Application,
+    lua: &Lua,
+    connection: &gio::SocketConnection,
+) -> Result<(), ControlError> {
+    let input = gio::DataInputStream::new(&connection.input_stream());
+    let output = connection.output_stream();
+    while let Some(line) = input.read_line_utf8_future(glib::Priority::DEFAULT).await? {
+        let response: Response = match serde_json::from_str::<Message>(&line) {
+            Ok(message) => handle_message(app, lua, message).into(),
+            Err(e) => Err(format!("Invalid message: {}", e)).into(),
+        };
+        let mut reply = serde_json::to_string(&response)?;
+        reply.push('\n');
+        output
+            .write_all_future(reply.into_bytes(), glib::Priority::DEFAULT)
+            .await
+            .map_err(|(_, e)| e)?;
+    }
+    Ok(())
+}
+
+fn find_window(app: &Application, lua: &Lua, target: &str) -> Result<gtk4::Window, String> {
+    let by_id = get_core_context(lua).ok().and_then(|core_context| {
+        core_context
+            .borrow()
+            .ui_builder
+            .borrow()
+            .get_widget_by_id(target)
+    });
+    if let Some(window) = by_id.and_then(|w| w.downcast::<gtk4::Window>().ok()) {
+        return Ok(window);
+    }
+    app.windows()
+        .into_iter()
+        .find(|w| w.title().is_some_and(|t| t == target))
+        .ok_or_else(|| format!("No window with id or title '{}'", target))
+}
+
+fn list_windows(app: &Application, lua: &Lua) -> JsonValue {
+    let core_context = get_core_context(lua).ok();
+    let windows: Vec<JsonValue> = app
+        .windows()
+        .iter()
+        .map(|window| {
+            let id = core_context.as_ref().and_then(|c| {
+                c.borrow()
+                    .ui_builder
+                    .borrow()
+                    .get_widget_id(window.upcast_ref())
+            });
+            json!({
+                "id": id,
+                "title": window.title().map(|t| t.to_string()),
+                "visible": window.is_visible(),
+            })
+        })
+        .collect();
+    JsonValue::Array(windows)
+}
+
+fn handle_message(
+    app: &Application,
+    lua: &Lua,
+    message: Message,
+) -> Result<Option<JsonValue>, String> {
+    match message {
+        Message::Reload => {
+            let reload = lua
+                .globals()
+                .get::<Table>("app")
+                .and_then(|app_table| app_table.get::<Function>("reload"))
+                .map_err(|e| e.to_string())?;
+            reload.call::<()>(()).map_err(|e| e.to_string())?;
+            Ok(None)
+        }
+        Message::Toggle { target } => {
+            let window = find_window(app, lua, &target)?;
+            if window.is_visible() {
+                window.set_visible(false);
+            } else {
+                window.present();
+            }
+            Ok(None)
+        }
+        Message::Show { target } => {
+            find_window(app, lua, &target)?.present();
+            Ok(None)
+        }
+        Message::Hide { target } => {
+            find_window(app, lua, &target)?.set_visible(false);
+            Ok(None)
+        }
+        Message::Action { name } => {
+            let name = name.strip_prefix("app.").unwrap_or(&name);
+            let action = app
+                .lookup_action(name)
+                .ok_or_else(|| format!("No action named '{}'", name))?;
+            action.activate(None);
+            Ok(None)
+        }
+        Message::Eval { code } => {
+            let value = lua
+                .load(&code)
+                .set_name("ink msg")
+                .eval::<mlua::Value>()
+                .map_err(|e| e.to_string())?;
+            match serde_json::to_value(&value) {
+                Ok(json) => Ok(Some(json)),
+                Err(_) => Ok(Some(JsonValue::String(
+                    value.to_string().map_err(|e| e.to_string())?,
+                ))),
+            }
+        }
+        Message::Windows => Ok(Some(list_windows(app, lua))),
+        Message::Repl { code } => repl_eval(lua, &code)
+            .map(|output| Some(JsonValue::String(output)))
+            .map_err(|e| e.to_string()),
+        Message::Complete { line } => complete(lua, completion_word(&line))
+            .map(|names| Some(json!(names)))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn repl_eval(lua: &Lua, code: &str) -> mlua::Result<String> {
+    let chunk = match lua
+        .load(format!("return {}", code))
+        .set_name("=repl")
+        .into_function()
+    {
+        Ok(chunk) => chunk,
+        Err(_) => lua.load(code).set_name("=repl").into_function()?,
+    };
+
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let globals = lua.globals();
+    let original_print: mlua::Value = globals.get("print")?;
+    let captured = output.clone();
+    globals.set(
+        "print",
+        lua.create_function(move |_, args: MultiValue| {
+            let line = args
+                .iter()
+                .map(|v| {
+                    v.to_string()
+                        .unwrap_or_else(|_| format!("<{}>", v.type_name()))
+                })
+                .collect::<Vec<_>>()
+                .join("\t");
+            captured.borrow_mut().push(line);
+            Ok(())
+        })?,
+    )?;
+    let result = chunk.call::<MultiValue>(());
+    globals.set("print", original_print)?;
+
+    let mut lines = output.take();
+    for value in result? {
+        lines.push(inspect(&value, 0));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn inspect(value: &mlua::Value, depth: usize) -> String {
+    match value {
+        mlua::Value::String(s) => format!("{:?}", s.to_string_lossy()),
+        mlua::Value::Table(t) => inspect_table(t, depth),
+        other => other
+            .to_string()
+            .unwrap_or_else(|_| format!("<{}>", other.type_name())),
+    }
+}
+
+fn inspect_table(table: &Table, depth: usize) -> String {
+    if depth >= INSPECT_MAX_DEPTH {
+        return "{...}".to_string();
+    }
+    let len = table.raw_len();
+    let mut entries: Vec<String> = (1..=len)
+        .map(|i| {
+            let value = table.raw_get::<mlua::Value>(i).unwrap_or(mlua::Value::Nil);
+            inspect(&value, depth + 1)
+        })
+        .collect();
+
+    let mut fields: Vec<(String, String)> = table
+        .pairs::<mlua::Value, mlua::Value>()
+        .flatten()
+        .filter(|(k, _)| !matches!(k, mlua::Value::Integer(i) if *i >= 1 && *i as usize <= len))
+        .map(|(k, v)| {
+            let key = match &k {
+                mlua::Value::String(s) if is_identifier(&s.to_string_lossy()) => {
+                    s.to_string_lossy()
+                }
+                other => format!("[{}]", inspect(other, depth + 1)),
+            };
+            (key, inspect(&v, depth + 1))
+        })
+        .collect();
+    fields.sort();
+    entries.extend(fields.into_iter().map(|(k, v)| format!("{} = {}", k, v)));
+
+    if entries.is_empty() {
+        return "{}".to_string();
+    }
+    let indent = "  ".repeat(depth + 1);
+    format!(
+        "{{\n{}{}\n{}}}",
+        indent,
+        entries.join(&format!(",\n{}", indent)),
+        "  ".repeat(depth)
+    )
+}
+
+fn complete(lua: &Lua, word: &str) -> mlua::Result<Vec<String>> {
+    let (base, separator, partial) = match word.rfind(['.', ':']) {
+        Some(i) => (&word[..i], &word[i..i + 1], &word[i + 1..]),
+        None => ("", "", word),
+    };
+    let target = if word.is_empty() || (base.is_empty() && !separator.is_empty()) {
+        return Ok(Vec::new());
+    } else if base.is_empty() {
+        mlua::Value::Table(lua.globals())
+    } else if base.split('.').all(is_identifier) {
+        lua.load(format!("return {}", base))
+            .set_name("=complete")
+            .eval::<mlua::Value>()?
+    } else {
+        return Ok(Vec::new());
+    };
+
+    let mut names = BTreeSet::new();
+    let mut collect = |table: &Table| {
+        for (key, value) in table.pairs::<mlua::Value, mlua::Value>().flatten() {
+            if let mlua::Value::String(key) = key
+                && (separator != ":" || value.is_function())
+            {
+                names.insert(key.to_string_lossy());
+            }
+        }
+    };
+    match &target {
+        mlua::Value::Table(table) => collect(table),
+        mlua::Value::UserData(ud) => {
+            if let Ok(index) = ud.metatable().and_then(|mt| mt.get::<Table>("__index")) {
+                collect(&index);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(names
+        .into_iter()
+        .filter(|name| name.starts_with(partial) && !name.starts_with("__"))
+        .map(|name| format!("{}{}{}", base, separator, name))
+        .collect())
+}
@@ -1,3 +1,4 @@
 pub mod config;
 pub mod context;
+pub mod control;
 pub mod error;
@@ -1,4 +1,5 @@
 use crate::scripting::traits::{ScriptArg, ScriptValue};
+use crate::ui::lifecycle;
 use gtk4::glib::{self, Object};
 use gtk4::prelude::*;
 pub struct SignalConnector;
@@ -55,7 +56,11 @@ impl SignalConnector {
                     _ => None,
                 }
             };
-            match func.call(args) {
+            let result = match widget_clone.downcast_ref::<gtk4::Widget>() {
+                Some(w) => lifecycle::with_owner(w, || func.call(args)),
+                None => func.call(args),
+            };
+            match result {
                 Ok(ret) => handle_return(ret),
                 Err(e) => {
                     eprintln!("Signal Error [{}]: {}", signal_name, e);
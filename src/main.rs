@@ -8,6 +8,7 @@ mod ui;
 use crate::app::App;
 use crate::core::config::{Commands, Config};
 use crate::core::context::AppContext;
+use crate::core::control;
 use crate::ui::builder::UiBuilder;
 use gtk4::prelude::*;
 use std::cell::RefCell;
@@ -27,6 +28,29 @@ async fn main() {
         }
         return;
     }
+    if let Some(Commands::Repl) = config.command {
+        if let Err(e) = tools::repl::run(&config.instance) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Commands::Msg { message }) = &config.command {
+        match control::send(&config.instance, message) {
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let application_id = control::application_id(&config.instance);
+    if !gio::Application::id_is_valid(&application_id) {
+        eprintln!("Invalid instance name: '{}'", config.instance);
+        std::process::exit(1);
+    }
     let target_file = if config.file.is_some() {
         config.file
     } else {
@@ -43,13 +67,34 @@ async fn main() {
             None
         }
     };
+    if let Some(Commands::Check) = config.command {
+        let Some(file) = target_file else {
+            eprintln!("Error: No file provided.");
+            std::process::exit(1);
+        };
+        match tools::check::run(&file, &config.instance) {
+            Ok(diagnostics) if diagnostics.is_empty() => println!("No problems found."),
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    eprintln!("{}", diagnostic);
+                }
+                eprintln!("{} problem(s) found.", diagnostics.len());
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
     if let Some(file) = target_file {
         let app = gtk4::Application::builder()
-            .application_id("dev.ink.ui")
+            .application_id(application_id)
             .build();
         let lua = Rc::new(mlua::Lua::new());
         let ui_builder = Rc::new(RefCell::new(UiBuilder::new(lua.clone())));
-        let context = AppContext::new(file.clone());
+        let context = AppContext::new(file.clone(), config.instance.clone());
         let app_instance_context = Arc::new(context);
 
         if let Err(e) = scripting::globals::init(
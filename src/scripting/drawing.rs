@@ -0,0 +1,457 @@
+use gtk4::cairo::{self, Context, Format, Gradient, ImageSurface, LineCap, LineJoin};
+use gtk4::gdk::RGBA;
+use gtk4::gdk::prelude::GdkCairoContextExt;
+use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::prelude::*;
+use mlua::{Error, FromLua, Function, Lua, UserData, UserDataMethods, Value, Variadic};
+
+fn cairo_err(e: cairo::Error) -> Error {
+    Error::external(e)
+}
+
+fn userdata_from_lua<T: Clone + 'static>(value: Value, name: &str) -> mlua::Result<T> {
+    let ud = value
+        .as_userdata()
+        .ok_or_else(|| Error::FromLuaConversionError {
+            from: value.type_name(),
+            to: name.to_string(),
+            message: Some(format!("Expected a {} object", name)),
+        })?;
+    let inner = ud.borrow::<T>()?;
+    Ok(inner.clone())
+}
+
+fn parse_color(color: &str) -> mlua::Result<RGBA> {
+    RGBA::parse(color).map_err(|_| Error::runtime(format!("Invalid color '{}'", color)))
+}
+
+/// The cairo context passed to `draw` functions.
+#[derive(Clone)]
+pub struct LuaCairo(pub Context);
+
+impl FromLua for LuaCairo {
+    fn from_lua(value: Value, _lua: &Lua) -> mlua::Result<Self> {
+        userdata_from_lua(value, "CairoContext")
+    }
+}
+
+impl UserData for LuaCairo {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("save", |_, this, ()| this.0.save().map_err(cairo_err));
+        methods.add_method("restore", |_, this, ()| this.0.restore().map_err(cairo_err));
+
+        methods.add_method("move_to", |_, this, (x, y): (f64, f64)| {
+            this.0.move_to(x, y);
+            Ok(())
+        });
+        methods.add_method("line_to", |_, this, (x, y): (f64, f64)| {
+            this.0.line_to(x, y);
+            Ok(())
+        });
+        methods.add_method("rel_move_to", |_, this, (dx, dy): (f64, f64)| {
+            this.0.rel_move_to(dx, dy);
+            Ok(())
+        });
+        methods.add_method("rel_line_to", |_, this, (dx, dy): (f64, f64)| {
+            this.0.rel_line_to(dx, dy);
+            Ok(())
+        });
+        methods.add_method(
+            "curve_to",
+            |_, this, (x1, y1, x2, y2, x3, y3): (f64, f64, f64, f64, f64, f64)| {
+                this.0.curve_to(x1, y1, x2, y2, x3, y3);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "rel_curve_to",
+            |_, this, (x1, y1, x2, y2, x3, y3): (f64, f64, f64, f64, f64, f64)| {
+                this.0.rel_curve_to(x1, y1, x2, y2, x3, y3);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "arc",
+            |_, this, (xc, yc, radius, angle1, angle2): (f64, f64, f64, f64, f64)| {
+                this.0.arc(xc, yc, radius, angle1, angle2);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "arc_negative",
+            |_, this, (xc, yc, radius, angle1, angle2): (f64, f64, f64, f64, f64)| {
+                this.0.arc_negative(xc, yc, radius, angle1, angle2);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "rectangle",
+            |_, this, (x, y, width, height): (f64, f64, f64, f64)| {
+                this.0.rectangle(x, y, width, height);
+                Ok(())
+            },
+        );
+        methods.add_method(
+            "rounded_rectangle",
+            |_, this, (x, y, width, height, radius): (f64, f64, f64, f64, f64)| {
+                let r = radius.min(width / 2.0).min(height / 2.0).max(0.0);
+                let cr = &this.0;
+                let half_pi = std::f64::consts::FRAC_PI_2;
+                cr.new_sub_path();
+                cr.arc(x + width - r, y + r, r, -half_pi, 0.0);
+                cr.arc(x + width - r, y + height - r, r, 0.0, half_pi);
+                cr.arc(x + r, y + height - r, r, half_pi, 2.0 * half_pi);
+                cr.arc(x + r, y + r, r, 2.0 * half_pi, 3.0 * half_pi);
+                cr.close_path();
+                Ok(())
+            },
+        );
+        methods.add_method("close_path", |_, this, ()| {
+            this.0.close_path();
+            Ok(())
+        });
+        methods.add_method("new_path", |_, this, ()| {
+            this.0.new_path();
+            Ok(())
+        });
+        methods.add_method("new_sub_path", |_, this, ()| {
+            this.0.new_sub_path();
+            Ok(())
+        });
+
+        methods.add_method("stroke", |_, this, ()| this.0.stroke().map_err(cairo_err));
+        methods.add_method("stroke_preserve", |_, this, ()| {
+            this.0.stroke_preserve().map_err(cairo_err)
+        });
+        methods.add_method("fill", |_, this, ()| this.0.fill().map_err(cairo_err));
+        methods.add_method("fill_preserve", |_, this, ()| {
+            this.0.fill_preserve().map_err(cairo_err)
+        });
+        methods.add_method("paint", |_, this, ()| this.0.paint().map_err(cairo_err));
+        methods.add_method("paint_with_alpha", |_, this, alpha: f64| {
+            this.0.paint_with_alpha(alpha).map_err(cairo_err)
+        });
+
+        methods.add_method("set_source_rgb", |_, this, (r, g, b): (f64, f64, f64)| {
+            this.0.set_source_rgb(r, g, b);
+            Ok(())
+        });
+        methods.add_method(
+            "set_source_rgba",
+            |_, this, (r, g, b, a): (f64, f64, f64, f64)| {
+                this.0.set_source_rgba(r, g, b, a);
+                Ok(())
+            },
+        );
+        methods.add_method("set_source_color", |_, this, color: String| {
+            this.0.set_source_color(&parse_color(&color)?);
+            Ok(())
+        });
+        methods.add_method("set_source", |_, this, gradient: LuaGradient| {
+            this.0.set_source(&gradient.0).map_err(cairo_err)
+        });
+        methods.add_method(
+            "set_source_surface",
+            |_, this, (surface, x, y): (LuaSurface, Option<f64>, Option<f64>)| {
+                this.0
+                    .set_source_surface(&surface.0, x.unwrap_or(0.0), y.unwrap_or(0.0))
+                    .map_err(cairo_err)
+            },
+        );
+
+        methods.add_method("set_line_width", |_, this, width: f64| {
+            this.0.set_line_width(width);
+            Ok(())
+        });
+        methods.add_method("set_line_cap", |_, this, cap: String| {
+            let cap = match cap.as_str() {
+                "butt" => LineCap::Butt,
+                "round" => LineCap::Round,
+                "square" => LineCap::Square,
+                _ => {
+                    return Err(Error::runtime(format!(
+                        "Invalid line cap '{}', expected one of: butt, round, square",
+                        cap
+                    )));
+                }
+            };
+            this.0.set_line_cap(cap);
+            Ok(())
+        });
+        methods.add_method("set_line_join", |_, this, join: String| {
+            let join = match join.as_str() {
+                "miter" => LineJoin::Miter,
+                "round" => LineJoin::Round,
+                "bevel" => LineJoin::Bevel,
+                _ => {
+                    return Err(Error::runtime(format!(
+                        "Invalid line join '{}', expected one of: miter, round, bevel",
+                        join
+                    )));
+                }
+            };
+            this.0.set_line_join(join);
+            Ok(())
+        });
+        methods.add_method(
+            "set_dash",
+            |_, this, (dashes, offset): (Vec<f64>, Option<f64>)| {
+                this.0.set_dash(&dashes, offset.unwrap_or(0.0));
+                Ok(())
+            },
+        );
+
+        methods.add_method("translate", |_, this, (tx, ty): (f64, f64)| {
+            this.0.translate(tx, ty);
+            Ok(())
+        });
+        methods.add_method("scale", |_, this, (sx, sy): (f64, Option<f64>)| {
+            this.0.scale(sx, sy.unwrap_or(sx));
+            Ok(())
+        });
+        methods.add_method("rotate", |_, this, angle: f64| {
+            this.0.rotate(angle);
+            Ok(())
+        });
+        methods.add_method("identity_matrix", |_, this, ()| {
+            this.0.identity_matrix();
+            Ok(())
+        });
+
+        methods.add_method("clip", |_, this, ()| {
+            this.0.clip();
+            Ok(())
+        });
+        methods.add_method("clip_preserve", |_, this, ()| {
+            this.0.clip_preserve();
+            Ok(())
+        });
+        methods.add_method("reset_clip", |_, this, ()| {
+            this.0.reset_clip();
+            Ok(())
+        });
+
+        methods.add_method("create_layout", |_, this, text: Option<String>| {
+            let layout = pangocairo::functions::create_layout(&this.0);
+            if let Some(text) = text {
+                layout.set_text(&text);
+            }
+            Ok(LuaLayout(layout))
+        });
+        methods.add_method("show_layout", |_, this, layout: LuaLayout| {
+            pangocairo::functions::update_layout(&this.0, &layout.0);
+            pangocairo::functions::show_layout(&this.0, &layout.0);
+            Ok(())
+        });
+
+        methods.add_method(
+            "draw_image",
+            |_, this, (surface, x, y, width, height): (LuaSurface, f64, f64, Option<f64>, Option<f64>)| {
+                let image = &surface.0;
+                let sx = width.map_or(1.0, |w| w / f64::from(image.width()));
+                let sy = height.map_or(sx, |h| h / f64::from(image.height()));
+                let cr = &this.0;
+                cr.save().map_err(cairo_err)?;
+                cr.translate(x, y);
+                cr.scale(sx, sy);
+                cr.set_source_surface(image, 0.0, 0.0).map_err(cairo_err)?;
+                cr.paint().map_err(cairo_err)?;
+                cr.restore().map_err(cairo_err)
+            },
+        );
+    }
+}
+
+/// A linear or radial gradient usable with `cr:set_source`.
+#[derive(Clone)]
+pub struct LuaGradient(pub Gradient);
+
+impl FromLua for LuaGradient {
+    fn from_lua(value: Value, _lua: &Lua) -> mlua::Result<Self> {
+        userdata_from_lua(value, "Gradient")
+    }
+}
+
+impl UserData for LuaGradient {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("add_color_stop", |_, this, args: Variadic<Value>| {
+            let offset = match args.first() {
+                Some(Value::Number(n)) => *n,
+                Some(Value::Integer(i)) => *i as f64,
+                _ => return Err(Error::runtime("add_color_stop expects an offset")),
+            };
+            let rest: Vec<Value> = args.iter().skip(1).cloned().collect();
+            let color = match rest.as_slice() {
+                [Value::String(s)] => parse_color(&s.to_str()?)?,
+                _ => {
+                    let channels: Vec<f64> = rest
+                        .iter()
+                        .map(|v| match v {
+                            Value::Number(n) => Ok(*n),
+                            Value::Integer(i) => Ok(*i as f64),
+                            _ => Err(Error::runtime(
+                                "add_color_stop expects a color string or r, g, b[, a]",
+                            )),
+                        })
+                        .collect::<mlua::Result<_>>()?;
+                    match channels.as_slice() {
+                        [r, g, b] => RGBA::new(*r as f32, *g as f32, *b as f32, 1.0),
+                        [r, g, b, a] => RGBA::new(*r as f32, *g as f32, *b as f32, *a as f32),
+                        _ => {
+                            return Err(Error::runtime(
+                                "add_color_stop expects a color string or r, g, b[, a]",
+                            ));
+                        }
+                    }
+                }
+            };
+            this.0.add_color_stop_rgba(
+                offset,
+                f64::from(color.red()),
+                f64::from(color.green()),
+                f64::from(color.blue()),
+                f64::from(color.alpha()),
+            );
+            Ok(())
+        });
+    }
+}
+
+/// A Pango layout for drawing text with `cr:show_layout`.
+#[derive(Clone)]
+pub struct LuaLayout(pub pango::Layout);
+
+impl FromLua for LuaLayout {
+    fn from_lua(value: Value, _lua: &Lua) -> mlua::Result<Self> {
+        userdata_from_lua(value, "Layout")
+    }
+}
+
+impl UserData for LuaLayout {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("set_text", |_, this, text: String| {
+            this.0.set_text(&text);
+            Ok(())
+        });
+        methods.add_method("set_markup", |_, this, markup: String| {
+            this.0.set_markup(&markup);
+            Ok(())
+        });
+        methods.add_method("set_font", |_, this, font: String| {
+            let desc = pango::FontDescription::from_string(&font);
+            this.0.set_font_description(Some(&desc));
+            Ok(())
+        });
+        methods.add_method("set_width", |_, this, width: Option<i32>| {
+            this.0
+                .set_width(width.map_or(-1, |w| w.saturating_mul(pango::SCALE)));
+            Ok(())
+        });
+        methods.add_method("set_alignment", |_, this, alignment: String| {
+            let alignment = match alignment.as_str() {
+                "left" => pango::Alignment::Left,
+                "center" => pango::Alignment::Center,
+                "right" => pango::Alignment::Right,
+                _ => {
+                    return Err(Error::runtime(format!(
+                        "Invalid alignment '{}', expected one of: left, center, right",
+                        alignment
+                    )));
+                }
+            };
+            this.0.set_alignment(alignment);
+            Ok(())
+        });
+        methods.add_method("get_pixel_size", |_, this, ()| Ok(this.0.pixel_size()));
+    }
+}
+
+/// An in-memory image, either loaded from a file or drawn offscreen.
+#[derive(Clone)]
+pub struct LuaSurface(pub ImageSurface);
+
+impl FromLua for LuaSurface {
+    fn from_lua(value: Value, _lua: &Lua) -> mlua::Result<Self> {
+        userdata_from_lua(value, "Surface")
+    }
+}
+
+impl UserData for LuaSurface {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("get_width", |_, this, ()| Ok(this.0.width()));
+        methods.add_method("get_height", |_, this, ()| Ok(this.0.height()));
+        methods.add_method("context", |_, this, ()| {
+            Context::new(&this.0).map(LuaCairo).map_err(cairo_err)
+        });
+    }
+}
+
+fn load_image(path: &str, width: Option<i32>, height: Option<i32>) -> mlua::Result<ImageSurface> {
+    let pixbuf = match (width, height) {
+        (None, None) => Pixbuf::from_file(path),
+        (w, h) => Pixbuf::from_file_at_scale(path, w.unwrap_or(-1), h.unwrap_or(-1), true),
+    }
+    .map_err(|e| Error::runtime(format!("Failed to load image '{}': {}", path, e)))?;
+
+    let surface =
+        ImageSurface::create(Format::ARgb32, pixbuf.width(), pixbuf.height()).map_err(cairo_err)?;
+    let cr = Context::new(&surface).map_err(cairo_err)?;
+    cr.set_source_pixbuf(&pixbuf, 0.0, 0.0);
+    cr.paint().map_err(cairo_err)?;
+    drop(cr);
+    Ok(surface)
+}
+
+/// Calls `func(cr, width, height)` whenever the drawing area is redrawn.
+pub fn set_draw_func(area: &gtk4::DrawingArea, func: Function) {
+    area.set_draw_func(move |_, cr, width, height| {
+        let _ = cr.save();
+        if let Err(e) = func.call::<()>((LuaCairo(cr.clone()), width, height)) {
+            eprintln!("Draw Error: {}", e);
+        }
+        let _ = cr.restore();
+    });
+}
+
+pub fn register(lua: &Lua) -> mlua::Result<()> {
+    let cairo_table = lua.create_table()?;
+
+    cairo_table.set(
+        "linear_gradient",
+        lua.create_function(|_, (x0, y0, x1, y1): (f64, f64, f64, f64)| {
+            let gradient = cairo::LinearGradient::new(x0, y0, x1, y1);
+            Ok(LuaGradient(Gradient::clone(&gradient)))
+        })?,
+    )?;
+
+    cairo_table.set(
+        "radial_gradient",
+        lua.create_function(
+            |_, (cx0, cy0, r0, cx1, cy1, r1): (f64, f64, f64, f64, f64, f64)| {
+                let gradient = cairo::RadialGradient::new(cx0, cy0, r0, cx1, cy1, r1);
+                Ok(LuaGradient(Gradient::clone(&gradient)))
+            },
+        )?,
+    )?;
+
+    cairo_table.set(
+        "image_surface",
+        lua.create_function(|_, (width, height): (i32, i32)| {
+            ImageSurface::create(Format::ARgb32, width, height)
+                .map(LuaSurface)
+                .map_err(cairo_err)
+        })?,
+    )?;
+
+    cairo_table.set(
+        "load_image",
+        lua.create_function(
+            |_, (path, width, height): (String, Option<i32>, Option<i32>)| {
+                load_image(&path, width, height).map(LuaSurface)
+            },
+        )?,
+    )?;
+
+    lua.globals().set("Cairo", cairo_table)?;
+    Ok(())
+}
@@ -1,8 +1,13 @@
+use crate::scripting::drawing;
 use crate::scripting::lua_driver::LuaWrapper;
+use crate::scripting::sources;
+use crate::scripting::state;
 use crate::scripting::stdlib;
 use crate::scripting::widget_wrapper::{LuaGType, LuaWidget};
 use crate::services;
 use crate::ui::builder::UiBuilder;
+use crate::ui::lifecycle;
+use crate::ui::monitors;
 use gtk4::Application;
 use gtk4::gdk;
 use gtk4::glib;
@@ -42,6 +47,72 @@ impl mlua::FromLua for LuaCoreContext {
     }
 }
 
+/// Handle returned by `set_interval` and `set_timeout`. Timers started from a
+/// widget's hook or signal handler are cancelled when the widget is destroyed.
+#[derive(Clone, Default)]
+struct LuaTimer {
+    source: Rc<RefCell<Option<glib::SourceId>>>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl LuaTimer {
+    fn start(&self, id: glib::SourceId) {
+        *self.source.borrow_mut() = Some(id);
+        let timer = self.clone();
+        self.cleanup.set(lifecycle::track(move || timer.cancel()));
+    }
+
+    /// Forgets the timer once it has stopped, returning its source if it was
+    /// still registered.
+    fn finish(&self) -> Option<glib::SourceId> {
+        self.cleanup.remove();
+        self.source.borrow_mut().take()
+    }
+
+    fn cancel(&self) {
+        if let Some(id) = self.finish() {
+            id.remove();
+        }
+    }
+}
+
+impl mlua::UserData for LuaTimer {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("cancel", |_, this, ()| {
+            this.cancel();
+            Ok(())
+        });
+        methods.add_method("is_active", |_, this, ()| {
+            Ok(this.source.borrow().is_some())
+        });
+    }
+}
+
+/// Handle returned by `Display.on_monitors_changed`.
+#[derive(Clone, Default)]
+struct LuaMonitorWatch(
+    Rc<RefCell<Option<(gio::ListModel, glib::SignalHandlerId)>>>,
+    lifecycle::CleanupSlot,
+);
+
+impl LuaMonitorWatch {
+    fn disconnect(&self) {
+        self.1.remove();
+        if let Some((model, handler)) = self.0.borrow_mut().take() {
+            model.disconnect(handler);
+        }
+    }
+}
+
+impl mlua::UserData for LuaMonitorWatch {
+    fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("disconnect", |_, this, ()| {
+            this.disconnect();
+            Ok(())
+        });
+    }
+}
+
 static CORE_CONTEXT_REGISTRY_KEY: OnceCell<RegistryKey> = OnceCell::new();
 
 pub fn get_core_context(lua: &Lua) -> mlua::Result<Rc<RefCell<CoreContext>>> {
@@ -132,6 +203,70 @@ fn init_clipboard_functions(lua: &Rc<Lua>, globals: &mlua::Table) -> Result<()>
     Ok(())
 }
 
+fn default_display() -> Result<gdk::Display> {
+    gdk::Display::default()
+        .ok_or_else(|| mlua::Error::RuntimeError("Could not get default display".to_string()))
+}
+
+fn monitor_list(lua: &Lua, display: &gdk::Display) -> Result<mlua::Table> {
+    let list = lua.create_table()?;
+    for (i, monitor) in monitors::list(display).iter().enumerate() {
+        list.push(monitors::info(lua, i + 1, monitor)?)?;
+    }
+    Ok(list)
+}
+
+fn init_display_functions(lua: &Rc<Lua>, globals: &mlua::Table) -> Result<()> {
+    let display_table = lua.create_table()?;
+    globals.set("Display", display_table.clone())?;
+
+    display_table.set(
+        "monitors",
+        lua.create_function(|lua, ()| monitor_list(lua, &default_display()?))?,
+    )?;
+
+    display_table.set(
+        "monitor_of",
+        lua.create_function(|lua, widget: LuaWidget| {
+            let Some(surface) = widget.0.native().and_then(|n| n.surface()) else {
+                return Ok(None);
+            };
+            let display = surface.display();
+            let Some(monitor) = display.monitor_at_surface(&surface) else {
+                return Ok(None);
+            };
+            let Some(index) = monitors::list(&display).iter().position(|m| *m == monitor) else {
+                return Ok(None);
+            };
+            monitors::info(lua, index + 1, &monitor).map(Some)
+        })?,
+    )?;
+
+    display_table.set(
+        "on_monitors_changed",
+        lua.create_function(|lua, callback: Function| {
+            let display = default_display()?;
+            let model = display.monitors();
+            let lua = lua.clone();
+            let handler = model.connect_items_changed(move |_, _, _, _| {
+                let called =
+                    monitor_list(&lua, &display).and_then(|list| callback.call::<()>(list));
+                if let Err(e) = called {
+                    eprintln!("Error in on_monitors_changed callback: {}", e);
+                }
+            });
+            let watch = LuaMonitorWatch(
+                Rc::new(RefCell::new(Some((model, handler)))),
+                Default::default(),
+            );
+            let tracked = watch.clone();
+            watch.1.set(lifecycle::track(move || tracked.disconnect()));
+            Ok(watch)
+        })?,
+    )?;
+    Ok(())
+}
+
 fn init_ui_builder_function(
     lua: &Rc<Lua>,
     globals: &mlua::Table,
@@ -254,28 +389,38 @@ fn init_utility_functions(lua: &Rc<Lua>, globals: &mlua::Table) -> Result<()> {
     let set_interval = lua.create_function(|lua, (ms, callback): (u32, Function)| {
         let lua = lua.clone();
         let cb_key = lua.create_registry_value(callback)?;
-                glib::timeout_add_local(std::time::Duration::from_millis(ms as u64), move || {
-                    if let Ok(func) = lua.registry_value::<Function>(&cb_key)
-                        && let Err(_e) = func.call::<()>(()) {
-                            return glib::ControlFlow::Break;
-                        }
-                    glib::ControlFlow::Continue
-                });
-                Ok(())
+        let timer = LuaTimer::default();
+        let slot = timer.clone();
+        let id = glib::timeout_add_local(std::time::Duration::from_millis(ms as u64), move || {
+            if let Ok(func) = lua.registry_value::<Function>(&cb_key)
+                && let Err(_e) = func.call::<()>(())
+            {
+                slot.finish();
+                return glib::ControlFlow::Break;
+            }
+            glib::ControlFlow::Continue
+        });
+        timer.start(id);
+        Ok(timer)
     })?;
     globals.set("set_interval", set_interval)?;
 
     let set_timeout = lua.create_function(|lua, (ms, callback): (u32, Function)| {
         let lua = lua.clone();
         let cb_key = lua.create_registry_value(callback)?;
-        glib::timeout_add_local_once(std::time::Duration::from_millis(ms as u64), move || {
-            if let Ok(func) = lua.registry_value::<Function>(&cb_key)
-                && let Err(e) = func.call::<()>(())
-            {
-                eprintln!("set_timeout Error: {}", e);
-            }
-        });
-        Ok(())
+        let timer = LuaTimer::default();
+        let slot = timer.clone();
+        let id =
+            glib::timeout_add_local_once(std::time::Duration::from_millis(ms as u64), move || {
+                slot.finish();
+                if let Ok(func) = lua.registry_value::<Function>(&cb_key)
+                    && let Err(e) = func.call::<()>(())
+                {
+                    eprintln!("set_timeout Error: {}", e);
+                }
+            });
+        timer.start(id);
+        Ok(timer)
     })?;
     globals.set("set_timeout", set_timeout)?;
     let exec = lua.create_function(|_, cmd: String| {
@@ -338,10 +483,13 @@ fn init_utility_functions(lua: &Rc<Lua>, globals: &mlua::Table) -> Result<()> {
                     };
 
                     match result {
-                        Ok(s) => {
+                        Ok((status, s)) => {
                             if let Err(e) = lua_result_table.set("ok", s) {
                                 eprintln!("Failed to set 'ok' field in Lua table: {}", e);
                             }
+                            if let Err(e) = lua_result_table.set("status", status) {
+                                eprintln!("Failed to set 'status' field in Lua table: {}", e);
+                            }
                         }
                         Err(e) => {
                             if let Err(e) = lua_result_table.set("err", e.to_string()) {
@@ -376,6 +524,19 @@ pub fn init(
     app: Application,
     app_context: Arc<AppContext>,
     ui_builder: Rc<RefCell<UiBuilder>>,
+) -> Result<()> {
+    register(lua.clone(), app, app_context, ui_builder)?;
+    services::init(lua).map_err(mlua::Error::external)?;
+    Ok(())
+}
+
+/// Sets up every Lua global like `init`, but leaves the service servers
+/// stopped, for loading a config without running it.
+pub fn register(
+    lua: Rc<Lua>,
+    app: Application,
+    app_context: Arc<AppContext>,
+    ui_builder: Rc<RefCell<UiBuilder>>,
 ) -> Result<()> {
     let globals = lua.globals();
 
@@ -387,6 +548,7 @@ pub fn init(
     init_gtk_bindings(&lua, &globals)?;
     init_app_core_functions(&lua, &app_table)?;
     init_clipboard_functions(&lua, &globals)?;
+    init_display_functions(&lua, &globals)?;
     init_ui_builder_function(
         &lua,
         &globals,
@@ -396,7 +558,10 @@ pub fn init(
     )?;
     init_notification_function(&lua, &globals)?;
     init_utility_functions(&lua, &globals)?;
+    drawing::register(&lua)?;
+    state::register(&lua)?;
+    sources::register(&lua)?;
 
-    services::init(lua.clone()).map_err(mlua::Error::external)?;
+    services::register(lua.clone()).map_err(mlua::Error::external)?;
     Ok(())
 }
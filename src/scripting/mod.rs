@@ -1,5 +1,8 @@
+pub mod drawing;
 pub mod globals;
 pub mod lua_driver;
+pub mod sources;
+pub mod state;
 pub mod stdlib;
 pub mod traits;
 pub mod widget_wrapper;
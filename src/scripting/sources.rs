@@ -0,0 +1,239 @@
+use crate::scripting::state::{LuaState, WeakState};
+use crate::scripting::stdlib;
+use gtk4::glib;
+use mlua::{Function, Lua, ObjectLike, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+thread_local! {
+    /// Command-backed sources by kind and command, so that every `poll` or
+    /// `listen` of the same command shares one timer or process.
+    static SHARED: RefCell<HashMap<String, WeakState>> = RefCell::new(HashMap::new());
+}
+
+fn shared(key: String, create: impl FnOnce() -> LuaState) -> LuaState {
+    if let Some(state) = SHARED.with_borrow(|shared| shared.get(&key).and_then(WeakState::upgrade))
+    {
+        return state;
+    }
+    let state = create();
+    SHARED.with_borrow_mut(|shared| {
+        shared.retain(|_, s| s.upgrade().is_some());
+        shared.insert(key, state.downgrade());
+    });
+    state
+}
+
+enum PollSource {
+    Function(Function),
+    /// A shell command, and whether a run of it is still in flight.
+    Command(String, Rc<Cell<bool>>),
+}
+
+fn poll_once(lua: &Lua, source: &PollSource, state: &WeakState) {
+    match source {
+        PollSource::Function(func) => match func.call::<Value>(()) {
+            Ok(value) => {
+                if let Some(state) = state.upgrade() {
+                    state.set(value);
+                }
+            }
+            Err(e) => eprintln!("poll Error: {}", e),
+        },
+        PollSource::Command(cmd, running) => {
+            // Ticks that come while the previous run is still going are
+            // skipped, so slow commands don't pile up.
+            if running.replace(true) {
+                return;
+            }
+            let lua = lua.clone();
+            let cmd = cmd.clone();
+            let running = running.clone();
+            let state = WeakState::clone(state);
+            glib::MainContext::default().spawn_local(async move {
+                let result = stdlib::exec_async(cmd.clone()).await;
+                running.set(false);
+                match result {
+                    Ok(output) => {
+                        if let (Some(state), Ok(value)) =
+                            (state.upgrade(), lua.create_string(output))
+                        {
+                            state.set(Value::String(value));
+                        }
+                    }
+                    Err(e) => eprintln!("poll Error [{}]: {}", cmd, e),
+                }
+            });
+        }
+    }
+}
+
+/// Runs `source` every `ms` milliseconds while the state has consumers.
+fn poll(lua: &Lua, ms: u32, source: PollSource, initial: Value) -> LuaState {
+    let state = LuaState::new(initial);
+    let weak = state.downgrade();
+    let lua = lua.clone();
+    let source = Rc::new(source);
+    let timer: RefCell<Option<glib::SourceId>> = RefCell::new(None);
+    state.set_activity_handler(move |active| {
+        if let Some(id) = timer.borrow_mut().take() {
+            id.remove();
+        }
+        if !active {
+            return;
+        }
+        poll_once(&lua, &source, &weak);
+        let lua = lua.clone();
+        let source = source.clone();
+        let weak = WeakState::clone(&weak);
+        let id = glib::timeout_add_local(Duration::from_millis(ms as u64), move || {
+            poll_once(&lua, &source, &weak);
+            glib::ControlFlow::Continue
+        });
+        *timer.borrow_mut() = Some(id);
+    });
+    state
+}
+
+/// Runs `cmd` while the state has consumers and emits each line it prints.
+fn listen(lua: &Lua, cmd: String, initial: Value) -> LuaState {
+    let state = LuaState::new(initial);
+    let weak = state.downgrade();
+    let lua = lua.clone();
+    let task: RefCell<Option<glib::JoinHandle<()>>> = RefCell::new(None);
+    state.set_activity_handler(move |active| {
+        if let Some(handle) = task.borrow_mut().take() {
+            handle.abort();
+        }
+        if !active {
+            return;
+        }
+        let lua = lua.clone();
+        let cmd = cmd.clone();
+        let weak = WeakState::clone(&weak);
+        let handle = glib::MainContext::default().spawn_local(async move {
+            let child = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(&cmd)
+                .stdout(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn();
+            let mut child = match child {
+                Ok(child) => child,
+                Err(e) => {
+                    eprintln!("listen Error [{}]: {}", cmd, e);
+                    return;
+                }
+            };
+            let Some(stdout) = child.stdout.take() else {
+                return;
+            };
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Some(state) = weak.upgrade() else {
+                    return;
+                };
+                match lua.create_string(&line) {
+                    Ok(line) => state.emit(Value::String(line)),
+                    Err(e) => eprintln!("listen Error [{}]: {}", cmd, e),
+                }
+            }
+            let _ = child.wait().await;
+        });
+        *task.borrow_mut() = Some(handle);
+    });
+    state
+}
+
+/// Calls a handle returned by a service subscription to stop it: either a
+/// function, or an object with `disconnect`, `cancel` or `unsubscribe`.
+fn stop_handle(handle: &Value) -> mlua::Result<()> {
+    match handle {
+        Value::Function(stop) => stop.call(()),
+        Value::Table(_) | Value::UserData(_) => {
+            for method in ["disconnect", "cancel", "unsubscribe"] {
+                let stop = match handle {
+                    Value::Table(t) => t.get::<Option<Function>>(method)?,
+                    Value::UserData(ud) => ud.get::<Option<Function>>(method).ok().flatten(),
+                    _ => None,
+                };
+                if let Some(stop) = stop {
+                    return stop.call(handle.clone());
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Calls `start(set)` while the state has consumers. `start` subscribes to a
+/// service with `set` as its callback and returns the subscription handle.
+fn watch(lua: &Lua, start: Function, initial: Value) -> mlua::Result<LuaState> {
+    let state = LuaState::new(initial);
+    let setter = {
+        let weak = state.downgrade();
+        lua.create_function(move |_, value: Value| {
+            if let Some(state) = weak.upgrade() {
+                state.emit(value);
+            }
+            Ok(())
+        })?
+    };
+    let handle: RefCell<Option<Value>> = RefCell::new(None);
+    state.set_activity_handler(move |active| {
+        if let Some(previous) = handle.borrow_mut().take()
+            && let Err(e) = stop_handle(&previous)
+        {
+            eprintln!("watch Error: {}", e);
+        }
+        if !active {
+            return;
+        }
+        match start.call::<Value>(setter.clone()) {
+            Ok(h) => *handle.borrow_mut() = Some(h),
+            Err(e) => eprintln!("watch Error: {}", e),
+        }
+    });
+    Ok(state)
+}
+
+pub fn register(lua: &Lua) -> mlua::Result<()> {
+    lua.globals().set(
+        "poll",
+        lua.create_function(
+            |lua, (ms, source, initial): (u32, Value, Value)| match source {
+                Value::Function(func) => Ok(poll(lua, ms, PollSource::Function(func), initial)),
+                Value::String(cmd) => {
+                    let cmd = cmd.to_str()?.to_string();
+                    Ok(shared(format!("poll:{}:{}", ms, cmd), || {
+                        poll(lua, ms, PollSource::Command(cmd, Rc::default()), initial)
+                    }))
+                }
+                other => Err(mlua::Error::runtime(format!(
+                    "poll expects a function or a command string, got {}",
+                    other.type_name()
+                ))),
+            },
+        )?,
+    )?;
+
+    lua.globals().set(
+        "listen",
+        lua.create_function(|lua, (cmd, initial): (String, Value)| {
+            Ok(shared(format!("listen:{}", cmd), || {
+                listen(lua, cmd, initial)
+            }))
+        })?,
+    )?;
+
+    lua.globals().set(
+        "watch",
+        lua.create_function(|lua, (start, initial): (Function, Value)| watch(lua, start, initial))?,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,407 @@
+use crate::interop::converter::GenericConverter;
+use crate::scripting::lua_driver::LuaWrapper;
+use crate::ui::lifecycle;
+use gtk4::Widget;
+use gtk4::glib::Type;
+use gtk4::prelude::*;
+use mlua::{Error, FromLua, Function, Lua, UserData, UserDataMethods, Value};
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+
+type Subscriber = Rc<dyn Fn(&Value)>;
+type ActivityHandler = Rc<dyn Fn(bool)>;
+
+struct StateInner {
+    value: Value,
+    subscribers: Vec<(u64, Subscriber)>,
+    next_id: u64,
+    consumers: usize,
+    on_activity: Option<ActivityHandler>,
+    /// Subscriptions of a derived state on its sources.
+    sources: Vec<SourceGuard>,
+}
+
+/// Removes a derived state's subscriber from one of its sources when the
+/// derived state is dropped.
+struct SourceGuard {
+    source: WeakState,
+    id: u64,
+    cleanup: Option<lifecycle::CleanupHandle>,
+}
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        if let Some(cleanup) = self.cleanup.take() {
+            cleanup.remove();
+        }
+        if let Some(source) = self.source.upgrade() {
+            source.unsubscribe(self.id);
+        }
+    }
+}
+
+/// A value that notifies its subscribers whenever it changes.
+#[derive(Clone)]
+pub struct LuaState(Rc<RefCell<StateInner>>);
+
+#[derive(Clone)]
+pub struct WeakState(Weak<RefCell<StateInner>>);
+
+impl WeakState {
+    pub fn upgrade(&self) -> Option<LuaState> {
+        self.0.upgrade().map(LuaState)
+    }
+}
+
+impl LuaState {
+    pub fn new(value: Value) -> Self {
+        Self(Rc::new(RefCell::new(StateInner {
+            value,
+            subscribers: Vec::new(),
+            next_id: 0,
+            consumers: 0,
+            on_activity: None,
+            sources: Vec::new(),
+        })))
+    }
+
+    pub fn downgrade(&self) -> WeakState {
+        WeakState(Rc::downgrade(&self.0))
+    }
+
+    /// Sets the handler called when the state gains its first consumer or
+    /// loses its last one, so sources can pause while nothing is watching.
+    pub fn set_activity_handler(&self, handler: impl Fn(bool) + 'static) {
+        let handler: ActivityHandler = Rc::new(handler);
+        let active = {
+            let mut inner = self.0.borrow_mut();
+            inner.on_activity = Some(handler.clone());
+            inner.consumers > 0
+        };
+        if active {
+            lifecycle::detached(|| handler(true));
+        }
+    }
+
+    /// Registers a consumer: a Lua subscriber or a bound widget that is mapped.
+    pub fn acquire(&self) {
+        let handler = {
+            let mut inner = self.0.borrow_mut();
+            inner.consumers += 1;
+            inner.on_activity.clone().filter(|_| inner.consumers == 1)
+        };
+        if let Some(handler) = handler {
+            lifecycle::detached(|| handler(true));
+        }
+    }
+
+    pub fn release(&self) {
+        let handler = {
+            let mut inner = self.0.borrow_mut();
+            inner.consumers = inner.consumers.saturating_sub(1);
+            inner.on_activity.clone().filter(|_| inner.consumers == 0)
+        };
+        if let Some(handler) = handler {
+            lifecycle::detached(|| handler(false));
+        }
+    }
+
+    pub fn get(&self) -> Value {
+        self.0.borrow().value.clone()
+    }
+
+    /// Stores `value` and notifies subscribers. Setting the same plain value
+    /// again is a no-op, while tables always notify since they may have been
+    /// modified in place.
+    pub fn set(&self, value: Value) {
+        {
+            let inner = self.0.borrow();
+            if inner.value == value && !value.is_table() {
+                return;
+            }
+        }
+        self.emit(value);
+    }
+
+    /// Stores `value` and notifies subscribers even if it did not change,
+    /// for sources where every value is an event.
+    pub fn emit(&self, value: Value) {
+        self.0.borrow_mut().value = value.clone();
+        let subscribers: Vec<Subscriber> = self
+            .0
+            .borrow()
+            .subscribers
+            .iter()
+            .map(|(_, s)| s.clone())
+            .collect();
+        for subscriber in subscribers {
+            subscriber(&value);
+        }
+    }
+
+    pub fn subscribe(&self, subscriber: impl Fn(&Value) + 'static) -> u64 {
+        let mut inner = self.0.borrow_mut();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.subscribers.push((id, Rc::new(subscriber)));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: u64) {
+        self.0.borrow_mut().subscribers.retain(|(i, _)| *i != id);
+    }
+
+    /// Creates a state computed from `sources` with `compute`, updated
+    /// whenever any of them changes.
+    pub fn derive(sources: Vec<LuaState>, compute: Function) -> mlua::Result<Self> {
+        let values =
+            |sources: &[LuaState]| -> Vec<Value> { sources.iter().map(LuaState::get).collect() };
+        let derived =
+            LuaState::new(compute.call::<Value>(mlua::MultiValue::from_vec(values(&sources)))?);
+        // Weak, since each source would otherwise hold itself through its
+        // own subscriber list.
+        let weak_sources: Vec<WeakState> = sources.iter().map(LuaState::downgrade).collect();
+        for source in &sources {
+            let weak_sources = weak_sources.clone();
+            let compute = compute.clone();
+            let weak = Rc::downgrade(&derived.0);
+            let id = source.subscribe(move |_| {
+                let Some(derived) = weak.upgrade().map(LuaState) else {
+                    return;
+                };
+                let Some(sources) = weak_sources
+                    .iter()
+                    .map(WeakState::upgrade)
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return;
+                };
+                let args = mlua::MultiValue::from_vec(values(&sources));
+                match compute.call::<Value>(args) {
+                    Ok(value) => derived.set(value),
+                    Err(e) => eprintln!("Derived State Error: {}", e),
+                }
+            });
+            let cleanup = {
+                let source = source.clone();
+                lifecycle::track(move || source.unsubscribe(id))
+            };
+            derived.0.borrow_mut().sources.push(SourceGuard {
+                source: source.downgrade(),
+                id,
+                cleanup,
+            });
+        }
+        derived.set_activity_handler(move |active| {
+            for source in &sources {
+                if active {
+                    source.acquire();
+                } else {
+                    source.release();
+                }
+            }
+        });
+        Ok(derived)
+    }
+}
+
+impl FromLua for LuaState {
+    fn from_lua(value: Value, _lua: &Lua) -> mlua::Result<Self> {
+        let ud = value
+            .as_userdata()
+            .ok_or_else(|| Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "State".to_string(),
+                message: Some("Expected a State object".to_string()),
+            })?;
+        let state = ud.borrow::<Self>()?;
+        Ok(state.clone())
+    }
+}
+
+#[derive(Clone)]
+struct StateSubscription {
+    state: LuaState,
+    id: u64,
+    active: Rc<Cell<bool>>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl StateSubscription {
+    fn cancel(&self) {
+        self.cleanup.remove();
+        if self.active.replace(false) {
+            self.state.unsubscribe(self.id);
+            self.state.release();
+        }
+    }
+}
+
+impl UserData for StateSubscription {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("unsubscribe", |_, this, ()| {
+            this.cancel();
+            Ok(())
+        });
+    }
+}
+
+impl UserData for LuaState {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("get", |_, this, ()| Ok(this.get()));
+        methods.add_method("set", |_, this, value: Value| {
+            this.set(value);
+            Ok(())
+        });
+        methods.add_method("update", |_, this, update: Function| {
+            let value = update.call::<Value>(this.get())?;
+            this.set(value);
+            Ok(())
+        });
+        methods.add_method(
+            "subscribe",
+            |_, this, (callback, immediate): (Function, Option<bool>)| {
+                if immediate.unwrap_or(false) {
+                    callback.call::<()>(this.get())?;
+                }
+                let id = this.subscribe(move |value| {
+                    if let Err(e) = callback.call::<()>(value.clone()) {
+                        eprintln!("State Subscriber Error: {}", e);
+                    }
+                });
+                this.acquire();
+                let subscription = StateSubscription {
+                    state: this.clone(),
+                    id,
+                    active: Rc::new(Cell::new(true)),
+                    cleanup: Default::default(),
+                };
+                let tracked = subscription.clone();
+                subscription
+                    .cleanup
+                    .set(lifecycle::track(move || tracked.cancel()));
+                Ok(subscription)
+            },
+        );
+        methods.add_method("map", |_, this, compute: Function| {
+            LuaState::derive(vec![this.clone()], compute)
+        });
+    }
+}
+
+/// A state bound to a widget property, optionally through a transform.
+#[derive(Clone)]
+pub struct LuaBinding {
+    state: LuaState,
+    transform: Option<Function>,
+}
+
+impl UserData for LuaBinding {}
+
+impl LuaBinding {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        value
+            .as_userdata()
+            .and_then(|ud| ud.borrow::<Self>().ok())
+            .map(|b| b.clone())
+    }
+
+    pub fn value(&self) -> mlua::Result<Value> {
+        self.transform_value(self.state.get())
+    }
+
+    fn transform_value(&self, value: Value) -> mlua::Result<Value> {
+        match &self.transform {
+            Some(transform) => transform.call::<Value>(value),
+            None => Ok(value),
+        }
+    }
+
+    /// Sets `property` from the bound state now and on every change, until
+    /// the widget is destroyed. The state only counts the widget as a
+    /// consumer while it is mapped.
+    pub fn install(&self, widget: &Widget, property: &str, value_type: Type) -> Result<(), String> {
+        let apply = {
+            let binding = self.clone();
+            let property = property.to_string();
+            move |widget: &Widget, value: &Value| -> Result<(), String> {
+                let value = binding
+                    .transform_value(value.clone())
+                    .map_err(|e| e.to_string())?;
+                let gval = GenericConverter::to_gvalue(&LuaWrapper(value), value_type)
+                    .map_err(|e| format!("Failed to convert bound property: {}", e))?;
+                widget.set_property(&property, gval);
+                Ok(())
+            }
+        };
+
+        apply(widget, &self.state.get())?;
+
+        let weak = widget.downgrade();
+        let property = property.to_string();
+        let id = self.state.subscribe(move |value| {
+            if let Some(widget) = weak.upgrade()
+                && let Err(e) = apply(&widget, value)
+            {
+                eprintln!("Binding Error [{}]: {}", property, e);
+            }
+        });
+        let held = Rc::new(Cell::new(false));
+        let hold: Rc<dyn Fn(bool)> = {
+            let state = self.state.clone();
+            Rc::new(move |on| {
+                if held.replace(on) != on {
+                    if on {
+                        state.acquire();
+                    } else {
+                        state.release();
+                    }
+                }
+            })
+        };
+        if widget.is_mapped() {
+            hold(true);
+        }
+        widget.connect_map({
+            let hold = hold.clone();
+            move |_| hold(true)
+        });
+        widget.connect_unmap({
+            let hold = hold.clone();
+            move |_| hold(false)
+        });
+        let state = self.state.clone();
+        lifecycle::on_destroy(widget, move |_| {
+            hold(false);
+            state.unsubscribe(id);
+        });
+        Ok(())
+    }
+}
+
+pub fn register(lua: &Lua) -> mlua::Result<()> {
+    let new_state = lua.create_function(|_, initial: Value| Ok(LuaState::new(initial)))?;
+    lua.globals().set("State", new_state.clone())?;
+    lua.globals().set("Variable", new_state)?;
+
+    lua.globals().set(
+        "derive",
+        lua.create_function(|lua, (sources, compute): (Value, Function)| {
+            let sources = match sources {
+                Value::Table(t) => t
+                    .sequence_values::<LuaState>()
+                    .collect::<mlua::Result<_>>()?,
+                other => vec![LuaState::from_lua(other, lua)?],
+            };
+            LuaState::derive(sources, compute)
+        })?,
+    )?;
+
+    lua.globals().set(
+        "bind",
+        lua.create_function(|_, (state, transform): (LuaState, Option<Function>)| {
+            Ok(LuaBinding { state, transform })
+        })?,
+    )?;
+    Ok(())
+}
@@ -3,8 +3,11 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::io;
 use std::process::Command;
-use std::thread;
+use std::sync::mpsc;
+use std::time::Duration;
 
+use crate::services::http::{self, HttpError};
+use gtk4::glib;
 use pulldown_cmark::{Event, Parser, Tag, TagEnd};
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -12,7 +15,9 @@ use unicode_segmentation::UnicodeSegmentation;
 pub enum StdLibError {
     Io(io::Error),
     Reqwest(reqwest::Error),
+    Http(HttpError),
     Command(String),
+    Timeout(Duration),
 }
 
 impl fmt::Display for StdLibError {
@@ -20,7 +25,11 @@ impl fmt::Display for StdLibError {
         match self {
             StdLibError::Io(e) => write!(f, "I/O error: {}", e),
             StdLibError::Reqwest(e) => write!(f, "Request error: {}", e),
+            StdLibError::Http(e) => write!(f, "{}", e),
             StdLibError::Command(e) => write!(f, "Command execution error: {}", e),
+            StdLibError::Timeout(after) => {
+                write!(f, "Request timed out after {} seconds", after.as_secs())
+            }
         }
     }
 }
@@ -30,6 +39,7 @@ impl StdError for StdLibError {
         match self {
             StdLibError::Io(e) => Some(e),
             StdLibError::Reqwest(e) => Some(e),
+            StdLibError::Http(e) => Some(e),
             _ => None,
         }
     }
@@ -41,6 +51,12 @@ impl From<io::Error> for StdLibError {
     }
 }
 
+impl From<HttpError> for StdLibError {
+    fn from(err: HttpError) -> Self {
+        StdLibError::Http(err)
+    }
+}
+
 impl From<reqwest::Error> for StdLibError {
     fn from(err: reqwest::Error) -> Self {
         StdLibError::Reqwest(err)
@@ -78,50 +94,44 @@ pub fn exec(cmd: &str) -> Result<String, StdLibError> {
 }
 
 pub fn spawn(cmd: String) {
-    thread::spawn(move || {
-        let mut command = Command::new("sh");
-        command.arg("-c").arg(cmd);
-
-        match command.spawn() {
-            Ok(mut child) => {
-                let _ = child.wait();
-            }
-            Err(e) => {
-                eprintln!("Failed to spawn command: {}", e);
-            }
+    match tokio::process::Command::new("sh").arg("-c").arg(cmd).spawn() {
+        Ok(mut child) => {
+            glib::MainContext::default().spawn_local(async move {
+                let _ = child.wait().await;
+            });
         }
-    });
+        Err(e) => {
+            eprintln!("Failed to spawn command: {}", e);
+        }
+    }
 }
 
+/// Longest time the blocking `fetch` may hold up the main loop.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs `fetch_async` on a runtime worker and waits for it, so that blocking
+/// requests go through the shared connection pool too.
+///
+/// This blocks the GTK main loop until the response arrives, so the request
+/// is cut off after `FETCH_TIMEOUT`. Prefer `fetch_async` or `Http`.
 pub fn fetch(
     method: &str,
     uri: &str,
     headers: Option<HashMap<String, String>>,
     body: Option<String>,
 ) -> Result<String, StdLibError> {
-    let client = reqwest::blocking::Client::new();
-    let mut request = match method.to_lowercase().as_str() {
-        "post" => client.post(uri),
-        "put" => client.put(uri),
-        "delete" => client.delete(uri),
-        _ => client.get(uri),
-    };
-
-    if let Some(h) = headers {
-        for (key, val) in h {
-            request = request.header(&key, &val);
-        }
-    }
-
-    if let Some(b) = body {
-        request = request.body(b);
-    }
-
-    request
-        .send()
-        .map_err(StdLibError::Reqwest)?
-        .text()
-        .map_err(StdLibError::Reqwest)
+    let (sender, receiver) = mpsc::channel();
+    let (method, uri) = (method.to_string(), uri.to_string());
+    tokio::spawn(async move {
+        let result = tokio::time::timeout(FETCH_TIMEOUT, fetch_async(method, uri, headers, body))
+            .await
+            .unwrap_or(Err(StdLibError::Timeout(FETCH_TIMEOUT)));
+        let _ = sender.send(result);
+    });
+    let (_, text) = receiver
+        .recv()
+        .map_err(|_| StdLibError::Command("Request task stopped".to_string()))??;
+    Ok(text)
 }
 
 pub async fn exec_async(cmd: String) -> Result<String, StdLibError> {
@@ -145,14 +155,8 @@ pub async fn fetch_async(
     uri: String,
     headers: Option<HashMap<String, String>>,
     body: Option<String>,
-) -> Result<String, StdLibError> {
-    let client = reqwest::Client::new();
-    let mut request = match method.to_lowercase().as_str() {
-        "post" => client.post(&uri),
-        "put" => client.put(&uri),
-        "delete" => client.delete(&uri),
-        _ => client.get(&uri),
-    };
+) -> Result<(u16, String), StdLibError> {
+    let mut request = http::shared_client().request(http::parse_method(&method)?, &uri);
 
     if let Some(h) = headers {
         for (key, val) in h {
@@ -164,13 +168,10 @@ pub async fn fetch_async(
         request = request.body(b);
     }
 
-    request
-        .send()
-        .await
-        .map_err(StdLibError::Reqwest)?
-        .text()
-        .await
-        .map_err(StdLibError::Reqwest)
+    let response = request.send().await.map_err(StdLibError::Reqwest)?;
+    let status = response.status().as_u16();
+    let text = response.text().await.map_err(StdLibError::Reqwest)?;
+    Ok((status, text))
 }
 
 pub fn markdown_to_pango(markdown: &str) -> String {
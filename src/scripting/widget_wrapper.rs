@@ -1,10 +1,15 @@
 use crate::interop::signals::SignalConnector;
+use crate::scripting::globals::get_core_context;
 use crate::scripting::lua_driver::LuaWrapper;
+use crate::scripting::state::LuaBinding;
+use crate::ui::lifecycle;
+use crate::ui::popup::{self, PopupOptions};
 use crate::ui::registry::Registry;
 use gtk4::glib::Type as GType;
 use gtk4::glib::prelude::*;
 use gtk4::prelude::*;
-use mlua::{Error, FromLua, Function, Lua, UserData, UserDataMethods, Value};
+use mlua::{Error, FromLua, Function, Lua, MetaMethod, UserData, UserDataMethods, Value};
+use std::path::Path;
 
 #[derive(Clone, Copy)]
 pub struct LuaGType(pub GType);
@@ -52,6 +57,64 @@ impl UserData for LuaAdjustment {
     }
 }
 
+fn choice_option<V: Copy>(
+    opts: &mlua::Table,
+    key: &str,
+    choices: &[(&str, V)],
+    default: V,
+) -> mlua::Result<V> {
+    let Some(name) = opts.get::<Option<String>>(key)? else {
+        return Ok(default);
+    };
+    choices
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, v)| *v)
+        .ok_or_else(|| {
+            let names: Vec<&str> = choices.iter().map(|(n, _)| *n).collect();
+            Error::runtime(format!(
+                "Invalid {} '{}', expected one of: {}",
+                key,
+                name,
+                names.join(", ")
+            ))
+        })
+}
+
+fn popup_options(lua: &Lua, opts: Option<mlua::Table>) -> mlua::Result<PopupOptions> {
+    let mut options = PopupOptions::default();
+    let Some(opts) = opts else {
+        return Ok(options);
+    };
+    options.side = choice_option(&opts, "side", popup::SIDES, options.side)?;
+    options.align = choice_option(&opts, "align", popup::ALIGNS, options.align)?;
+    if let Some(offset) = opts.get::<Option<mlua::Table>>("offset")? {
+        options.offset = (
+            offset.get::<Option<i32>>("x")?.unwrap_or(0),
+            offset.get::<Option<i32>>("y")?.unwrap_or(0),
+        );
+    }
+    if let Some(close) = opts.get::<Option<bool>>("close_on_focus_loss")? {
+        options.close_on_focus_loss = close;
+    }
+    if let Some(close) = opts.get::<Option<bool>>("close_on_escape")? {
+        options.close_on_escape = close;
+    }
+    match opts.get::<Value>("group")? {
+        Value::Nil => {}
+        Value::Boolean(false) => options.group = None,
+        group => options.group = Some(String::from_lua(group, lua)?),
+    }
+    if let Some(on_close) = opts.get::<Option<Function>>("on_close")? {
+        options.on_close = Some(Box::new(move || {
+            if let Err(e) = on_close.call::<()>(()) {
+                eprintln!("Popup on_close error: {}", e);
+            }
+        }));
+    }
+    Ok(options)
+}
+
 fn find_child_by_name_recursive(parent: &gtk4::Widget, name: &str) -> Option<gtk4::Widget> {
     let mut child = parent.first_child();
     while let Some(c) = child {
@@ -68,7 +131,17 @@ fn find_child_by_name_recursive(parent: &gtk4::Widget, name: &str) -> Option<gtk
 
 impl UserData for LuaWidget {
     fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+            let type_name = this.0.type_().name();
+            let name = this.0.widget_name();
+            if name == type_name {
+                Ok(type_name.to_string())
+            } else {
+                Ok(format!("{}({})", type_name, name))
+            }
+        });
         methods.add_method("destroy", |_, this, ()| {
+            lifecycle::destroy_tree(&this.0);
             if let Some(window) = this.0.downcast_ref::<gtk4::Window>() {
                 window.destroy();
             } else {
@@ -122,6 +195,7 @@ impl UserData for LuaWidget {
         methods.add_method("remove_children", |_, this, ()| {
             if let Some(flowbox) = this.0.downcast_ref::<gtk4::FlowBox>() {
                 while let Some(child) = flowbox.first_child() {
+                    lifecycle::destroy_tree(&child);
                     flowbox.remove(&child);
                 }
             } else {
@@ -132,6 +206,7 @@ impl UserData for LuaWidget {
                     child = c.next_sibling();
                 }
                 for c in children {
+                    lifecycle::destroy_tree(&c);
                     c.unparent();
                 }
             }
@@ -237,6 +312,15 @@ impl UserData for LuaWidget {
                 Value::Boolean(b) => this.0.set_property(&key, b),
                 Value::Number(n) => this.0.set_property(&key, n),
                 Value::Integer(i) => this.0.set_property(&key, i as f64),
+                Value::UserData(_) => {
+                    if let Some(binding) = LuaBinding::from_value(&val)
+                        && let Some(pspec) = this.0.find_property(&key)
+                    {
+                        binding
+                            .install(&this.0, &key, pspec.value_type())
+                            .map_err(Error::runtime)?;
+                    }
+                }
                 _ => {}
             }
             Ok(())
@@ -300,5 +384,32 @@ impl UserData for LuaWidget {
                 Ok(())
             },
         );
+        methods.add_method(
+            "popup",
+            |lua, this, (definition, opts): (Value, Option<mlua::Table>)| {
+                let options = popup_options(lua, opts)?;
+                let build = || {
+                    let core_context = get_core_context(lua).map_err(|e| e.to_string())?;
+                    let core_context = core_context.borrow();
+                    let config_dir = core_context
+                        .app_context
+                        .main_file_path
+                        .parent()
+                        .map(Path::to_path_buf)
+                        .unwrap_or_default();
+                    let ui_builder = core_context.ui_builder.borrow();
+                    ui_builder.build_child(&LuaWrapper(definition), &config_dir)
+                };
+                let window = popup::open(&this.0, build, options).map_err(Error::runtime)?;
+                Ok(window.map(|w| LuaWidget(w.upcast())))
+            },
+        );
+        methods.add_method("on_destroy", |_, this, func: Function| {
+            lifecycle::connect_hooks(
+                &this.0,
+                &[("destroy".to_string(), LuaWrapper(Value::Function(func)))],
+            );
+            Ok(())
+        });
     }
 }
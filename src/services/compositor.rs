@@ -0,0 +1,866 @@
+use super::hyprland::{self, HyprlandError};
+use super::niri::{self, NiriError};
+use super::sway::{self, SwayError};
+use crate::ui::lifecycle;
+use futures_util::FutureExt;
+use futures_util::future::LocalBoxFuture;
+use gtk4::glib;
+use mlua::{Function, Lua, LuaSerdeExt, Result as LuaResult, UserData, UserDataMethods, Value};
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum CompositorError {
+    HyprlandError(HyprlandError),
+    SwayError(SwayError),
+    NiriError(NiriError),
+    Unsupported,
+    WorkspaceNotFound(i64),
+    InvalidWindow(String),
+    CommandFailed(String),
+}
+
+impl fmt::Display for CompositorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompositorError::HyprlandError(e) => write!(f, "{}", e),
+            CompositorError::SwayError(e) => write!(f, "{}", e),
+            CompositorError::NiriError(e) => write!(f, "{}", e),
+            CompositorError::Unsupported => {
+                write!(
+                    f,
+                    "No supported compositor found (Hyprland, Sway/i3 or niri)"
+                )
+            }
+            CompositorError::WorkspaceNotFound(id) => write!(f, "Workspace {} not found", id),
+            CompositorError::InvalidWindow(id) => write!(f, "Invalid window id '{}'", id),
+            CompositorError::CommandFailed(e) => write!(f, "Compositor command failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CompositorError {}
+
+impl From<HyprlandError> for CompositorError {
+    fn from(err: HyprlandError) -> Self {
+        CompositorError::HyprlandError(err)
+    }
+}
+
+impl From<SwayError> for CompositorError {
+    fn from(err: SwayError) -> Self {
+        CompositorError::SwayError(err)
+    }
+}
+
+impl From<NiriError> for CompositorError {
+    fn from(err: NiriError) -> Self {
+        CompositorError::NiriError(err)
+    }
+}
+
+impl From<CompositorError> for mlua::Error {
+    fn from(err: CompositorError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Output {
+    pub name: String,
+    pub focused: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Workspace {
+    pub id: i64,
+    pub name: String,
+    pub output: Option<String>,
+    /// Shown on its output.
+    pub active: bool,
+    /// Active on the focused output.
+    pub focused: bool,
+    pub urgent: bool,
+    pub windows: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Toplevel {
+    pub id: String,
+    pub title: String,
+    pub app_id: String,
+    pub workspace: Option<i64>,
+    pub focused: bool,
+    pub urgent: bool,
+    pub fullscreen: bool,
+}
+
+/// The compositor state, normalized across backends. Workspaces are ordered
+/// by output, then by their position on it.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Snapshot {
+    pub compositor: &'static str,
+    pub outputs: Vec<Output>,
+    pub workspaces: Vec<Workspace>,
+    pub toplevels: Vec<Toplevel>,
+}
+
+impl Snapshot {
+    /// Fills in window counts and urgency of workspaces from the toplevels.
+    fn link(mut self) -> Self {
+        for ws in &mut self.workspaces {
+            let windows = self.toplevels.iter().filter(|t| t.workspace == Some(ws.id));
+            ws.windows = windows.clone().count();
+            ws.urgent |= windows.clone().any(|t| t.urgent);
+        }
+        self
+    }
+
+    pub fn workspace(&self, id: i64) -> Option<&Workspace> {
+        self.workspaces.iter().find(|ws| ws.id == id)
+    }
+}
+
+type Task<T> = LocalBoxFuture<'static, Result<T, CompositorError>>;
+
+/// Stops a subscription started with `Compositor::watch`.
+pub type Unwatch = Box<dyn FnOnce()>;
+pub type OnEnd = Box<dyn FnOnce()>;
+
+/// A compositor IPC backend.
+pub trait Compositor {
+    fn name(&self) -> &'static str;
+    fn snapshot(&self) -> Task<Snapshot>;
+    fn focus_workspace(&self, workspace: &Workspace) -> Task<()>;
+    fn focus_toplevel(&self, id: &str) -> Task<()>;
+    /// Moves `toplevel`, or the focused window if `None`, to `workspace`.
+    fn move_to_workspace(&self, toplevel: Option<&str>, workspace: &Workspace) -> Task<()>;
+    /// Calls `on_change` whenever workspaces, windows or outputs may have
+    /// changed, and `on_end` if the event stream stops on its own.
+    fn watch(&self, on_change: Rc<dyn Fn()>, on_end: OnEnd) -> Result<Unwatch, CompositorError>;
+}
+
+fn str_of(value: &JsonValue) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+struct Hyprland {
+    /// Windows that requested attention and have not been focused since,
+    /// since `j/clients` does not report urgency.
+    urgent: Rc<RefCell<HashSet<String>>>,
+}
+
+impl Hyprland {
+    async fn json(command: &str) -> Result<JsonValue, CompositorError> {
+        let reply = hyprland::request(command).await?;
+        serde_json::from_str(&reply).map_err(|e| HyprlandError::from(e).into())
+    }
+
+    async fn dispatch(dispatcher: &str, args: String) -> Result<(), CompositorError> {
+        Ok(hyprland::dispatch(dispatcher, &args).await?)
+    }
+}
+
+impl Compositor for Hyprland {
+    fn name(&self) -> &'static str {
+        "hyprland"
+    }
+
+    fn snapshot(&self) -> Task<Snapshot> {
+        let urgent = self.urgent.borrow().clone();
+        async move {
+            let (monitors, workspaces, clients, active) = futures_util::try_join!(
+                Self::json("j/monitors"),
+                Self::json("j/workspaces"),
+                Self::json("j/clients"),
+                Self::json("j/activewindow"),
+            )?;
+            let monitors = monitors.as_array().cloned().unwrap_or_default();
+            let active_address = str_of(&active["address"]);
+
+            let outputs = monitors
+                .iter()
+                .map(|m| Output {
+                    name: str_of(&m["name"]),
+                    focused: m["focused"].as_bool() == Some(true),
+                })
+                .collect();
+            let mut workspaces: Vec<Workspace> = workspaces
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|ws| {
+                    let id = ws["id"].as_i64().unwrap_or_default();
+                    let monitor = monitors.iter().find(|m| m["name"] == ws["monitor"]);
+                    let active = monitor.is_some_and(|m| m["activeWorkspace"]["id"] == ws["id"]);
+                    Workspace {
+                        id,
+                        name: str_of(&ws["name"]),
+                        output: ws["monitor"].as_str().map(str::to_string),
+                        active,
+                        focused: active && monitor.is_some_and(|m| m["focused"] == true),
+                        ..Default::default()
+                    }
+                })
+                .collect();
+            workspaces.sort_by_key(|ws| (ws.output.clone(), ws.id));
+            let toplevels = clients
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|c| {
+                    let address = str_of(&c["address"]);
+                    Toplevel {
+                        title: str_of(&c["title"]),
+                        app_id: str_of(&c["class"]),
+                        workspace: c["workspace"]["id"].as_i64(),
+                        focused: address == active_address,
+                        urgent: urgent.contains(&address),
+                        // A boolean in older releases, a mode in newer ones
+                        // where 1 is maximized and 2 and up are fullscreen.
+                        fullscreen: c["fullscreen"]
+                            .as_bool()
+                            .unwrap_or_else(|| c["fullscreen"].as_i64().is_some_and(|m| m >= 2)),
+                        id: address,
+                    }
+                })
+                .collect();
+            Ok(Snapshot {
+                compositor: "hyprland",
+                outputs,
+                workspaces,
+                toplevels,
+            }
+            .link())
+        }
+        .boxed_local()
+    }
+
+    fn focus_workspace(&self, workspace: &Workspace) -> Task<()> {
+        Self::dispatch("workspace", workspace.id.to_string()).boxed_local()
+    }
+
+    fn focus_toplevel(&self, id: &str) -> Task<()> {
+        Self::dispatch("focuswindow", format!("address:{}", id)).boxed_local()
+    }
+
+    fn move_to_workspace(&self, toplevel: Option<&str>, workspace: &Workspace) -> Task<()> {
+        let args = match toplevel {
+            Some(id) => format!("{},address:{}", workspace.id, id),
+            None => workspace.id.to_string(),
+        };
+        Self::dispatch("movetoworkspacesilent", args).boxed_local()
+    }
+
+    fn watch(&self, on_change: Rc<dyn Fn()>, on_end: OnEnd) -> Result<Unwatch, CompositorError> {
+        let urgent = self.urgent.clone();
+        let task = hyprland::subscribe(
+            move |event, args| {
+                match event {
+                    "urgent" => {
+                        urgent.borrow_mut().insert(format!("0x{}", args[0]));
+                    }
+                    "activewindowv2" => {
+                        urgent.borrow_mut().remove(&format!("0x{}", args[0]));
+                    }
+                    "closewindow" => {
+                        urgent.borrow_mut().remove(&format!("0x{}", args[0]));
+                    }
+                    _ => {}
+                }
+                on_change();
+            },
+            on_end,
+        )?;
+        Ok(Box::new(move || task.abort()))
+    }
+}
+
+struct Sway;
+
+impl Sway {
+    async fn run_command(command: String) -> Result<(), CompositorError> {
+        let reply = sway::query(sway::RUN_COMMAND, &command).await?;
+        let failed = reply
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|r| r["success"] != true);
+        match failed {
+            Some(r) => Err(CompositorError::CommandFailed(str_of(&r["error"]))),
+            None => Ok(()),
+        }
+    }
+
+    /// Collects the windows of the tree, remembering the workspace each is on.
+    fn collect_windows(node: &JsonValue, workspace: Option<i64>, into: &mut Vec<Toplevel>) {
+        let workspace = match node["type"].as_str() {
+            Some("workspace") => node["id"].as_i64(),
+            _ => workspace,
+        };
+        let children: Vec<&JsonValue> = ["nodes", "floating_nodes"]
+            .iter()
+            .flat_map(|key| node[key].as_array().into_iter().flatten())
+            .collect();
+        let is_window = node["type"]
+            .as_str()
+            .is_some_and(|t| t == "con" || t == "floating_con")
+            && children.is_empty()
+            && (node["pid"].is_number() || node["window"].is_number());
+        if is_window {
+            let app_id = node["app_id"]
+                .as_str()
+                .or_else(|| node["window_properties"]["class"].as_str())
+                .unwrap_or_default();
+            into.push(Toplevel {
+                id: node["id"].to_string(),
+                title: str_of(&node["name"]),
+                app_id: app_id.to_string(),
+                workspace,
+                focused: node["focused"] == true,
+                urgent: node["urgent"] == true,
+                fullscreen: node["fullscreen_mode"].as_i64().is_some_and(|m| m > 0),
+            });
+        }
+        for child in children {
+            Self::collect_windows(child, workspace, into);
+        }
+    }
+
+    fn quote(name: &str) -> String {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+impl Compositor for Sway {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn snapshot(&self) -> Task<Snapshot> {
+        async move {
+            let (outputs, workspaces, tree) = futures_util::try_join!(
+                sway::query(sway::GET_OUTPUTS, ""),
+                sway::query(sway::GET_WORKSPACES, ""),
+                sway::query(sway::GET_TREE, ""),
+            )
+            .map_err(CompositorError::from)?;
+            let outputs = outputs
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|o| o["active"] != false)
+                .map(|o| Output {
+                    name: str_of(&o["name"]),
+                    focused: o["focused"] == true,
+                })
+                .collect();
+            // Sway lists workspaces in order already.
+            let workspaces = workspaces
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|ws| Workspace {
+                    id: ws["id"].as_i64().unwrap_or_default(),
+                    name: str_of(&ws["name"]),
+                    output: ws["output"].as_str().map(str::to_string),
+                    active: ws["visible"] == true,
+                    focused: ws["focused"] == true,
+                    urgent: ws["urgent"] == true,
+                    windows: 0,
+                })
+                .collect();
+            let mut toplevels = Vec::new();
+            Self::collect_windows(&tree, None, &mut toplevels);
+            Ok(Snapshot {
+                compositor: "sway",
+                outputs,
+                workspaces,
+                toplevels,
+            }
+            .link())
+        }
+        .boxed_local()
+    }
+
+    fn focus_workspace(&self, workspace: &Workspace) -> Task<()> {
+        Self::run_command(format!("workspace {}", Self::quote(&workspace.name))).boxed_local()
+    }
+
+    fn focus_toplevel(&self, id: &str) -> Task<()> {
+        Self::run_command(format!("[con_id={}] focus", id)).boxed_local()
+    }
+
+    fn move_to_workspace(&self, toplevel: Option<&str>, workspace: &Workspace) -> Task<()> {
+        let criteria = toplevel
+            .map(|id| format!("[con_id={}] ", id))
+            .unwrap_or_default();
+        Self::run_command(format!(
+            "{}move container to workspace {}",
+            criteria,
+            Self::quote(&workspace.name)
+        ))
+        .boxed_local()
+    }
+
+    fn watch(&self, on_change: Rc<dyn Fn()>, on_end: OnEnd) -> Result<Unwatch, CompositorError> {
+        let events = ["workspace", "window", "output"].map(String::from);
+        let task = sway::subscribe(&events, move |_, _| on_change(), on_end)?;
+        Ok(Box::new(move || task.abort()))
+    }
+}
+
+struct Niri;
+
+impl Niri {
+    async fn action(action: JsonValue) -> Result<(), CompositorError> {
+        niri::request(&json!({ "Action": action })).await?;
+        Ok(())
+    }
+
+    fn window_id(id: &str) -> Result<u64, CompositorError> {
+        id.parse()
+            .map_err(|_| CompositorError::InvalidWindow(id.to_string()))
+    }
+}
+
+impl Compositor for Niri {
+    fn name(&self) -> &'static str {
+        "niri"
+    }
+
+    fn snapshot(&self) -> Task<Snapshot> {
+        async move {
+            // The event stream keeps a model while the compositor is watched.
+            let model = match niri::model() {
+                Some(model) => model,
+                None => niri::fetch_model().await?,
+            };
+            let mut workspaces: Vec<(u64, Workspace)> = model
+                .workspaces
+                .values()
+                .map(|ws| {
+                    let idx = ws["idx"].as_u64().unwrap_or_default();
+                    let workspace = Workspace {
+                        id: ws["id"].as_i64().unwrap_or_default(),
+                        name: ws["name"]
+                            .as_str()
+                            .map(str::to_string)
+                            .unwrap_or_else(|| idx.to_string()),
+                        output: ws["output"].as_str().map(str::to_string),
+                        active: ws["is_active"] == true,
+                        focused: ws["is_focused"] == true,
+                        urgent: ws["is_urgent"] == true,
+                        windows: 0,
+                    };
+                    (idx, workspace)
+                })
+                .collect();
+            workspaces.sort_by_key(|(idx, ws)| (ws.output.clone(), *idx));
+            let workspaces: Vec<Workspace> = workspaces.into_iter().map(|(_, ws)| ws).collect();
+            // Every niri output has at least one workspace.
+            let focused_output = workspaces
+                .iter()
+                .find(|ws| ws.focused)
+                .and_then(|ws| ws.output.clone());
+            let mut outputs: Vec<Output> = workspaces
+                .iter()
+                .filter_map(|ws| ws.output.clone())
+                .map(|name| Output {
+                    focused: Some(&name) == focused_output.as_ref(),
+                    name,
+                })
+                .collect();
+            outputs.dedup_by(|a, b| a.name == b.name);
+            let toplevels = model
+                .windows
+                .values()
+                .map(|w| Toplevel {
+                    id: w["id"].to_string(),
+                    title: str_of(&w["title"]),
+                    app_id: str_of(&w["app_id"]),
+                    workspace: w["workspace_id"].as_i64(),
+                    focused: w["is_focused"] == true,
+                    urgent: w["is_urgent"] == true,
+                    // Not reported by niri's IPC.
+                    fullscreen: false,
+                })
+                .collect();
+            Ok(Snapshot {
+                compositor: "niri",
+                outputs,
+                workspaces,
+                toplevels,
+            }
+            .link())
+        }
+        .boxed_local()
+    }
+
+    fn focus_workspace(&self, workspace: &Workspace) -> Task<()> {
+        Self::action(json!({ "FocusWorkspace": { "reference": { "Id": workspace.id } } }))
+            .boxed_local()
+    }
+
+    fn focus_toplevel(&self, id: &str) -> Task<()> {
+        let id = Self::window_id(id);
+        async move { Self::action(json!({ "FocusWindow": { "id": id? } })).await }.boxed_local()
+    }
+
+    fn move_to_workspace(&self, toplevel: Option<&str>, workspace: &Workspace) -> Task<()> {
+        let window_id = toplevel.map(Self::window_id).transpose();
+        let workspace_id = workspace.id;
+        async move {
+            Self::action(json!({
+                "MoveWindowToWorkspace": {
+                    "window_id": window_id?,
+                    "reference": { "Id": workspace_id },
+                    "focus": false,
+                }
+            }))
+            .await
+        }
+        .boxed_local()
+    }
+
+    fn watch(&self, on_change: Rc<dyn Fn()>, _on_end: OnEnd) -> Result<Unwatch, CompositorError> {
+        // The shared niri event stream reconnects by itself.
+        let id = niri::subscribe(move |_, _, _| on_change());
+        Ok(Box::new(move || niri::unsubscribe(id)))
+    }
+}
+
+/// Picks the backend for the running compositor.
+fn detect() -> Option<Rc<dyn Compositor>> {
+    if hyprland::is_running() {
+        Some(Rc::new(Hyprland {
+            urgent: Rc::default(),
+        }))
+    } else if niri::is_running() {
+        Some(Rc::new(Niri))
+    } else if sway::is_running() {
+        Some(Rc::new(Sway))
+    } else {
+        None
+    }
+}
+
+type Listener = Rc<dyn Fn(&Snapshot)>;
+
+#[derive(Default)]
+struct Shared {
+    snapshot: Option<Snapshot>,
+    listeners: Vec<(u64, Listener)>,
+    next_id: u64,
+    unwatch: Option<Unwatch>,
+    /// How long to wait before watching again after the event stream ended.
+    retry_delay: Option<Duration>,
+    refreshing: bool,
+    dirty: bool,
+}
+
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+thread_local! {
+    static BACKEND: Option<Rc<dyn Compositor>> = detect();
+    /// The model shared by every watcher, kept up to date while there is at
+    /// least one.
+    static SHARED: RefCell<Shared> = RefCell::new(Shared::default());
+}
+
+pub fn backend() -> Result<Rc<dyn Compositor>, CompositorError> {
+    BACKEND.with(|backend| backend.clone().ok_or(CompositorError::Unsupported))
+}
+
+/// Fetches a new snapshot and passes it to the listeners. Changes that
+/// arrive while a fetch is running trigger one more fetch after it.
+fn refresh() {
+    let busy = SHARED.with_borrow_mut(|shared| {
+        shared.dirty = shared.refreshing;
+        std::mem::replace(&mut shared.refreshing, true)
+    });
+    if busy {
+        return;
+    }
+    let Ok(backend) = backend() else { return };
+    glib::MainContext::default().spawn_local(async move {
+        loop {
+            let result = backend.snapshot().await;
+            let again = SHARED.with_borrow_mut(|shared| {
+                shared.refreshing = std::mem::take(&mut shared.dirty);
+                shared.refreshing
+            });
+            match result {
+                Ok(snapshot) => {
+                    let listeners: Vec<Listener> = SHARED.with_borrow_mut(|shared| {
+                        if shared.listeners.is_empty() {
+                            return Vec::new();
+                        }
+                        shared.snapshot = Some(snapshot.clone());
+                        shared.listeners.iter().map(|(_, l)| l.clone()).collect()
+                    });
+                    for listener in listeners {
+                        listener(&snapshot);
+                    }
+                }
+                Err(e) => eprintln!("Compositor error: {}", e),
+            }
+            if !again {
+                break;
+            }
+        }
+    });
+}
+
+/// Calls `listener` with the current model now, if there is one, and after
+/// every change. Watching starts with the first listener.
+pub fn subscribe(listener: impl Fn(&Snapshot) + 'static) -> Result<u64, CompositorError> {
+    let backend = backend()?;
+    let listener: Listener = Rc::new(listener);
+    let (id, start, current) = SHARED.with_borrow_mut(|shared| {
+        let id = shared.next_id;
+        shared.next_id += 1;
+        shared.listeners.push((id, listener.clone()));
+        (id, shared.unwatch.is_none(), shared.snapshot.clone())
+    });
+    if start {
+        watch(&backend)?;
+    } else if let Some(snapshot) = current {
+        listener(&snapshot);
+    }
+    Ok(id)
+}
+
+/// Starts the backend's event stream for the shared model.
+fn watch(backend: &Rc<dyn Compositor>) -> Result<(), CompositorError> {
+    let on_change = Rc::new(|| {
+        SHARED.with_borrow_mut(|shared| shared.retry_delay = None);
+        refresh();
+    });
+    let unwatch = backend.watch(on_change, Box::new(watch_ended))?;
+    SHARED.with_borrow_mut(|shared| shared.unwatch = Some(unwatch));
+    refresh();
+    Ok(())
+}
+
+/// Watches again, with a growing delay, after the event stream closed while
+/// there were still listeners.
+fn watch_ended() {
+    let delay = SHARED.with_borrow_mut(|shared| {
+        shared.unwatch = None;
+        let delay = shared.retry_delay.unwrap_or(RETRY_DELAY);
+        shared.retry_delay = Some((delay * 2).min(MAX_RETRY_DELAY));
+        delay
+    });
+    glib::MainContext::default().spawn_local(async move {
+        glib::timeout_future(delay).await;
+        let resume =
+            SHARED.with_borrow(|shared| shared.unwatch.is_none() && !shared.listeners.is_empty());
+        if !resume {
+            return;
+        }
+        if let Err(e) = backend().and_then(|backend| watch(&backend)) {
+            eprintln!("Compositor error: {}", e);
+            watch_ended();
+        }
+    });
+}
+
+/// Removes a listener, stopping the watch after the last one.
+pub fn unsubscribe(id: u64) {
+    let unwatch = SHARED.with_borrow_mut(|shared| {
+        shared.listeners.retain(|(i, _)| *i != id);
+        if !shared.listeners.is_empty() {
+            return None;
+        }
+        shared.snapshot = None;
+        shared.retry_delay = None;
+        shared.unwatch.take()
+    });
+    if let Some(unwatch) = unwatch {
+        unwatch();
+    }
+}
+
+async fn find_workspace(
+    backend: &Rc<dyn Compositor>,
+    id: i64,
+) -> Result<Workspace, CompositorError> {
+    let cached = SHARED.with_borrow(|shared| {
+        shared
+            .snapshot
+            .as_ref()
+            .and_then(|s| s.workspace(id).cloned())
+    });
+    match cached {
+        Some(workspace) => Ok(workspace),
+        None => backend
+            .snapshot()
+            .await?
+            .workspace(id)
+            .cloned()
+            .ok_or(CompositorError::WorkspaceNotFound(id)),
+    }
+}
+
+pub async fn focus_workspace(id: i64) -> Result<(), CompositorError> {
+    let backend = backend()?;
+    let workspace = find_workspace(&backend, id).await?;
+    backend.focus_workspace(&workspace).await
+}
+
+pub async fn focus_toplevel(id: String) -> Result<(), CompositorError> {
+    backend()?.focus_toplevel(&id).await
+}
+
+pub async fn move_to_workspace(toplevel: Option<String>, id: i64) -> Result<(), CompositorError> {
+    let backend = backend()?;
+    let workspace = find_workspace(&backend, id).await?;
+    backend
+        .move_to_workspace(toplevel.as_deref(), &workspace)
+        .await
+}
+
+fn to_lua(lua: &Lua, snapshot: &Snapshot) -> LuaResult<Value> {
+    let options = mlua::SerializeOptions::new()
+        .serialize_none_to_null(false)
+        .serialize_unit_to_null(false);
+    lua.to_value_with(snapshot, options)
+}
+
+/// Runs an action and reports it to the optional `callback(ok, err)`.
+fn run_action(
+    action: impl Future<Output = Result<(), CompositorError>> + 'static,
+    callback: Option<Function>,
+) {
+    glib::MainContext::default().spawn_local(async move {
+        let result = action.await;
+        let called = match (callback, result) {
+            (Some(callback), Ok(())) => callback.call::<()>((true, Value::Nil)),
+            (Some(callback), Err(e)) => callback.call::<()>((false, e.to_string())),
+            (None, Err(e)) => {
+                eprintln!("Compositor error: {}", e);
+                Ok(())
+            }
+            (None, Ok(())) => Ok(()),
+        };
+        if let Err(e) = called {
+            eprintln!("Compositor callback error: {}", e);
+        }
+    });
+}
+
+#[derive(Clone)]
+struct CompositorSubscription {
+    id: u64,
+    active: Rc<Cell<bool>>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl CompositorSubscription {
+    fn disconnect(&self) {
+        self.cleanup.remove();
+        if self.active.replace(false) {
+            unsubscribe(self.id);
+        }
+    }
+}
+
+impl UserData for CompositorSubscription {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("disconnect", |_, this, ()| {
+            this.disconnect();
+            Ok(())
+        });
+    }
+}
+
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    let compositor = lua.create_table()?;
+
+    compositor.set(
+        "name",
+        lua.create_function(|_, ()| Ok(backend().ok().map(|b| b.name())))?,
+    )?;
+
+    compositor.set(
+        "snapshot",
+        lua.create_function(|lua, callback: Function| {
+            let backend = backend()?;
+            let lua = lua.clone();
+            glib::MainContext::default().spawn_local(async move {
+                let result = backend
+                    .snapshot()
+                    .await
+                    .map_err(mlua::Error::from)
+                    .and_then(|snapshot| to_lua(&lua, &snapshot));
+                let called = match result {
+                    Ok(snapshot) => callback.call::<()>((snapshot, Value::Nil)),
+                    Err(e) => callback.call::<()>((Value::Nil, e.to_string())),
+                };
+                if let Err(e) = called {
+                    eprintln!("Compositor callback error: {}", e);
+                }
+            });
+            Ok(())
+        })?,
+    )?;
+
+    compositor.set(
+        "watch",
+        lua.create_function(|lua, callback: Function| {
+            let lua = lua.clone();
+            let id = subscribe(move |snapshot| {
+                let called = to_lua(&lua, snapshot).and_then(|s| callback.call::<()>(s));
+                if let Err(e) = called {
+                    eprintln!("Compositor watch callback error: {}", e);
+                }
+            })?;
+            let subscription = CompositorSubscription {
+                id,
+                active: Rc::new(Cell::new(true)),
+                cleanup: Default::default(),
+            };
+            let tracked = subscription.clone();
+            subscription
+                .cleanup
+                .set(lifecycle::track(move || tracked.disconnect()));
+            Ok(subscription)
+        })?,
+    )?;
+
+    compositor.set(
+        "focus_workspace",
+        lua.create_function(|_, (id, callback): (i64, Option<Function>)| {
+            run_action(focus_workspace(id), callback);
+            Ok(())
+        })?,
+    )?;
+
+    compositor.set(
+        "focus_window",
+        lua.create_function(|_, (id, callback): (String, Option<Function>)| {
+            run_action(focus_toplevel(id), callback);
+            Ok(())
+        })?,
+    )?;
+
+    compositor.set(
+        "move_to_workspace",
+        lua.create_function(
+            |_, (window, workspace, callback): (Option<String>, i64, Option<Function>)| {
+                run_action(move_to_workspace(window, workspace), callback);
+                Ok(())
+            },
+        )?,
+    )?;
+
+    lua.globals().set("Compositor", compositor)?;
+    Ok(())
+}
@@ -0,0 +1,682 @@
+use crate::ui::lifecycle;
+use futures_util::StreamExt;
+use mlua::{
+    Function, IntoLuaMulti, Lua, MultiValue, Result as LuaResult, Table, UserData, UserDataMethods,
+};
+use std::fmt;
+use std::future::Future;
+use std::rc::Rc;
+use std::str::FromStr;
+use tokio::sync::OnceCell;
+use zbus::message::{Body, Type as MessageType};
+use zbus::zvariant::{Array, Dict, ObjectPath, Signature, Structure, StructureBuilder, Value};
+use zbus::{Connection, MatchRule, Message, MessageStream, OwnedMatchRule};
+
+#[derive(Debug)]
+pub enum DbusClientError {
+    DbusError(zbus::Error),
+    VariantError(zbus::zvariant::Error),
+    LuaError(mlua::Error),
+    Conversion(String),
+}
+
+impl fmt::Display for DbusClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbusClientError::DbusError(e) => write!(f, "DBus error: {}", e),
+            DbusClientError::VariantError(e) => write!(f, "DBus value error: {}", e),
+            DbusClientError::LuaError(e) => write!(f, "Lua error: {}", e),
+            DbusClientError::Conversion(e) => write!(f, "DBus conversion error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DbusClientError {}
+
+impl From<zbus::Error> for DbusClientError {
+    fn from(err: zbus::Error) -> Self {
+        DbusClientError::DbusError(err)
+    }
+}
+
+impl From<zbus::zvariant::Error> for DbusClientError {
+    fn from(err: zbus::zvariant::Error) -> Self {
+        DbusClientError::VariantError(err)
+    }
+}
+
+impl From<zbus::zvariant::signature::Error> for DbusClientError {
+    fn from(err: zbus::zvariant::signature::Error) -> Self {
+        DbusClientError::Conversion(format!("Invalid signature: {}", err))
+    }
+}
+
+impl From<mlua::Error> for DbusClientError {
+    fn from(err: mlua::Error) -> Self {
+        DbusClientError::LuaError(err)
+    }
+}
+
+impl From<DbusClientError> for mlua::Error {
+    fn from(err: DbusClientError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Bus {
+    Session,
+    System,
+}
+
+impl Bus {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "session" | "user" => Some(Bus::Session),
+            "system" => Some(Bus::System),
+            _ => None,
+        }
+    }
+}
+
+static SESSION_CONNECTION: OnceCell<Connection> = OnceCell::const_new();
+static SYSTEM_CONNECTION: OnceCell<Connection> = OnceCell::const_new();
+
+pub async fn connection(bus: Bus) -> zbus::Result<Connection> {
+    let cell = match bus {
+        Bus::Session => &SESSION_CONNECTION,
+        Bus::System => &SYSTEM_CONNECTION,
+    };
+    cell.get_or_try_init(|| async {
+        match bus {
+            Bus::Session => Connection::session().await,
+            Bus::System => Connection::system().await,
+        }
+    })
+    .await
+    .cloned()
+}
+
+pub struct LuaVariant {
+    signature: Signature,
+    value: mlua::Value,
+}
+
+impl UserData for LuaVariant {}
+
+fn complete_type_end(bytes: &[u8], pos: usize) -> Option<usize> {
+    match bytes.get(pos)? {
+        b'a' => complete_type_end(bytes, pos + 1),
+        b'(' | b'{' => {
+            let mut depth = 0;
+            for (i, b) in bytes.iter().enumerate().skip(pos) {
+                match b {
+                    b'(' | b'{' => depth += 1,
+                    b')' | b'}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i + 1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            None
+        }
+        _ => Some(pos + 1),
+    }
+}
+
+pub fn split_signature(signature: &str) -> Result<Vec<Signature>, DbusClientError> {
+    let bytes = signature.as_bytes();
+    let mut types = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = complete_type_end(bytes, start).ok_or_else(|| {
+            DbusClientError::Conversion(format!("Invalid signature: '{}'", signature))
+        })?;
+        types.push(Signature::from_str(&signature[start..end])?);
+        start = end;
+    }
+    Ok(types)
+}
+
+fn lua_integer(value: &mlua::Value) -> Option<i64> {
+    match value {
+        mlua::Value::Integer(i) => Some(*i),
+        mlua::Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Some(*n as i64),
+        _ => None,
+    }
+}
+
+fn lua_number(value: &mlua::Value) -> Option<f64> {
+    match value {
+        mlua::Value::Integer(i) => Some(*i as f64),
+        mlua::Value::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn lua_string(value: &mlua::Value) -> Option<String> {
+    match value {
+        mlua::Value::String(s) => s.to_str().ok().map(|s| s.to_string()),
+        _ => None,
+    }
+}
+
+pub fn infer_value(value: &mlua::Value) -> Result<Value<'static>, DbusClientError> {
+    match value {
+        mlua::Value::Boolean(b) => Ok(Value::Bool(*b)),
+        mlua::Value::Integer(i) => Ok(i32::try_from(*i).map(Value::I32).unwrap_or(Value::I64(*i))),
+        mlua::Value::Number(n) => Ok(Value::F64(*n)),
+        mlua::Value::String(_) => lua_to_value(value, &Signature::Str),
+        mlua::Value::Table(t) if t.raw_len() > 0 => {
+            lua_to_value(value, &Signature::array(Signature::Variant))
+        }
+        mlua::Value::Table(_) => {
+            lua_to_value(value, &Signature::dict(Signature::Str, Signature::Variant))
+        }
+        mlua::Value::UserData(ud) => {
+            let variant = ud.borrow::<LuaVariant>().map_err(|_| {
+                DbusClientError::Conversion("Unsupported userdata in DBus value".to_string())
+            })?;
+            lua_to_value(&variant.value, &variant.signature)
+        }
+        _ => Err(DbusClientError::Conversion(format!(
+            "Cannot infer a DBus type for Lua {}",
+            value.type_name()
+        ))),
+    }
+}
+
+/// Converts a Lua number to the integer type of `signature`, rejecting values
+/// that don't fit instead of wrapping them.
+fn integer<T: TryFrom<i64>>(
+    value: &mlua::Value,
+    signature: &Signature,
+) -> Result<T, DbusClientError> {
+    let n = lua_integer(value).ok_or_else(|| {
+        DbusClientError::Conversion(format!(
+            "Cannot convert Lua {} to DBus type '{}'",
+            value.type_name(),
+            signature
+        ))
+    })?;
+    T::try_from(n).map_err(|_| {
+        DbusClientError::Conversion(format!(
+            "{} is out of range for DBus type '{}'",
+            n, signature
+        ))
+    })
+}
+
+pub fn lua_to_value(
+    value: &mlua::Value,
+    signature: &Signature,
+) -> Result<Value<'static>, DbusClientError> {
+    let mismatch = || {
+        DbusClientError::Conversion(format!(
+            "Cannot convert Lua {} to DBus type '{}'",
+            value.type_name(),
+            signature
+        ))
+    };
+    let string = || lua_string(value).ok_or_else(mismatch);
+
+    let converted = match signature {
+        Signature::U8 => Value::U8(integer(value, signature)?),
+        Signature::Bool => Value::Bool(value.as_boolean().ok_or_else(mismatch)?),
+        Signature::I16 => Value::I16(integer(value, signature)?),
+        Signature::U16 => Value::U16(integer(value, signature)?),
+        Signature::I32 => Value::I32(integer(value, signature)?),
+        Signature::U32 => Value::U32(integer(value, signature)?),
+        Signature::I64 => Value::I64(integer(value, signature)?),
+        Signature::U64 => Value::U64(integer(value, signature)?),
+        Signature::F64 => Value::F64(lua_number(value).ok_or_else(mismatch)?),
+        Signature::Str => Value::Str(string()?.into()),
+        Signature::ObjectPath => Value::ObjectPath(ObjectPath::try_from(string()?)?),
+        Signature::Signature => Value::Signature(Signature::from_str(&string()?)?),
+        Signature::Variant => Value::Value(Box::new(infer_value(value)?)),
+        Signature::Array(child) => {
+            if let (Signature::U8, mlua::Value::String(s)) = (child.signature(), value) {
+                return Ok(Value::Array(Array::from(s.as_bytes().to_vec())));
+            }
+            let table = value.as_table().ok_or_else(mismatch)?;
+            let mut array = Array::new(child);
+            for item in table.sequence_values::<mlua::Value>() {
+                array.append(lua_to_value(&item?, child)?)?;
+            }
+            Value::Array(array)
+        }
+        Signature::Dict { key, value: item } => {
+            let table = value.as_table().ok_or_else(mismatch)?;
+            let mut dict = Dict::new(key, item);
+            for pair in table.pairs::<mlua::Value, mlua::Value>() {
+                let (k, v) = pair?;
+                dict.append(lua_to_value(&k, key)?, lua_to_value(&v, item)?)?;
+            }
+            Value::Dict(dict)
+        }
+        Signature::Structure(fields) => {
+            let table = value.as_table().ok_or_else(mismatch)?;
+            let mut builder = StructureBuilder::new();
+            for (i, field) in fields.iter().enumerate() {
+                let item: mlua::Value = table.raw_get(i + 1)?;
+                builder = builder.append_field(lua_to_value(&item, field)?);
+            }
+            Value::Structure(builder.build()?)
+        }
+        _ => {
+            return Err(DbusClientError::Conversion(format!(
+                "DBus type '{}' is not supported",
+                signature
+            )));
+        }
+    };
+    Ok(converted)
+}
+
+pub fn value_to_lua(lua: &Lua, value: &Value) -> LuaResult<mlua::Value> {
+    let converted = match value {
+        Value::U8(v) => mlua::Value::Integer(*v as i64),
+        Value::Bool(v) => mlua::Value::Boolean(*v),
+        Value::I16(v) => mlua::Value::Integer(*v as i64),
+        Value::U16(v) => mlua::Value::Integer(*v as i64),
+        Value::I32(v) => mlua::Value::Integer(*v as i64),
+        Value::U32(v) => mlua::Value::Integer(*v as i64),
+        Value::I64(v) => mlua::Value::Integer(*v),
+        Value::U64(v) => mlua::Value::Integer(*v as i64),
+        Value::F64(v) => mlua::Value::Number(*v),
+        Value::Str(s) => mlua::Value::String(lua.create_string(s.as_str())?),
+        Value::Signature(s) => mlua::Value::String(lua.create_string(s.to_string())?),
+        Value::ObjectPath(p) => mlua::Value::String(lua.create_string(p.as_str())?),
+        Value::Value(inner) => value_to_lua(lua, inner)?,
+        Value::Array(array) if *array.element_signature() == Signature::U8 => {
+            let bytes: Vec<u8> = array
+                .iter()
+                .filter_map(|v| match v {
+                    Value::U8(b) => Some(*b),
+                    _ => None,
+                })
+                .collect();
+            mlua::Value::String(lua.create_string(bytes)?)
+        }
+        Value::Array(array) => {
+            let table = lua.create_table_with_capacity(array.len(), 0)?;
+            for (i, item) in array.iter().enumerate() {
+                table.raw_set(i + 1, value_to_lua(lua, item)?)?;
+            }
+            mlua::Value::Table(table)
+        }
+        Value::Dict(dict) => {
+            let table = lua.create_table()?;
+            for (k, v) in dict.iter() {
+                table.raw_set(value_to_lua(lua, k)?, value_to_lua(lua, v)?)?;
+            }
+            mlua::Value::Table(table)
+        }
+        Value::Structure(structure) => {
+            let table = lua.create_table_with_capacity(structure.fields().len(), 0)?;
+            for (i, field) in structure.fields().iter().enumerate() {
+                table.raw_set(i + 1, value_to_lua(lua, field)?)?;
+            }
+            mlua::Value::Table(table)
+        }
+        _ => mlua::Value::Nil,
+    };
+    Ok(converted)
+}
+
+pub fn build_body(
+    signature: &str,
+    args: Option<&Table>,
+) -> Result<Option<Structure<'static>>, DbusClientError> {
+    let types = split_signature(signature)?;
+    if types.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = StructureBuilder::new();
+    for (i, ty) in types.iter().enumerate() {
+        let arg = match args {
+            Some(t) => t.raw_get::<mlua::Value>(i + 1)?,
+            None => mlua::Value::Nil,
+        };
+        builder = builder.append_field(lua_to_value(&arg, ty)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+pub fn body_values<'b>(body: &'b Body) -> Result<Vec<Value<'b>>, DbusClientError> {
+    if *body.signature() == Signature::Unit {
+        return Ok(Vec::new());
+    }
+    Ok(body.deserialize::<Structure>()?.into_fields())
+}
+
+fn reply_values(lua: &Lua, msg: &Message) -> Result<mlua::Value, DbusClientError> {
+    let body = msg.body();
+    let table = lua.create_table()?;
+    for (i, value) in body_values(&body)?.iter().enumerate() {
+        table.raw_set(i + 1, value_to_lua(lua, value)?)?;
+    }
+    Ok(mlua::Value::Table(table))
+}
+
+fn reply_first_value(lua: &Lua, msg: &Message) -> Result<mlua::Value, DbusClientError> {
+    let body = msg.body();
+    match body_values(&body)?.first() {
+        Some(value) => Ok(value_to_lua(lua, value)?),
+        None => Ok(mlua::Value::Nil),
+    }
+}
+
+fn message_info(lua: &Lua, msg: &Message) -> LuaResult<Table> {
+    let header = msg.header();
+    let info = lua.create_table_with_capacity(0, 4)?;
+    info.set("sender", header.sender().map(|s| s.to_string()))?;
+    info.set("path", header.path().map(|p| p.to_string()))?;
+    info.set("interface", header.interface().map(|i| i.to_string()))?;
+    info.set("member", header.member().map(|m| m.to_string()))?;
+    Ok(info)
+}
+
+fn call_lua_callback<A: IntoLuaMulti>(lua: &Lua, key: &mlua::RegistryKey, args: A) {
+    if let Ok(func) = lua.registry_value::<Function>(key)
+        && let Err(e) = func.call::<()>(args)
+    {
+        eprintln!("DBus callback error: {}", e);
+    }
+}
+
+fn dispatch_call<F, C>(
+    lua: &Lua,
+    callback: Option<Function>,
+    future: F,
+    convert: C,
+) -> LuaResult<()>
+where
+    F: Future<Output = Result<Message, DbusClientError>> + 'static,
+    C: Fn(&Lua, &Message) -> Result<mlua::Value, DbusClientError> + 'static,
+{
+    let lua = lua.clone();
+    let cb_key = callback.map(|f| lua.create_registry_value(f)).transpose()?;
+    glib::MainContext::default().spawn_local(async move {
+        let result = match future.await {
+            Ok(msg) => convert(&lua, &msg),
+            Err(e) => Err(e),
+        };
+        match (cb_key, result) {
+            (Some(key), result) => call_lua_callback(&lua, &key, result.map_err(|e| e.to_string())),
+            (None, Err(e)) => eprintln!("DBus call failed: {}", e),
+            (None, Ok(_)) => {}
+        }
+    });
+    Ok(())
+}
+
+struct Subscription {
+    handle: glib::JoinHandle<()>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl UserData for Subscription {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("disconnect", |_, this, ()| {
+            this.cleanup.remove();
+            this.handle.abort();
+            Ok(())
+        });
+    }
+}
+
+fn subscribe<D>(
+    lua: &Lua,
+    bus: Bus,
+    rule: OwnedMatchRule,
+    callback: Function,
+    deliver: D,
+) -> LuaResult<Subscription>
+where
+    D: Fn(&Lua, &Message) -> Result<MultiValue, DbusClientError> + 'static,
+{
+    let lua = lua.clone();
+    let cb_key = lua.create_registry_value(callback)?;
+    let cleanup = lifecycle::CleanupSlot::default();
+    let finished = cleanup.clone();
+    let handle = glib::MainContext::default().spawn_local(async move {
+        let stream = match connection(bus).await {
+            Ok(conn) => MessageStream::for_match_rule(rule, &conn, None).await,
+            Err(e) => Err(e),
+        };
+        match stream {
+            Ok(mut stream) => {
+                while let Some(msg) = stream.next().await {
+                    let Ok(msg) = msg else {
+                        continue;
+                    };
+                    match deliver(&lua, &msg) {
+                        Ok(args) => call_lua_callback(&lua, &cb_key, args),
+                        Err(e) => eprintln!("DBus signal conversion error: {}", e),
+                    }
+                }
+            }
+            Err(e) => eprintln!("DBus subscription failed: {}", e),
+        }
+        finished.remove();
+    });
+    let source = handle.source().clone();
+    cleanup.set(lifecycle::track(move || source.destroy()));
+    Ok(Subscription { handle, cleanup })
+}
+
+fn match_rule_from_table(rule: &Table) -> Result<OwnedMatchRule, DbusClientError> {
+    let sender: Option<String> = rule.get("sender")?;
+    let path: Option<String> = rule.get("path")?;
+    let path_namespace: Option<String> = rule.get("path_namespace")?;
+    let interface: Option<String> = rule.get("interface")?;
+    let member: Option<String> = rule.get("member")?;
+    let arg0: Option<String> = rule.get("arg0")?;
+
+    let mut builder = MatchRule::builder().msg_type(MessageType::Signal);
+    if let Some(sender) = sender.as_deref() {
+        builder = builder.sender(sender)?;
+    }
+    if let Some(path) = path.as_deref() {
+        builder = builder.path(path)?;
+    }
+    if let Some(path_namespace) = path_namespace.as_deref() {
+        builder = builder.path_namespace(path_namespace)?;
+    }
+    if let Some(interface) = interface.as_deref() {
+        builder = builder.interface(interface)?;
+    }
+    if let Some(member) = member.as_deref() {
+        builder = builder.member(member)?;
+    }
+    if let Some(arg0) = arg0.as_deref() {
+        builder = builder.arg(0, arg0)?;
+    }
+    Ok(builder.build().into())
+}
+
+#[derive(Clone, Copy)]
+pub struct LuaBusConnection(pub Bus);
+
+impl UserData for LuaBusConnection {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method(
+            "call",
+            |lua, this, (opts, callback): (Table, Option<Function>)| {
+                let destination: String = opts.get("destination")?;
+                let path: String = opts.get("path")?;
+                let interface: Option<String> = opts.get("interface")?;
+                let method: String = opts.get("method")?;
+                let signature: Option<String> = opts.get("signature")?;
+                let args: Option<Table> = opts.get("args")?;
+                let body = build_body(signature.as_deref().unwrap_or(""), args.as_ref())?;
+                let bus = this.0;
+
+                let future = async move {
+                    let conn = connection(bus).await?;
+                    let destination = Some(destination.as_str());
+                    let interface = interface.as_deref();
+                    let reply = match &body {
+                        Some(body) => {
+                            conn.call_method(
+                                destination,
+                                path.as_str(),
+                                interface,
+                                method.as_str(),
+                                body,
+                            )
+                            .await?
+                        }
+                        None => {
+                            conn.call_method(
+                                destination,
+                                path.as_str(),
+                                interface,
+                                method.as_str(),
+                                &(),
+                            )
+                            .await?
+                        }
+                    };
+                    Ok(reply)
+                };
+                dispatch_call(lua, callback, future, reply_values)
+            },
+        );
+
+        methods.add_method(
+            "get_property",
+            |lua,
+             this,
+             (destination, path, interface, name, callback): (
+                String,
+                String,
+                String,
+                String,
+                Function,
+            )| {
+                let bus = this.0;
+                let future = async move {
+                    let conn = connection(bus).await?;
+                    let reply = conn
+                        .call_method(
+                            Some(destination.as_str()),
+                            path.as_str(),
+                            Some("org.freedesktop.DBus.Properties"),
+                            "Get",
+                            &(interface.as_str(), name.as_str()),
+                        )
+                        .await?;
+                    Ok(reply)
+                };
+                dispatch_call(lua, Some(callback), future, reply_first_value)
+            },
+        );
+
+        methods.add_method(
+            "set_property",
+            |lua,
+             this,
+             (destination, path, interface, name, value, signature, callback): (
+                String,
+                String,
+                String,
+                String,
+                mlua::Value,
+                Option<String>,
+                Option<Function>,
+            )| {
+                let value = match signature {
+                    Some(sig) => lua_to_value(
+                        &value,
+                        &Signature::from_str(&sig).map_err(DbusClientError::from)?,
+                    )?,
+                    None => infer_value(&value)?,
+                };
+                let body = StructureBuilder::new()
+                    .append_field(Value::Str(interface.into()))
+                    .append_field(Value::Str(name.into()))
+                    .append_field(Value::Value(Box::new(value)))
+                    .build()
+                    .map_err(DbusClientError::from)?;
+                let bus = this.0;
+                let future = async move {
+                    let conn = connection(bus).await?;
+                    let reply = conn
+                        .call_method(
+                            Some(destination.as_str()),
+                            path.as_str(),
+                            Some("org.freedesktop.DBus.Properties"),
+                            "Set",
+                            &body,
+                        )
+                        .await?;
+                    Ok(reply)
+                };
+                dispatch_call(lua, callback, future, |_, _| Ok(mlua::Value::Boolean(true)))
+            },
+        );
+
+        methods.add_method(
+            "subscribe",
+            |lua, this, (rule, callback): (Table, Function)| {
+                let rule = match_rule_from_table(&rule)?;
+                subscribe(lua, this.0, rule, callback, |lua, msg| {
+                    let args = reply_values(lua, msg)?;
+                    let info = message_info(lua, msg)?;
+                    Ok((args, info).into_lua_multi(lua)?)
+                })
+            },
+        );
+
+        methods.add_method(
+            "watch_properties",
+            |lua,
+             this,
+             (destination, path, interface, callback): (String, String, String, Function)| {
+                let rule: OwnedMatchRule = MatchRule::builder()
+                    .msg_type(MessageType::Signal)
+                    .sender(destination.as_str())
+                    .and_then(|b| b.path(path.as_str()))
+                    .and_then(|b| b.interface("org.freedesktop.DBus.Properties"))
+                    .and_then(|b| b.member("PropertiesChanged"))
+                    .and_then(|b| b.arg(0, interface.as_str()))
+                    .map_err(DbusClientError::from)?
+                    .build()
+                    .into();
+                subscribe(lua, this.0, rule, callback, |lua, msg| {
+                    let body = msg.body();
+                    let values = body_values(&body)?;
+                    let changed = match values.get(1) {
+                        Some(v) => value_to_lua(lua, v)?,
+                        None => mlua::Value::Table(lua.create_table()?),
+                    };
+                    let invalidated = match values.get(2) {
+                        Some(v) => value_to_lua(lua, v)?,
+                        None => mlua::Value::Table(lua.create_table()?),
+                    };
+                    Ok((changed, invalidated).into_lua_multi(lua)?)
+                })
+            },
+        );
+    }
+}
+
+pub fn register(lua: Rc<Lua>) -> LuaResult<()> {
+    let dbus = lua.create_table()?;
+    dbus.set("session", LuaBusConnection(Bus::Session))?;
+    dbus.set("system", LuaBusConnection(Bus::System))?;
+    dbus.set(
+        "variant",
+        lua.create_function(|_, (signature, value): (String, mlua::Value)| {
+            let signature = Signature::from_str(&signature).map_err(DbusClientError::from)?;
+            Ok(LuaVariant { signature, value })
+        })?,
+    )?;
+    lua.globals().set("DBus", dbus)?;
+    Ok(())
+}
@@ -0,0 +1,832 @@
+use mlua::{Function, Lua, MultiValue, Result as LuaResult, Table, UserData, UserDataMethods};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio::sync::oneshot;
+use zbus::export::async_trait::async_trait;
+use zbus::fdo;
+use zbus::message::Header;
+use zbus::names::{InterfaceName, MemberName, WellKnownName};
+use zbus::object_server::{DispatchResult, Interface, SignalEmitter};
+use zbus::zvariant::{
+    Array, Dict, ObjectPath, OwnedValue, Signature, Structure, StructureBuilder, Value,
+};
+use zbus::{Connection, Message, ObjectServer};
+
+use super::dbus_client::{
+    DbusClientError, body_values, build_body, lua_to_value, split_signature, value_to_lua,
+};
+use super::dbus_service::service_connection;
+
+struct ExportedMethod {
+    args: String,
+    returns: String,
+    handler: mlua::RegistryKey,
+}
+
+struct ExportedProperty {
+    signature: Signature,
+    value: RefCell<Value<'static>>,
+    writable: bool,
+    on_set: Option<mlua::RegistryKey>,
+}
+
+struct ExportedObject {
+    path: String,
+    interface: String,
+    methods: HashMap<String, ExportedMethod>,
+    properties: HashMap<String, ExportedProperty>,
+    signals: HashMap<String, String>,
+}
+
+struct MethodError(fdo::Error);
+
+impl From<fdo::Error> for MethodError {
+    fn from(err: fdo::Error) -> Self {
+        MethodError(err)
+    }
+}
+
+impl From<DbusClientError> for MethodError {
+    fn from(err: DbusClientError) -> Self {
+        MethodError(fdo::Error::Failed(err.to_string()))
+    }
+}
+
+impl From<mlua::Error> for MethodError {
+    fn from(err: mlua::Error) -> Self {
+        MethodError(fdo::Error::Failed(err.to_string()))
+    }
+}
+
+/// Work for the Lua side of an exported object. The object server runs on
+/// its own executor, so calls are handed to the main thread and answered
+/// through `reply`.
+enum Request {
+    Call {
+        msg: Message,
+        path: String,
+        interface: String,
+        member: String,
+        reply: oneshot::Sender<Result<Vec<OwnedValue>, fdo::Error>>,
+    },
+    Get {
+        path: String,
+        interface: String,
+        name: String,
+        reply: oneshot::Sender<Option<fdo::Result<OwnedValue>>>,
+    },
+    GetAll {
+        path: String,
+        interface: String,
+        reply: oneshot::Sender<fdo::Result<HashMap<String, OwnedValue>>>,
+    },
+    Set {
+        path: String,
+        interface: String,
+        name: String,
+        value: OwnedValue,
+        reply: oneshot::Sender<Option<fdo::Result<()>>>,
+    },
+}
+
+enum ServerOp {
+    Add(LuaInterface),
+    Remove { path: String, interface: String },
+}
+
+/// The number of distinct interface names that can be exported.
+const NAME_SLOTS: usize = 16;
+
+/// `Interface::name` belongs to the type, while exported interfaces are
+/// named from Lua, so each name is given its own `Exported<SLOT>` type. A
+/// slot keeps its name for the rest of the process.
+static SLOT_NAMES: [OnceLock<InterfaceName<'static>>; NAME_SLOTS] =
+    [const { OnceLock::new() }; NAME_SLOTS];
+
+/// The slot named `name`, claiming a free one if there is none yet.
+fn name_slot(name: &InterfaceName<'static>) -> Option<usize> {
+    SLOT_NAMES
+        .iter()
+        .position(|slot| slot.get_or_init(|| name.clone()) == name)
+}
+
+/// Runs `$body` with `$slot` as the const `SLOT` of the matching
+/// `Exported<SLOT>`.
+macro_rules! with_slot {
+    ($slot:expr, $body:expr) => {
+        with_slot!($slot, $body, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15)
+    };
+    ($slot:expr, $body:expr, $($n:literal)*) => {
+        match $slot {
+            $($n => {
+                const SLOT: usize = $n;
+                $body
+            })*
+            _ => unreachable!("interface name slot out of range"),
+        }
+    };
+}
+
+thread_local! {
+    static EXPORTS: RefCell<Vec<Rc<ExportedObject>>> = const { RefCell::new(Vec::new()) };
+    static REQUESTS: RefCell<Option<UnboundedSender<Request>>> = const { RefCell::new(None) };
+    static SERVER_OPS: RefCell<VecDeque<ServerOp>> = const { RefCell::new(VecDeque::new()) };
+    static SERVER_BUSY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// An exported object as served by the service connection's object server.
+struct LuaInterface {
+    path: String,
+    interface: String,
+    methods: Vec<String>,
+    /// Introspection XML for the interface's members, one per line.
+    members: Vec<String>,
+    requests: UnboundedSender<Request>,
+}
+
+impl LuaInterface {
+    async fn request<T>(
+        &self,
+        build: impl FnOnce(oneshot::Sender<T>) -> Request,
+    ) -> Result<T, fdo::Error> {
+        let (reply, receiver) = oneshot::channel();
+        self.requests
+            .send(build(reply))
+            .map_err(|_| fdo::Error::Failed("Lua side of the object is gone".to_string()))?;
+        receiver
+            .await
+            .map_err(|_| fdo::Error::Failed("Lua handler did not reply".to_string()))
+    }
+}
+
+/// A `LuaInterface` served under the name of its slot.
+struct Exported<const SLOT: usize>(LuaInterface);
+
+#[async_trait]
+impl<const SLOT: usize> Interface for Exported<SLOT> {
+    fn name() -> InterfaceName<'static> {
+        SLOT_NAMES[SLOT]
+            .get()
+            .cloned()
+            .expect("Exported is only served from a named slot")
+    }
+
+    async fn get(
+        &self,
+        property_name: &str,
+        _server: &ObjectServer,
+        _connection: &Connection,
+        _header: Option<&Header<'_>>,
+        _emitter: &SignalEmitter<'_>,
+    ) -> Option<fdo::Result<OwnedValue>> {
+        self.0
+            .request(|reply| Request::Get {
+                path: self.0.path.clone(),
+                interface: self.0.interface.clone(),
+                name: property_name.to_string(),
+                reply,
+            })
+            .await
+            .unwrap_or_else(|e| Some(Err(e)))
+    }
+
+    async fn get_all(
+        &self,
+        _server: &ObjectServer,
+        _connection: &Connection,
+        _header: Option<&Header<'_>>,
+        _emitter: &SignalEmitter<'_>,
+    ) -> fdo::Result<HashMap<String, OwnedValue>> {
+        self.0
+            .request(|reply| Request::GetAll {
+                path: self.0.path.clone(),
+                interface: self.0.interface.clone(),
+                reply,
+            })
+            .await?
+    }
+
+    async fn set_mut(
+        &mut self,
+        property_name: &str,
+        value: &Value<'_>,
+        _server: &ObjectServer,
+        _connection: &Connection,
+        _header: Option<&Header<'_>>,
+        _emitter: &SignalEmitter<'_>,
+    ) -> Option<fdo::Result<()>> {
+        let value = match value.try_to_owned() {
+            Ok(value) => value,
+            Err(e) => return Some(Err(fdo::Error::InvalidArgs(e.to_string()))),
+        };
+        self.0
+            .request(|reply| Request::Set {
+                path: self.0.path.clone(),
+                interface: self.0.interface.clone(),
+                name: property_name.to_string(),
+                value,
+                reply,
+            })
+            .await
+            .unwrap_or_else(|e| Some(Err(e)))
+    }
+
+    fn call<'call>(
+        &'call self,
+        _server: &'call ObjectServer,
+        connection: &'call Connection,
+        msg: &'call Message,
+        name: MemberName<'call>,
+    ) -> DispatchResult<'call> {
+        if !self.0.methods.iter().any(|m| m == name.as_str()) {
+            return DispatchResult::NotFound;
+        }
+        DispatchResult::Async(Box::pin(async move {
+            let result = self
+                .0
+                .request(|reply| Request::Call {
+                    msg: msg.clone(),
+                    path: self.0.path.clone(),
+                    interface: self.0.interface.clone(),
+                    member: name.to_string(),
+                    reply,
+                })
+                .await
+                .and_then(|result| result);
+            let header = msg.header();
+            match result {
+                Ok(values) if values.is_empty() => connection.reply(&header, &()).await,
+                Ok(values) => {
+                    let mut builder = StructureBuilder::new();
+                    for value in values {
+                        builder = builder.append_field(Value::from(value));
+                    }
+                    connection.reply(&header, &builder.build()?).await
+                }
+                Err(e) => connection.reply_dbus_error(&header, e).await,
+            }
+        }))
+    }
+
+    fn call_mut<'call>(
+        &'call mut self,
+        server: &'call ObjectServer,
+        connection: &'call Connection,
+        msg: &'call Message,
+        name: MemberName<'call>,
+    ) -> DispatchResult<'call> {
+        self.call(server, connection, msg, name)
+    }
+
+    fn introspect_to_writer(&self, writer: &mut dyn Write, level: usize) {
+        let _ = writeln!(
+            writer,
+            "{:indent$}<interface name=\"{}\">",
+            "",
+            self.0.interface,
+            indent = level
+        );
+        for line in &self.0.members {
+            let _ = writeln!(writer, "{:indent$}{}", "", line, indent = level + 2);
+        }
+        let _ = writeln!(writer, "{:indent$}</interface>", "", indent = level);
+    }
+}
+
+fn find_object(path: &str, interface: &str) -> Option<Rc<ExportedObject>> {
+    EXPORTS.with_borrow(|exports| {
+        exports
+            .iter()
+            .find(|o| o.path == path && o.interface == interface)
+            .cloned()
+    })
+}
+
+fn write_args(members: &mut Vec<String>, signature: &str, direction: Option<&str>) {
+    for ty in split_signature(signature).unwrap_or_default() {
+        members.push(match direction {
+            Some(direction) => format!("  <arg type=\"{}\" direction=\"{}\"/>", ty, direction),
+            None => format!("  <arg type=\"{}\"/>", ty),
+        });
+    }
+}
+
+fn introspect(object: &ExportedObject) -> Vec<String> {
+    let mut members = Vec::new();
+    for (name, method) in &object.methods {
+        members.push(format!("<method name=\"{}\">", name));
+        write_args(&mut members, &method.args, Some("in"));
+        write_args(&mut members, &method.returns, Some("out"));
+        members.push("</method>".to_string());
+    }
+    for (name, property) in &object.properties {
+        let access = if property.writable {
+            "readwrite"
+        } else {
+            "read"
+        };
+        members.push(format!(
+            "<property name=\"{}\" type=\"{}\" access=\"{}\"/>",
+            name, property.signature, access
+        ));
+    }
+    for (name, signature) in &object.signals {
+        members.push(format!("<signal name=\"{}\">", name));
+        write_args(&mut members, signature, None);
+        members.push("</signal>".to_string());
+    }
+    members
+}
+
+fn spawn_emit(path: String, interface: String, member: String, body: Option<Structure<'static>>) {
+    glib::MainContext::default().spawn_local(async move {
+        let result = async {
+            let conn = service_connection().await?;
+            let destination = None::<&str>;
+            match &body {
+                Some(body) => {
+                    conn.emit_signal(
+                        destination,
+                        path.as_str(),
+                        interface.as_str(),
+                        member.as_str(),
+                        body,
+                    )
+                    .await
+                }
+                None => {
+                    conn.emit_signal(
+                        destination,
+                        path.as_str(),
+                        interface.as_str(),
+                        member.as_str(),
+                        &(),
+                    )
+                    .await
+                }
+            }
+        }
+        .await;
+        if let Err(e) = result {
+            eprintln!("Failed to emit DBus signal '{}': {}", member, e);
+        }
+    });
+}
+
+fn notify_property_changed(object: &ExportedObject, name: &str) -> Result<(), DbusClientError> {
+    let Some(property) = object.properties.get(name) else {
+        return Ok(());
+    };
+    let mut changed = Dict::new(&Signature::Str, &Signature::Variant);
+    changed.append(
+        Value::Str(name.to_string().into()),
+        Value::Value(Box::new(property.value.borrow().clone())),
+    )?;
+    let body = StructureBuilder::new()
+        .append_field(Value::Str(object.interface.clone().into()))
+        .append_field(Value::Dict(changed))
+        .append_field(Value::Array(Array::new(&Signature::Str)))
+        .build()?;
+    spawn_emit(
+        object.path.clone(),
+        "org.freedesktop.DBus.Properties".to_string(),
+        "PropertiesChanged".to_string(),
+        Some(body),
+    );
+    Ok(())
+}
+
+fn object_for(path: &str, interface: &str) -> Result<Rc<ExportedObject>, MethodError> {
+    find_object(path, interface).ok_or_else(|| {
+        MethodError(fdo::Error::UnknownInterface(format!(
+            "Unknown interface '{}'",
+            interface
+        )))
+    })
+}
+
+fn property_value(property: &ExportedProperty) -> Result<OwnedValue, MethodError> {
+    let value = property.value.borrow().try_to_owned();
+    Ok(value.map_err(DbusClientError::from)?)
+}
+
+fn get_property(path: &str, interface: &str, name: &str) -> Option<fdo::Result<OwnedValue>> {
+    let object = match object_for(path, interface) {
+        Ok(object) => object,
+        Err(e) => return Some(Err(e.0)),
+    };
+    let property = object.properties.get(name)?;
+    Some(property_value(property).map_err(|e| e.0))
+}
+
+fn get_all_properties(
+    path: &str,
+    interface: &str,
+) -> Result<HashMap<String, OwnedValue>, MethodError> {
+    let object = object_for(path, interface)?;
+    let mut all = HashMap::new();
+    for (name, property) in &object.properties {
+        all.insert(name.clone(), property_value(property)?);
+    }
+    Ok(all)
+}
+
+fn set_property(
+    lua: &Lua,
+    path: &str,
+    interface: &str,
+    name: &str,
+    value: &Value,
+) -> Result<(), MethodError> {
+    let object = object_for(path, interface)?;
+    let property = object.properties.get(name).ok_or_else(|| {
+        MethodError(fdo::Error::UnknownProperty(format!(
+            "Unknown property '{}'",
+            name
+        )))
+    })?;
+    if !property.writable {
+        return Err(MethodError(fdo::Error::PropertyReadOnly(format!(
+            "Property '{}' is read-only",
+            name
+        ))));
+    }
+    let value = match value {
+        Value::Value(inner) => value_to_lua(lua, inner)?,
+        other => value_to_lua(lua, other)?,
+    };
+    if let Some(key) = &property.on_set {
+        let on_set: Function = lua.registry_value(key)?;
+        on_set.call::<()>(value.clone())?;
+    }
+    *property.value.borrow_mut() = lua_to_value(&value, &property.signature)?;
+    notify_property_changed(&object, name)?;
+    Ok(())
+}
+
+fn call_method(
+    lua: &Lua,
+    msg: &Message,
+    path: &str,
+    interface: &str,
+    member: &str,
+) -> Result<Vec<OwnedValue>, MethodError> {
+    let object = object_for(path, interface)?;
+    let method = object.methods.get(member).ok_or_else(|| {
+        MethodError(fdo::Error::UnknownMethod(format!(
+            "Unknown method '{}'",
+            member
+        )))
+    })?;
+
+    let body = msg.body();
+    if body.signature().to_string_no_parens() != method.args {
+        return Err(MethodError(fdo::Error::InvalidArgs(format!(
+            "Expected arguments of type '{}', got '{}'",
+            method.args,
+            body.signature().to_string_no_parens()
+        ))));
+    }
+    let args = body_values(&body)?
+        .iter()
+        .map(|v| value_to_lua(lua, v))
+        .collect::<LuaResult<MultiValue>>()?;
+
+    let handler: Function = lua.registry_value(&method.handler)?;
+    let returned: Vec<mlua::Value> = handler.call::<MultiValue>(args)?.into_iter().collect();
+
+    let mut values = Vec::new();
+    for (i, ty) in split_signature(&method.returns)?.iter().enumerate() {
+        let value = returned.get(i).cloned().unwrap_or(mlua::Value::Nil);
+        let value = lua_to_value(&value, ty)?.try_into_owned();
+        values.push(value.map_err(DbusClientError::from)?);
+    }
+    Ok(values)
+}
+
+fn handle_request(lua: &Lua, request: Request) {
+    // A closed receiver means the caller gave up; there is nobody to tell.
+    match request {
+        Request::Call {
+            msg,
+            path,
+            interface,
+            member,
+            reply,
+        } => {
+            let result = call_method(lua, &msg, &path, &interface, &member).map_err(|e| e.0);
+            let _ = reply.send(result);
+        }
+        Request::Get {
+            path,
+            interface,
+            name,
+            reply,
+        } => {
+            let _ = reply.send(get_property(&path, &interface, &name));
+        }
+        Request::GetAll {
+            path,
+            interface,
+            reply,
+        } => {
+            let _ = reply.send(get_all_properties(&path, &interface).map_err(|e| e.0));
+        }
+        Request::Set {
+            path,
+            interface,
+            name,
+            value,
+            reply,
+        } => {
+            if find_object(&path, &interface)
+                .is_some_and(|object| !object.properties.contains_key(&name))
+            {
+                let _ = reply.send(None);
+                return;
+            }
+            let result = set_property(lua, &path, &interface, &name, &Value::from(value));
+            let _ = reply.send(Some(result.map_err(|e| e.0)));
+        }
+    }
+}
+
+/// The channel that exported objects hand their calls to, answered on the
+/// main thread.
+fn requests(lua: &Lua) -> UnboundedSender<Request> {
+    REQUESTS.with_borrow_mut(|requests| {
+        requests
+            .get_or_insert_with(|| {
+                let (sender, mut receiver) = unbounded_channel();
+                let lua = lua.clone();
+                glib::MainContext::default().spawn_local(async move {
+                    while let Some(request) = receiver.recv().await {
+                        handle_request(&lua, request);
+                    }
+                });
+                sender
+            })
+            .clone()
+    })
+}
+
+fn slot_of(interface: &str) -> zbus::Result<usize> {
+    let name = InterfaceName::try_from(interface.to_string())?;
+    name_slot(&name).ok_or_else(|| {
+        zbus::Error::Failure(format!(
+            "Cannot export '{}', at most {} interface names can be exported",
+            interface, NAME_SLOTS
+        ))
+    })
+}
+
+async fn apply_server_op(op: ServerOp) -> zbus::Result<()> {
+    let conn = service_connection().await?;
+    let server = conn.object_server();
+    match op {
+        ServerOp::Add(iface) => {
+            let path = iface.path.clone();
+            with_slot!(slot_of(&iface.interface)?, {
+                server.at(path.as_str(), Exported::<SLOT>(iface)).await?
+            });
+        }
+        ServerOp::Remove { path, interface } => {
+            with_slot!(slot_of(&interface)?, {
+                server.remove::<Exported<SLOT>, _>(path.as_str()).await?
+            });
+        }
+    }
+    Ok(())
+}
+
+fn queue_server_op(op: ServerOp) {
+    SERVER_OPS.with_borrow_mut(|ops| ops.push_back(op));
+    if SERVER_BUSY.replace(true) {
+        return;
+    }
+    glib::MainContext::default().spawn_local(async {
+        while let Some(op) = SERVER_OPS.with_borrow_mut(|ops| ops.pop_front()) {
+            if let Err(e) = apply_server_op(op).await {
+                eprintln!("Failed to update exported DBus objects: {}", e);
+            }
+        }
+        SERVER_BUSY.set(false);
+    });
+}
+
+fn parse_object(
+    lua: &Lua,
+    path: String,
+    interface: String,
+    def: &Table,
+) -> LuaResult<ExportedObject> {
+    ObjectPath::try_from(path.as_str()).map_err(mlua::Error::external)?;
+    InterfaceName::try_from(interface.as_str()).map_err(mlua::Error::external)?;
+
+    let mut methods = HashMap::new();
+    if let Ok(table) = def.get::<Table>("methods") {
+        for pair in table.pairs::<String, mlua::Value>() {
+            let (name, value) = pair?;
+            MemberName::try_from(name.as_str()).map_err(mlua::Error::external)?;
+            let method = match value {
+                mlua::Value::Function(handler) => ExportedMethod {
+                    args: String::new(),
+                    returns: String::new(),
+                    handler: lua.create_registry_value(handler)?,
+                },
+                mlua::Value::Table(t) => {
+                    let args: Option<String> = t.get("args")?;
+                    let returns: Option<String> = t.get("returns")?;
+                    let args = args.unwrap_or_default();
+                    let returns = returns.unwrap_or_default();
+                    split_signature(&args)?;
+                    split_signature(&returns)?;
+                    ExportedMethod {
+                        args,
+                        returns,
+                        handler: lua.create_registry_value(t.get::<Function>("handler")?)?,
+                    }
+                }
+                other => {
+                    return Err(mlua::Error::runtime(format!(
+                        "Method '{}' must be a function or a table, got {}",
+                        name,
+                        other.type_name()
+                    )));
+                }
+            };
+            methods.insert(name, method);
+        }
+    }
+
+    let mut properties = HashMap::new();
+    if let Ok(table) = def.get::<Table>("properties") {
+        for pair in table.pairs::<String, Table>() {
+            let (name, t) = pair?;
+            MemberName::try_from(name.as_str()).map_err(mlua::Error::external)?;
+            let signature: String = t.get("signature")?;
+            let signature = Signature::from_str(&signature).map_err(DbusClientError::from)?;
+            let value = lua_to_value(&t.get::<mlua::Value>("value")?, &signature)?;
+            let on_set = t
+                .get::<Option<Function>>("on_set")?
+                .map(|f| lua.create_registry_value(f))
+                .transpose()?;
+            properties.insert(
+                name,
+                ExportedProperty {
+                    signature,
+                    value: RefCell::new(value),
+                    writable: t.get::<Option<bool>>("writable")?.unwrap_or(false),
+                    on_set,
+                },
+            );
+        }
+    }
+
+    let mut signals = HashMap::new();
+    if let Ok(table) = def.get::<Table>("signals") {
+        for pair in table.pairs::<String, String>() {
+            let (name, signature) = pair?;
+            MemberName::try_from(name.as_str()).map_err(mlua::Error::external)?;
+            split_signature(&signature)?;
+            signals.insert(name, signature);
+        }
+    }
+
+    Ok(ExportedObject {
+        path,
+        interface,
+        methods,
+        properties,
+        signals,
+    })
+}
+
+struct LuaExportedObject(Rc<ExportedObject>);
+
+impl UserData for LuaExportedObject {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("emit", |lua, this, (name, args): (String, MultiValue)| {
+            let signature = this
+                .0
+                .signals
+                .get(&name)
+                .ok_or_else(|| mlua::Error::runtime(format!("Unknown signal '{}'", name)))?;
+            let args = lua.create_sequence_from(args)?;
+            let body = build_body(signature, Some(&args))?;
+            spawn_emit(this.0.path.clone(), this.0.interface.clone(), name, body);
+            Ok(())
+        });
+        methods.add_method("set", |_, this, (name, value): (String, mlua::Value)| {
+            let property = this
+                .0
+                .properties
+                .get(&name)
+                .ok_or_else(|| mlua::Error::runtime(format!("Unknown property '{}'", name)))?;
+            *property.value.borrow_mut() = lua_to_value(&value, &property.signature)?;
+            notify_property_changed(&this.0, &name)?;
+            Ok(())
+        });
+        methods.add_method("get", |lua, this, name: String| {
+            match this.0.properties.get(&name) {
+                Some(property) => value_to_lua(lua, &property.value.borrow()),
+                None => Ok(mlua::Value::Nil),
+            }
+        });
+        methods.add_method("unexport", |_, this, ()| {
+            let exported = EXPORTS.with_borrow_mut(|exports| {
+                let before = exports.len();
+                exports.retain(|o| !Rc::ptr_eq(o, &this.0));
+                exports.len() != before
+            });
+            if exported {
+                queue_server_op(ServerOp::Remove {
+                    path: this.0.path.clone(),
+                    interface: this.0.interface.clone(),
+                });
+            }
+            Ok(())
+        });
+    }
+}
+
+pub fn register(lua: Rc<Lua>) -> LuaResult<()> {
+    let dbus: Table = lua.globals().get("DBus")?;
+
+    dbus.set(
+        "export",
+        lua.create_function(|lua, (path, interface, def): (String, String, Table)| {
+            if find_object(&path, &interface).is_some() {
+                return Err(mlua::Error::runtime(format!(
+                    "Interface '{}' is already exported at '{}'",
+                    interface, path
+                )));
+            }
+            let object = Rc::new(parse_object(lua, path, interface, &def)?);
+            EXPORTS.with_borrow_mut(|exports| exports.push(object.clone()));
+            queue_server_op(ServerOp::Add(LuaInterface {
+                path: object.path.clone(),
+                interface: object.interface.clone(),
+                methods: object.methods.keys().cloned().collect(),
+                members: introspect(&object),
+                requests: requests(lua),
+            }));
+            Ok(LuaExportedObject(object))
+        })?,
+    )?;
+
+    dbus.set(
+        "own_name",
+        lua.create_function(|lua, (name, callback): (String, Option<Function>)| {
+            WellKnownName::try_from(name.as_str()).map_err(mlua::Error::external)?;
+            let lua = lua.clone();
+            let cb_key = callback.map(|f| lua.create_registry_value(f)).transpose()?;
+            glib::MainContext::default().spawn_local(async move {
+                let result = match service_connection().await {
+                    Ok(conn) => conn.request_name(name.as_str()).await,
+                    Err(e) => Err(e),
+                };
+                match (cb_key, result) {
+                    (Some(key), result) => {
+                        if let Ok(func) = lua.registry_value::<Function>(&key) {
+                            let args = match result {
+                                Ok(()) => (true, None),
+                                Err(e) => (false, Some(e.to_string())),
+                            };
+                            if let Err(e) = func.call::<()>(args) {
+                                eprintln!("DBus callback error: {}", e);
+                            }
+                        }
+                    }
+                    (None, Err(e)) => eprintln!("Failed to own DBus name '{}': {}", name, e),
+                    (None, Ok(())) => {}
+                }
+            });
+            Ok(())
+        })?,
+    )?;
+
+    dbus.set(
+        "release_name",
+        lua.create_function(|_, name: String| {
+            WellKnownName::try_from(name.as_str()).map_err(mlua::Error::external)?;
+            glib::MainContext::default().spawn_local(async move {
+                let result = match service_connection().await {
+                    Ok(conn) => conn.release_name(name.as_str()).await.map(|_| ()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    eprintln!("Failed to release DBus name '{}': {}", name, e);
+                }
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
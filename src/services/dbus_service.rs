@@ -6,8 +6,9 @@ use std::sync::{
     Arc, Mutex,
     mpsc::{Sender, channel},
 };
+use tokio::sync::OnceCell;
 use zbus::zvariant::Value;
-use zbus::{connection, interface};
+use zbus::{Connection, connection, interface};
 
 use crate::core::error::AppError;
 
@@ -143,6 +144,17 @@ pub fn init(lua: Rc<Lua>) -> Result<(), AppError> {
     Ok(())
 }
 
+static SERVICE_CONNECTION: OnceCell<Connection> = OnceCell::const_new();
+
+/// The session connection that serves Ink's objects: the notification and
+/// tray servers, and objects exported from Lua.
+pub async fn service_connection() -> zbus::Result<Connection> {
+    SERVICE_CONNECTION
+        .get_or_try_init(|| async { connection::Builder::session()?.build().await })
+        .await
+        .cloned()
+}
+
 async fn run_server(sender: Sender<DbusUpdate>) -> Result<(), Box<dyn Error>> {
     let notif_server = NotificationServer {
         sender: sender.clone(),
@@ -152,11 +164,12 @@ async fn run_server(sender: Sender<DbusUpdate>) -> Result<(), Box<dyn Error>> {
         items: Arc::new(Mutex::new(Vec::new())),
     };
 
-    let conn = connection::Builder::session()?
-        .serve_at("/org/freedesktop/Notifications", notif_server)?
-        .serve_at("/StatusNotifierWatcher", tray_server)?
-        .build()
+    let conn = service_connection().await?;
+    let server = conn.object_server();
+    server
+        .at("/org/freedesktop/Notifications", notif_server)
         .await?;
+    server.at("/StatusNotifierWatcher", tray_server).await?;
 
     conn.request_name("org.freedesktop.Notifications").await?;
     conn.request_name("org.kde.StatusNotifierWatcher").await?;
@@ -1,3 +1,4 @@
+use crate::ui::lifecycle;
 use gio::prelude::*;
 use gio::{File, FileMonitor, FileMonitorEvent};
 use mlua::{Function, Lua, Result, UserData, UserDataMethods};
@@ -6,6 +7,7 @@ use std::rc::Rc;
 
 struct FileWatcher {
     monitor: FileMonitor,
+    cleanup: lifecycle::CleanupSlot,
 }
 
 impl Drop for FileWatcher {
@@ -17,6 +19,7 @@ impl Drop for FileWatcher {
 impl UserData for FileWatcher {
     fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
         methods.add_method("disconnect", |_, this, ()| {
+            this.cleanup.remove();
             this.monitor.cancel();
             Ok(())
         });
@@ -82,7 +85,12 @@ pub fn register(lua: Rc<Lua>) -> Result<()> {
                     }
                 });
 
-                Ok(FileWatcher { monitor })
+                let tracked = monitor.clone();
+                let cleanup = lifecycle::CleanupSlot::default();
+                cleanup.set(lifecycle::track(move || {
+                    tracked.cancel();
+                }));
+                Ok(FileWatcher { monitor, cleanup })
             }
         })?,
     )?;
@@ -0,0 +1,449 @@
+use super::http_stream::{self, LuaHttpStream, StreamFormat};
+use gtk4::glib;
+use mlua::{
+    Function, Lua, LuaSerdeExt, Result as LuaResult, Table, UserData, UserDataFields,
+    UserDataMethods, Value,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use reqwest::{Client, ClientBuilder, Method, RequestBuilder, redirect};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum HttpError {
+    RequestError(reqwest::Error),
+    JsonError(serde_json::Error),
+    InvalidMethod(String),
+    InvalidHeader(String),
+    InvalidFormat(String),
+    MissingUrl,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::RequestError(e) => write!(f, "Request error: {}", e),
+            HttpError::JsonError(e) => write!(f, "JSON error: {}", e),
+            HttpError::InvalidMethod(m) => write!(f, "Invalid HTTP method '{}'", m),
+            HttpError::InvalidHeader(h) => write!(f, "Invalid header '{}'", h),
+            HttpError::InvalidFormat(format) => write!(
+                f,
+                "Invalid stream format '{}', expected chunks, lines, ndjson or sse",
+                format
+            ),
+            HttpError::MissingUrl => write!(f, "Request has no url"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(err: reqwest::Error) -> Self {
+        HttpError::RequestError(err)
+    }
+}
+
+impl From<serde_json::Error> for HttpError {
+    fn from(err: serde_json::Error) -> Self {
+        HttpError::JsonError(err)
+    }
+}
+
+impl From<HttpError> for mlua::Error {
+    fn from(err: HttpError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+/// Methods exposed as shortcuts on `Http` and on every client.
+const METHODS: &[&str] = &["get", "post", "put", "patch", "delete", "head"];
+
+/// The client used by `Http.*`, `fetch` and `fetch_async`, so that all of
+/// them share one connection pool.
+pub fn shared_client() -> Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new).clone()
+}
+
+pub fn parse_method(method: &str) -> Result<Method, HttpError> {
+    Method::from_bytes(method.to_uppercase().as_bytes())
+        .map_err(|_| HttpError::InvalidMethod(method.to_string()))
+}
+
+fn lua_to_string(value: Value) -> LuaResult<String> {
+    match value {
+        Value::String(s) => Ok(s.to_str()?.to_string()),
+        other => Ok(other.to_string()?),
+    }
+}
+
+fn header_map(headers: &Table) -> LuaResult<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for pair in headers.pairs::<String, Value>() {
+        let (name, value) = pair?;
+        let header = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| HttpError::InvalidHeader(name.clone()))?;
+        let value = HeaderValue::from_str(&lua_to_string(value)?)
+            .map_err(|_| HttpError::InvalidHeader(name))?;
+        map.insert(header, value);
+    }
+    Ok(map)
+}
+
+fn pairs(table: &Table) -> LuaResult<Vec<(String, String)>> {
+    table
+        .pairs::<String, Value>()
+        .map(|pair| {
+            let (key, value) = pair?;
+            Ok((key, lua_to_string(value)?))
+        })
+        .collect()
+}
+
+/// How a client follows redirects. Like the other transport options, this is
+/// fixed when a `reqwest::Client` is built.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+enum Redirects {
+    #[default]
+    Default,
+    Disabled,
+    Limited(usize),
+}
+
+/// Options that can't be set per request, so clients that use them are built
+/// once per distinct combination and then shared.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct Transport {
+    connect_timeout: Option<u64>,
+    redirects: Redirects,
+}
+
+impl Transport {
+    fn client(self) -> Result<Client, HttpError> {
+        static CLIENTS: OnceLock<Mutex<HashMap<Transport, Client>>> = OnceLock::new();
+        if self == Transport::default() {
+            return Ok(shared_client());
+        }
+        let mut clients = CLIENTS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(client) = clients.get(&self) {
+            return Ok(client.clone());
+        }
+        let client = self.builder().build()?;
+        clients.insert(self, client.clone());
+        Ok(client)
+    }
+
+    fn builder(self) -> ClientBuilder {
+        let mut builder = Client::builder();
+        if let Some(ms) = self.connect_timeout {
+            builder = builder.connect_timeout(Duration::from_millis(ms));
+        }
+        match self.redirects {
+            Redirects::Default => builder,
+            Redirects::Disabled => builder.redirect(redirect::Policy::none()),
+            Redirects::Limited(n) => builder.redirect(redirect::Policy::limited(n)),
+        }
+    }
+}
+
+/// A client with its own defaults. Requests go through the shared connection
+/// pool unless the client needs different transport options or a cookie
+/// store.
+#[derive(Clone)]
+pub struct LuaHttpClient {
+    client: Client,
+    base_url: Option<String>,
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+}
+
+impl LuaHttpClient {
+    pub fn shared() -> Self {
+        Self {
+            client: shared_client(),
+            base_url: None,
+            headers: HeaderMap::new(),
+            timeout: None,
+        }
+    }
+
+    /// Builds a client from `{ base_url, headers, timeout, connect_timeout,
+    /// redirects, cookies, user_agent }`.
+    fn from_config(config: Option<Table>) -> LuaResult<Self> {
+        let Some(config) = config else {
+            return Ok(Self::shared());
+        };
+        let transport = Transport {
+            connect_timeout: config.get("connect_timeout")?,
+            redirects: match config.get::<Value>("redirects")? {
+                Value::Boolean(false) => Redirects::Disabled,
+                Value::Integer(n) => Redirects::Limited(n.max(0) as usize),
+                _ => Redirects::Default,
+            },
+        };
+        // A cookie store belongs to a single client, so it can't be shared.
+        let client = if config.get::<Option<bool>>("cookies")?.unwrap_or(false) {
+            transport
+                .builder()
+                .cookie_store(true)
+                .build()
+                .map_err(HttpError::from)?
+        } else {
+            transport.client()?
+        };
+
+        let mut headers = match config.get::<Option<Table>>("headers")? {
+            Some(headers) => header_map(&headers)?,
+            None => HeaderMap::new(),
+        };
+        if let Some(agent) = config.get::<Option<String>>("user_agent")? {
+            let value = HeaderValue::from_str(&agent)
+                .map_err(|_| HttpError::InvalidHeader(USER_AGENT.to_string()))?;
+            headers.insert(USER_AGENT, value);
+        }
+        Ok(Self {
+            client,
+            base_url: config.get("base_url")?,
+            headers,
+            timeout: config
+                .get::<Option<u64>>("timeout")?
+                .map(Duration::from_millis),
+        })
+    }
+
+    fn resolve(&self, url: &str) -> String {
+        match &self.base_url {
+            Some(base) if !url.contains("://") => format!(
+                "{}/{}",
+                base.trim_end_matches('/'),
+                url.trim_start_matches('/')
+            ),
+            _ => url.to_string(),
+        }
+    }
+
+    /// Builds a request from `{ method, url, headers, query, body, json,
+    /// form, timeout, bearer, basic }`.
+    pub fn build(&self, opts: &Table) -> LuaResult<RequestBuilder> {
+        let method = parse_method(
+            &opts
+                .get::<Option<String>>("method")?
+                .unwrap_or_else(|| "GET".to_string()),
+        )?;
+        let url = opts
+            .get::<Option<String>>("url")?
+            .ok_or(HttpError::MissingUrl)?;
+        let mut request = self
+            .client
+            .request(method, self.resolve(&url))
+            .headers(self.headers.clone());
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+
+        if let Some(headers) = opts.get::<Option<Table>>("headers")? {
+            request = request.headers(header_map(&headers)?);
+        }
+        if let Some(query) = opts.get::<Option<Table>>("query")? {
+            request = request.query(&pairs(&query)?);
+        }
+        if let Some(ms) = opts.get::<Option<u64>>("timeout")? {
+            request = request.timeout(Duration::from_millis(ms));
+        }
+        if let Some(token) = opts.get::<Option<String>>("bearer")? {
+            request = request.bearer_auth(token);
+        }
+        if let Some(basic) = opts.get::<Option<Table>>("basic")? {
+            let user: String = basic.get("user")?;
+            request = request.basic_auth(user, basic.get::<Option<String>>("password")?);
+        }
+
+        match opts.get::<Value>("json")? {
+            Value::Nil => {}
+            value => {
+                let body = serde_json::to_vec(&value).map_err(HttpError::from)?;
+                request = request
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body);
+            }
+        }
+        if let Some(form) = opts.get::<Option<Table>>("form")? {
+            request = request.form(&pairs(&form)?);
+        }
+        if let Some(body) = opts.get::<Option<mlua::String>>("body")? {
+            request = request.body(body.as_bytes().to_vec());
+        }
+        Ok(request)
+    }
+
+    fn dispatch(&self, opts: &Table, callback: Function) -> LuaResult<()> {
+        let request = self.build(opts)?;
+        glib::MainContext::default().spawn_local(async move {
+            let result = match LuaResponse::receive(request).await {
+                Ok(response) => callback.call::<()>((response, Value::Nil)),
+                Err(e) => callback.call::<()>((Value::Nil, e.to_string())),
+            };
+            if let Err(e) = result {
+                eprintln!("Http callback error: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    fn stream(
+        &self,
+        lua: &Lua,
+        (opts, on_data, on_done): (Table, Function, Option<Function>),
+    ) -> LuaResult<LuaHttpStream> {
+        let format = opts
+            .get::<Option<String>>("format")?
+            .map(|f| StreamFormat::parse(&f))
+            .transpose()?;
+        let request = self.build(&opts)?;
+        Ok(http_stream::start(lua, request, format, on_data, on_done))
+    }
+
+    /// Handles `(url, opts, callback)` where `opts` may be omitted.
+    fn shortcut(
+        &self,
+        lua: &Lua,
+        method: &str,
+        (url, opts, callback): (String, Value, Value),
+    ) -> LuaResult<()> {
+        let (opts, callback) = match (opts, callback) {
+            (Value::Function(callback), _) => (lua.create_table()?, callback),
+            (Value::Table(opts), Value::Function(callback)) => (opts, callback),
+            (Value::Nil, Value::Function(callback)) => (lua.create_table()?, callback),
+            _ => {
+                return Err(mlua::Error::runtime(format!(
+                    "Http.{} expects (url, [opts], callback)",
+                    method
+                )));
+            }
+        };
+        opts.set("method", method)?;
+        opts.set("url", url)?;
+        self.dispatch(&opts, callback)
+    }
+}
+
+impl UserData for LuaHttpClient {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("request", |_, this, (opts, callback): (Table, Function)| {
+            this.dispatch(&opts, callback)
+        });
+        methods.add_method("stream", |lua, this, args| this.stream(lua, args));
+        for method in METHODS {
+            methods.add_method(*method, move |lua, this, args: (String, Value, Value)| {
+                this.shortcut(lua, method, args)
+            });
+        }
+    }
+}
+
+pub struct LuaResponse {
+    status: reqwest::StatusCode,
+    url: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl LuaResponse {
+    /// The status and headers of `response`, without its body.
+    pub fn head(response: &reqwest::Response) -> Self {
+        Self {
+            status: response.status(),
+            url: response.url().to_string(),
+            headers: response.headers().clone(),
+            body: Vec::new(),
+        }
+    }
+
+    async fn receive(request: RequestBuilder) -> Result<Self, HttpError> {
+        let response = request.send().await?;
+        let head = Self::head(&response);
+        Ok(Self {
+            body: response.bytes().await?.to_vec(),
+            ..head
+        })
+    }
+}
+
+/// Converts response headers to a table of lowercase names, joining repeated
+/// headers with ", ".
+pub fn headers_table(lua: &Lua, headers: &HeaderMap) -> LuaResult<Table> {
+    let table = lua.create_table()?;
+    for name in headers.keys() {
+        let values: Vec<String> = headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+            .collect();
+        table.set(name.as_str(), values.join(", "))?;
+    }
+    Ok(table)
+}
+
+impl UserData for LuaResponse {
+    fn add_fields<F: UserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("status", |_, this| Ok(this.status.as_u16()));
+        fields.add_field_method_get("ok", |_, this| Ok(this.status.is_success()));
+        fields.add_field_method_get("reason", |_, this| {
+            Ok(this.status.canonical_reason().unwrap_or_default())
+        });
+        fields.add_field_method_get("url", |_, this| Ok(this.url.clone()));
+        fields.add_field_method_get("headers", |lua, this| headers_table(lua, &this.headers));
+        fields.add_field_method_get("body", |lua, this| lua.create_string(&this.body));
+    }
+
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("text", |_, this, ()| {
+            Ok(String::from_utf8_lossy(&this.body).into_owned())
+        });
+        methods.add_method("json", |lua, this, ()| {
+            let value: serde_json::Value =
+                serde_json::from_slice(&this.body).map_err(HttpError::from)?;
+            lua.to_value(&value)
+        });
+        methods.add_method("header", |_, this, name: String| {
+            Ok(this
+                .headers
+                .get(name.as_str())
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned()))
+        });
+    }
+}
+
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    let http = lua.create_table()?;
+    http.set(
+        "client",
+        lua.create_function(|_, config: Option<Table>| LuaHttpClient::from_config(config))?,
+    )?;
+    http.set(
+        "request",
+        lua.create_function(|_, (opts, callback): (Table, Function)| {
+            LuaHttpClient::shared().dispatch(&opts, callback)
+        })?,
+    )?;
+    http.set(
+        "stream",
+        lua.create_function(|lua, args| LuaHttpClient::shared().stream(lua, args))?,
+    )?;
+    for method in METHODS {
+        http.set(
+            *method,
+            lua.create_function(move |lua, args: (String, Value, Value)| {
+                LuaHttpClient::shared().shortcut(lua, method, args)
+            })?,
+        )?;
+    }
+    lua.globals().set("Http", http)?;
+    Ok(())
+}
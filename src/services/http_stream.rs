@@ -0,0 +1,269 @@
+use super::http::{HttpError, LuaResponse};
+use crate::ui::lifecycle;
+use gtk4::glib;
+use mlua::{Function, Lua, LuaSerdeExt, Result as LuaResult, UserData, UserDataMethods, Value};
+use reqwest::RequestBuilder;
+use reqwest::header::CONTENT_TYPE;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How a streamed body is split before it is handed to Lua.
+#[derive(Clone, Copy)]
+pub enum StreamFormat {
+    Chunks,
+    Lines,
+    Ndjson,
+    Sse,
+}
+
+impl StreamFormat {
+    pub fn parse(name: &str) -> Result<Self, HttpError> {
+        match name {
+            "chunks" => Ok(Self::Chunks),
+            "lines" => Ok(Self::Lines),
+            "ndjson" => Ok(Self::Ndjson),
+            "sse" => Ok(Self::Sse),
+            other => Err(HttpError::InvalidFormat(other.to_string())),
+        }
+    }
+
+    fn from_content_type(content_type: &str) -> Self {
+        if content_type.starts_with("text/event-stream") {
+            Self::Sse
+        } else if content_type.starts_with("application/x-ndjson")
+            || content_type.starts_with("application/jsonl")
+        {
+            Self::Ndjson
+        } else {
+            Self::Chunks
+        }
+    }
+}
+
+/// Collects bytes until a full line is available. Lines may end with `\n`
+/// or `\r\n`.
+#[derive(Default)]
+struct LineBuffer(Vec<u8>);
+
+impl LineBuffer {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.0.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(end) = self.0.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.0.drain(..=end).collect();
+            lines.push(Self::decode(&line));
+        }
+        lines
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        (!self.0.is_empty()).then(|| Self::decode(&std::mem::take(&mut self.0)))
+    }
+
+    fn decode(line: &[u8]) -> String {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        String::from_utf8_lossy(line).into_owned()
+    }
+}
+
+/// The fields of the server-sent event being read.
+#[derive(Default)]
+struct SseEvent {
+    event: Option<String>,
+    data: Vec<String>,
+    id: Option<String>,
+}
+
+impl SseEvent {
+    /// Applies one line of the event stream, returning the event once a
+    /// blank line completes it.
+    fn feed(&mut self, lua: &Lua, line: &str) -> LuaResult<Option<Value>> {
+        if line.is_empty() {
+            return self.dispatch(lua);
+        }
+        if line.starts_with(':') {
+            return Ok(None);
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            "id" => self.id = Some(value.to_string()),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn dispatch(&mut self, lua: &Lua) -> LuaResult<Option<Value>> {
+        let event = std::mem::take(self);
+        if event.data.is_empty() {
+            return Ok(None);
+        }
+        let table = lua.create_table()?;
+        table.set("event", event.event.as_deref().unwrap_or("message"))?;
+        table.set("data", event.data.join("\n"))?;
+        table.set("id", event.id)?;
+        Ok(Some(Value::Table(table)))
+    }
+}
+
+enum Decoder {
+    Chunks,
+    Lines(LineBuffer),
+    Ndjson(LineBuffer),
+    Sse(LineBuffer, SseEvent),
+}
+
+impl Decoder {
+    fn new(format: StreamFormat) -> Self {
+        match format {
+            StreamFormat::Chunks => Self::Chunks,
+            StreamFormat::Lines => Self::Lines(LineBuffer::default()),
+            StreamFormat::Ndjson => Self::Ndjson(LineBuffer::default()),
+            StreamFormat::Sse => Self::Sse(LineBuffer::default(), SseEvent::default()),
+        }
+    }
+
+    /// Decodes `chunk` into the values passed to the data callback. An empty
+    /// chunk marks the end of the body and flushes any partial line.
+    fn feed(&mut self, lua: &Lua, chunk: &[u8]) -> LuaResult<Vec<Value>> {
+        let done = chunk.is_empty();
+        let lines = |buffer: &mut LineBuffer| {
+            let mut lines = buffer.push(chunk);
+            if done {
+                lines.extend(buffer.finish());
+            }
+            lines
+        };
+        let mut values = Vec::new();
+        match self {
+            Self::Chunks if !done => values.push(Value::String(lua.create_string(chunk)?)),
+            Self::Chunks => {}
+            Self::Lines(buffer) => {
+                for line in lines(buffer) {
+                    values.push(Value::String(lua.create_string(line)?));
+                }
+            }
+            Self::Ndjson(buffer) => {
+                for line in lines(buffer) {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let json: serde_json::Value =
+                        serde_json::from_str(&line).map_err(HttpError::from)?;
+                    values.push(lua.to_value(&json)?);
+                }
+            }
+            Self::Sse(buffer, event) => {
+                for line in lines(buffer) {
+                    values.extend(event.feed(lua, &line)?);
+                }
+                if done {
+                    values.extend(event.dispatch(lua)?);
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// A streaming request. Cancelling it stops the callbacks and closes the
+/// connection.
+#[derive(Clone)]
+pub struct LuaHttpStream(
+    Rc<RefCell<Option<glib::JoinHandle<()>>>>,
+    lifecycle::CleanupSlot,
+);
+
+impl LuaHttpStream {
+    fn cancel(&self) {
+        self.1.remove();
+        if let Some(task) = self.0.borrow_mut().take() {
+            task.abort();
+        }
+    }
+}
+
+impl UserData for LuaHttpStream {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("cancel", |_, this, ()| {
+            this.cancel();
+            Ok(())
+        });
+        methods.add_method("is_active", |_, this, ()| Ok(this.0.borrow().is_some()));
+    }
+}
+
+async fn read(
+    lua: &Lua,
+    request: RequestBuilder,
+    format: Option<StreamFormat>,
+    on_data: &Function,
+) -> (Option<LuaResponse>, Option<String>) {
+    let mut response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return (None, Some(HttpError::from(e).to_string())),
+    };
+    let head = LuaResponse::head(&response);
+    let format = format.unwrap_or_else(|| {
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        StreamFormat::from_content_type(content_type)
+    });
+    let mut decoder = Decoder::new(format);
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(chunk) => chunk.unwrap_or_default(),
+            Err(e) => return (Some(head), Some(HttpError::from(e).to_string())),
+        };
+        let values = match decoder.feed(lua, &chunk) {
+            Ok(values) => values,
+            Err(e) => return (Some(head), Some(e.to_string())),
+        };
+        for value in values {
+            if let Err(e) = on_data.call::<()>(value) {
+                eprintln!("Http stream callback error: {}", e);
+            }
+        }
+        if chunk.is_empty() {
+            return (Some(head), None);
+        }
+    }
+}
+
+/// Sends `request` and calls `on_data` for each decoded piece of the body,
+/// then `on_done(response, err)`.
+pub fn start(
+    lua: &Lua,
+    request: RequestBuilder,
+    format: Option<StreamFormat>,
+    on_data: Function,
+    on_done: Option<Function>,
+) -> LuaHttpStream {
+    let stream = LuaHttpStream(Rc::new(RefCell::new(None)), Default::default());
+    let task = glib::MainContext::default().spawn_local({
+        let lua = lua.clone();
+        let slot = stream.clone();
+        async move {
+            let (response, err) = read(&lua, request, format, &on_data).await;
+            slot.0.borrow_mut().take();
+            slot.1.remove();
+            if let Some(on_done) = on_done
+                && let Err(e) = on_done.call::<()>((response, err))
+            {
+                eprintln!("Http stream callback error: {}", e);
+            }
+        }
+    });
+    *stream.0.borrow_mut() = Some(task);
+    let tracked = stream.clone();
+    stream.1.set(lifecycle::track(move || tracked.cancel()));
+    stream
+}
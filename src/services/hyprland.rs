@@ -0,0 +1,421 @@
+use crate::ui::lifecycle;
+use gtk4::glib;
+use mlua::{Function, Lua, LuaSerdeExt, Result as LuaResult, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+#[derive(Debug)]
+pub enum HyprlandError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+    NotRunning,
+    RequestFailed(String),
+}
+
+impl fmt::Display for HyprlandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HyprlandError::IoError(e) => write!(f, "Hyprland IPC error: {}", e),
+            HyprlandError::JsonError(e) => write!(f, "Invalid Hyprland reply: {}", e),
+            HyprlandError::NotRunning => write!(f, "Hyprland is not running"),
+            HyprlandError::RequestFailed(e) => write!(f, "Hyprland request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HyprlandError {}
+
+impl From<io::Error> for HyprlandError {
+    fn from(err: io::Error) -> Self {
+        HyprlandError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for HyprlandError {
+    fn from(err: serde_json::Error) -> Self {
+        HyprlandError::JsonError(err)
+    }
+}
+
+impl From<HyprlandError> for mlua::Error {
+    fn from(err: HyprlandError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+/// The path of one of the instance's sockets: `.socket.sock` for requests or
+/// `.socket2.sock` for events. Older releases kept them under `/tmp/hypr`.
+fn socket_path(name: &str) -> Result<PathBuf, HyprlandError> {
+    let signature =
+        env::var("HYPRLAND_INSTANCE_SIGNATURE").map_err(|_| HyprlandError::NotRunning)?;
+    let runtime = env::var("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("hypr").join(&signature).join(name))
+        .ok()
+        .filter(|path| path.exists());
+    Ok(runtime.unwrap_or_else(|| PathBuf::from("/tmp/hypr").join(signature).join(name)))
+}
+
+pub fn is_running() -> bool {
+    socket_path(".socket.sock").is_ok_and(|path| path.exists())
+}
+
+/// Sends one request, such as `j/workspaces` or `dispatch workspace 2`, and
+/// returns the reply.
+pub async fn request(command: &str) -> Result<String, HyprlandError> {
+    request_at(&socket_path(".socket.sock")?, command).await
+}
+
+/// Sends one request to the request socket at `path`.
+pub async fn request_at(path: &Path, command: &str) -> Result<String, HyprlandError> {
+    let mut stream = UnixStream::connect(path).await?;
+    stream.write_all(command.as_bytes()).await?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).await?;
+    Ok(reply)
+}
+
+/// Runs a dispatcher, failing with Hyprland's message unless it replies `ok`.
+pub async fn dispatch(dispatcher: &str, args: &str) -> Result<(), HyprlandError> {
+    dispatch_at(&socket_path(".socket.sock")?, dispatcher, args).await
+}
+
+/// Runs a dispatcher through the request socket at `path`.
+pub async fn dispatch_at(path: &Path, dispatcher: &str, args: &str) -> Result<(), HyprlandError> {
+    let reply = request_at(path, &format!("dispatch {} {}", dispatcher, args)).await?;
+    match reply.trim() {
+        "ok" => Ok(()),
+        other => Err(HyprlandError::RequestFailed(other.to_string())),
+    }
+}
+
+/// The number of comma-separated fields of each event, so that the last
+/// field keeps any commas it contains (e.g. window titles).
+fn event_arity(event: &str) -> usize {
+    match event {
+        "workspacev2" | "focusedmon" | "focusedmonv2" | "activewindow" | "movewindow"
+        | "windowtitlev2" | "activelayout" | "renameworkspace" | "createworkspacev2"
+        | "destroyworkspacev2" | "moveworkspace" | "activespecial" | "changefloatingmode"
+        | "minimized" | "pin" | "togglegroup" | "screencast" => 2,
+        "movewindowv2" | "moveworkspacev2" | "monitoraddedv2" | "monitorremovedv2"
+        | "activespecialv2" => 3,
+        "openwindow" => 4,
+        _ => 1,
+    }
+}
+
+/// Splits a `name>>data` line from the event socket.
+pub fn parse_event(line: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, data) = line.split_once(">>")?;
+    Some((name, data.splitn(event_arity(name), ',').collect()))
+}
+
+/// Calls `on_event(name, args)` for every event until the returned task is
+/// aborted or the connection closes, in which case `on_close` is called.
+pub fn subscribe(
+    on_event: impl Fn(&str, Vec<&str>) + 'static,
+    on_close: impl FnOnce() + 'static,
+) -> Result<glib::JoinHandle<()>, HyprlandError> {
+    Ok(subscribe_at(
+        socket_path(".socket2.sock")?,
+        on_event,
+        on_close,
+    ))
+}
+
+/// Reads events from the event socket at `path`.
+pub fn subscribe_at(
+    path: PathBuf,
+    on_event: impl Fn(&str, Vec<&str>) + 'static,
+    on_close: impl FnOnce() + 'static,
+) -> glib::JoinHandle<()> {
+    glib::MainContext::default().spawn_local(async move {
+        match UnixStream::connect(&path).await {
+            Ok(stream) => {
+                let mut lines = BufReader::new(stream).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Some((name, args)) = parse_event(&line) {
+                        on_event(name, args);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Hyprland event socket error: {}", e),
+        }
+        on_close();
+    })
+}
+
+#[derive(Clone)]
+struct HyprlandSubscription(
+    Rc<RefCell<Option<glib::JoinHandle<()>>>>,
+    lifecycle::CleanupSlot,
+);
+
+impl HyprlandSubscription {
+    fn disconnect(&self) {
+        self.1.remove();
+        if let Some(task) = self.0.borrow_mut().take() {
+            task.abort();
+        }
+    }
+}
+
+impl UserData for HyprlandSubscription {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("disconnect", |_, this, ()| {
+            this.disconnect();
+            Ok(())
+        });
+    }
+}
+
+/// Runs `command` and calls `callback(result, err)`, decoding JSON replies.
+fn query(lua: &Lua, command: String, callback: Function) {
+    let lua = lua.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let result = match request(&command).await {
+            Ok(reply) => match serde_json::from_str::<serde_json::Value>(&reply) {
+                Ok(json) => lua.to_value(&json),
+                Err(e) if command.starts_with("j/") => Err(HyprlandError::from(e).into()),
+                Err(_) => lua.create_string(reply.trim_end()).map(Value::String),
+            },
+            Err(e) => Err(e.into()),
+        };
+        let called = match result {
+            Ok(value) => callback.call::<()>((value, Value::Nil)),
+            Err(e) => callback.call::<()>((Value::Nil, e.to_string())),
+        };
+        if let Err(e) = called {
+            eprintln!("Hyprland callback error: {}", e);
+        }
+    });
+}
+
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    let hyprland = lua.create_table()?;
+
+    hyprland.set(
+        "request",
+        lua.create_function(|lua, (command, callback): (String, Function)| {
+            query(lua, command, callback);
+            Ok(())
+        })?,
+    )?;
+
+    for (name, command) in [
+        ("workspaces", "j/workspaces"),
+        ("active_workspace", "j/activeworkspace"),
+        ("clients", "j/clients"),
+        ("active_window", "j/activewindow"),
+        ("monitors", "j/monitors"),
+        ("layers", "j/layers"),
+        ("devices", "j/devices"),
+    ] {
+        hyprland.set(
+            name,
+            lua.create_function(move |lua, callback: Function| {
+                query(lua, command.to_string(), callback);
+                Ok(())
+            })?,
+        )?;
+    }
+
+    hyprland.set(
+        "dispatch",
+        lua.create_function(
+            |_, (dispatcher, args, callback): (String, Option<String>, Option<Function>)| {
+                glib::MainContext::default().spawn_local(async move {
+                    let result = dispatch(&dispatcher, args.as_deref().unwrap_or("")).await;
+                    let Some(callback) = callback else {
+                        if let Err(e) = result {
+                            eprintln!("Hyprland dispatch error: {}", e);
+                        }
+                        return;
+                    };
+                    let called = match result {
+                        Ok(()) => callback.call::<()>((true, Value::Nil)),
+                        Err(e) => callback.call::<()>((false, e.to_string())),
+                    };
+                    if let Err(e) = called {
+                        eprintln!("Hyprland callback error: {}", e);
+                    }
+                });
+                Ok(())
+            },
+        )?,
+    )?;
+
+    hyprland.set(
+        "subscribe",
+        lua.create_function(|_, callback: Function| {
+            let cleanup = lifecycle::CleanupSlot::default();
+            let closed = cleanup.clone();
+            let task = subscribe(
+                move |name, args| {
+                    if let Err(e) = callback.call::<()>((name, args)) {
+                        eprintln!("Hyprland event callback error: {}", e);
+                    }
+                },
+                move || closed.remove(),
+            )?;
+            let subscription = HyprlandSubscription(Rc::new(RefCell::new(Some(task))), cleanup);
+            let tracked = subscription.clone();
+            subscription
+                .1
+                .set(lifecycle::track(move || tracked.disconnect()));
+            Ok(subscription)
+        })?,
+    )?;
+
+    hyprland.set("is_running", lua.create_function(|_, ()| Ok(is_running()))?)?;
+
+    lua.globals().set("Hyprland", hyprland)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dispatch_at, parse_event, request_at, subscribe_at};
+    use gtk4::glib;
+    use std::cell::RefCell;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+
+    /// A fresh directory to hold the fake sockets of one test.
+    fn socket_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ink-hypr-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Serves one connection on a fake `.socket.sock`, answering `reply` and
+    /// returning the request it received.
+    fn serve_request(path: &PathBuf, reply: &'static str) -> tokio::task::JoinHandle<String> {
+        let listener = UnixListener::bind(path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        })
+    }
+
+    #[test]
+    fn splits_fields_by_event_arity() {
+        assert_eq!(
+            parse_event("monitoraddedv2>>1,DP-1,Dell Inc. U2720Q"),
+            Some(("monitoraddedv2", vec!["1", "DP-1", "Dell Inc. U2720Q"]))
+        );
+        assert_eq!(
+            parse_event("activespecialv2>>-98,special:term,DP-1"),
+            Some(("activespecialv2", vec!["-98", "special:term", "DP-1"]))
+        );
+        assert_eq!(
+            parse_event("screencast>>1,0"),
+            Some(("screencast", vec!["1", "0"]))
+        );
+    }
+
+    #[test]
+    fn last_field_keeps_commas() {
+        assert_eq!(
+            parse_event("openwindow>>80e62df0,2,kitty,vim a.rs, b.rs"),
+            Some((
+                "openwindow",
+                vec!["80e62df0", "2", "kitty", "vim a.rs, b.rs"]
+            ))
+        );
+        assert_eq!(
+            parse_event("windowtitle>>80e62df0"),
+            Some(("windowtitle", vec!["80e62df0"]))
+        );
+    }
+
+    #[test]
+    fn rejects_lines_without_separator() {
+        assert_eq!(parse_event("garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn request_returns_reply() {
+        let path = socket_dir("request").join(".socket.sock");
+        let server = serve_request(&path, "[{\"id\":1}]");
+        let reply = request_at(&path, "j/workspaces").await.unwrap();
+        assert_eq!(reply, "[{\"id\":1}]");
+        assert_eq!(server.await.unwrap(), "j/workspaces");
+    }
+
+    #[tokio::test]
+    async fn dispatch_checks_reply() {
+        let dir = socket_dir("dispatch");
+        let path = dir.join(".socket.sock");
+        let server = serve_request(&path, "ok");
+        dispatch_at(&path, "workspace", "2").await.unwrap();
+        assert_eq!(server.await.unwrap(), "dispatch workspace 2");
+
+        let path = dir.join(".socket-failing.sock");
+        let server = serve_request(&path, "Invalid dispatcher");
+        let err = dispatch_at(&path, "nope", "").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Hyprland request failed: Invalid dispatcher"
+        );
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn subscribe_reads_events_until_closed() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let path = socket_dir("subscribe").join(".socket2.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        runtime.spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"workspacev2>>2,2\nopenwindow>>80e62df0,2,kitty,a, b\n")
+                .await
+                .unwrap();
+        });
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let closed = Rc::new(RefCell::new(false));
+        let (seen, ended) = (events.clone(), closed.clone());
+        let context = glib::MainContext::default();
+        context
+            .block_on(subscribe_at(
+                path,
+                move |name, args| {
+                    let args: Vec<String> = args.into_iter().map(str::to_string).collect();
+                    seen.borrow_mut().push((name.to_string(), args));
+                },
+                move || *ended.borrow_mut() = true,
+            ))
+            .unwrap();
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                (
+                    "workspacev2".to_string(),
+                    vec!["2".to_string(), "2".to_string()]
+                ),
+                (
+                    "openwindow".to_string(),
+                    ["80e62df0", "2", "kitty", "a, b"]
+                        .map(str::to_string)
+                        .to_vec()
+                ),
+            ]
+        );
+        assert!(*closed.borrow());
+    }
+}
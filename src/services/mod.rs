@@ -1,22 +1,52 @@
 pub mod apps;
 pub mod audio;
+pub mod compositor;
+pub mod dbus_client;
+pub mod dbus_export;
 pub mod dbus_service;
 pub mod desktop_entry;
 pub mod fs;
+pub mod hyprland;
+pub mod http;
+pub mod http_stream;
 pub mod json;
+pub mod niri;
+pub mod process;
+pub mod socket;
+pub mod sway;
 pub mod system;
+pub mod systemd;
 pub mod tray_api;
+pub mod websocket;
 
 use mlua::{Lua, Result};
 use std::rc::Rc;
 
-pub fn init(lua: Rc<Lua>) -> Result<()> {
+/// Registers the Lua tables of every service, without starting anything.
+pub fn register(lua: Rc<Lua>) -> Result<()> {
     apps::register(&lua)?;
     audio::register(lua.clone())?;
     system::register(&lua)?;
     json::register(&lua)?;
+    systemd::register(lua.clone())?;
     fs::register(lua.clone())?;
-    dbus_service::init(lua.clone()).map_err(mlua::Error::external)?;
+    process::register(lua.clone())?;
+    http::register(&lua)?;
+    websocket::register(&lua)?;
+    socket::register(&lua)?;
+    hyprland::register(&lua)?;
+    sway::register(&lua)?;
+    niri::register(&lua)?;
+    compositor::register(&lua)?;
+    dbus_client::register(lua.clone())?;
+    dbus_export::register(lua.clone())?;
     tray_api::register(lua.clone())?;
     Ok(())
 }
+
+/// Registers the services and starts the servers that claim D-Bus names.
+pub fn init(lua: Rc<Lua>) -> Result<()> {
+    register(lua.clone())?;
+    dbus_service::init(lua).map_err(mlua::Error::external)?;
+    Ok(())
+}
@@ -0,0 +1,569 @@
+use crate::ui::lifecycle;
+use gtk4::glib;
+use mlua::{Function, Lua, LuaSerdeExt, Result as LuaResult, UserData, UserDataMethods, Value};
+use serde_json::{Value as JsonValue, json};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+#[derive(Debug)]
+pub enum NiriError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+    NotRunning,
+    RequestFailed(String),
+}
+
+impl fmt::Display for NiriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NiriError::IoError(e) => write!(f, "Niri IPC error: {}", e),
+            NiriError::JsonError(e) => write!(f, "Invalid Niri reply: {}", e),
+            NiriError::NotRunning => write!(f, "NIRI_SOCKET is not set, is niri running?"),
+            NiriError::RequestFailed(e) => write!(f, "Niri request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for NiriError {}
+
+impl From<io::Error> for NiriError {
+    fn from(err: io::Error) -> Self {
+        NiriError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for NiriError {
+    fn from(err: serde_json::Error) -> Self {
+        NiriError::JsonError(err)
+    }
+}
+
+impl From<NiriError> for mlua::Error {
+    fn from(err: NiriError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+fn socket_path() -> Result<PathBuf, NiriError> {
+    env::var("NIRI_SOCKET")
+        .map(PathBuf::from)
+        .map_err(|_| NiriError::NotRunning)
+}
+
+pub fn is_running() -> bool {
+    socket_path().is_ok_and(|path| path.exists())
+}
+
+/// Unwraps a `{"Ok": ...}` or `{"Err": ...}` reply.
+fn unwrap_reply(reply: JsonValue) -> Result<JsonValue, NiriError> {
+    match reply {
+        JsonValue::Object(mut map) if map.contains_key("Ok") => {
+            Ok(map.remove("Ok").unwrap_or_default())
+        }
+        JsonValue::Object(mut map) if map.contains_key("Err") => Err(NiriError::RequestFailed(
+            map.remove("Err")
+                .and_then(|e| e.as_str().map(str::to_string))
+                .unwrap_or_default(),
+        )),
+        other => Err(NiriError::RequestFailed(other.to_string())),
+    }
+}
+
+/// Splits a `{"Name": payload}` object, as used by niri for replies and
+/// events, into its name and payload.
+fn variant(value: JsonValue) -> (String, JsonValue) {
+    match value {
+        JsonValue::String(name) => (name, JsonValue::Null),
+        JsonValue::Object(map) if map.len() == 1 => map.into_iter().next().unwrap_or_default(),
+        other => (String::new(), other),
+    }
+}
+
+/// Sends one request, such as `"Workspaces"` or `{"Action": ...}`, and
+/// returns the payload of the reply, e.g. the list of workspaces.
+pub async fn request(request: &JsonValue) -> Result<JsonValue, NiriError> {
+    let stream = UnixStream::connect(socket_path()?).await?;
+    let mut stream = BufReader::new(stream);
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream.get_mut().write_all(line.as_bytes()).await?;
+    let mut reply = String::new();
+    stream.read_line(&mut reply).await?;
+    let (_, payload) = variant(unwrap_reply(serde_json::from_str(&reply)?)?);
+    Ok(payload)
+}
+
+/// The compositor state, kept up to date from the event stream.
+#[derive(Clone, Default)]
+pub struct NiriModel {
+    pub workspaces: BTreeMap<u64, JsonValue>,
+    pub windows: BTreeMap<u64, JsonValue>,
+}
+
+fn id_of(value: &JsonValue) -> Option<u64> {
+    value["id"].as_u64()
+}
+
+fn by_id(items: &JsonValue) -> BTreeMap<u64, JsonValue> {
+    items
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| Some((id_of(item)?, item.clone())))
+        .collect()
+}
+
+impl NiriModel {
+    pub fn focused_window(&self) -> Option<u64> {
+        self.windows
+            .values()
+            .find(|w| w["is_focused"].as_bool() == Some(true))
+            .and_then(id_of)
+    }
+
+    fn apply(&mut self, event: &str, payload: &JsonValue) {
+        match event {
+            "WorkspacesChanged" => self.workspaces = by_id(&payload["workspaces"]),
+            "WorkspaceActivated" => {
+                let Some(id) = id_of(payload) else { return };
+                let output = self.workspaces.get(&id).map(|w| w["output"].clone());
+                let focused = payload["focused"].as_bool() == Some(true);
+                for (ws_id, ws) in self.workspaces.iter_mut() {
+                    if Some(&ws["output"]) == output.as_ref() {
+                        ws["is_active"] = json!(*ws_id == id);
+                    }
+                    if focused {
+                        ws["is_focused"] = json!(*ws_id == id);
+                    }
+                }
+            }
+            "WorkspaceActiveWindowChanged" => {
+                if let Some(ws) = payload["workspace_id"]
+                    .as_u64()
+                    .and_then(|id| self.workspaces.get_mut(&id))
+                {
+                    ws["active_window_id"] = payload["active_window_id"].clone();
+                }
+            }
+            "WorkspaceUrgencyChanged" => {
+                if let Some(ws) = id_of(payload).and_then(|id| self.workspaces.get_mut(&id)) {
+                    ws["is_urgent"] = payload["urgent"].clone();
+                }
+            }
+            "WindowsChanged" => self.windows = by_id(&payload["windows"]),
+            "WindowOpenedOrChanged" => {
+                let window = &payload["window"];
+                let Some(id) = id_of(window) else { return };
+                if window["is_focused"].as_bool() == Some(true) {
+                    for w in self.windows.values_mut() {
+                        w["is_focused"] = json!(false);
+                    }
+                }
+                self.windows.insert(id, window.clone());
+            }
+            "WindowClosed" => {
+                if let Some(id) = id_of(payload) {
+                    self.windows.remove(&id);
+                }
+            }
+            "WindowFocusChanged" => {
+                let focused = id_of(payload);
+                for (id, w) in self.windows.iter_mut() {
+                    w["is_focused"] = json!(Some(*id) == focused);
+                }
+            }
+            "WindowUrgencyChanged" => {
+                if let Some(w) = id_of(payload).and_then(|id| self.windows.get_mut(&id)) {
+                    w["is_urgent"] = payload["urgent"].clone();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn to_lua(&self, lua: &Lua) -> LuaResult<Value> {
+        let mut workspaces: Vec<&JsonValue> = self.workspaces.values().collect();
+        workspaces.sort_by_key(|w| (w["output"].as_str().map(str::to_string), w["idx"].as_u64()));
+        // Nulls become nil, so that e.g. `if state.focused_window` works.
+        let options = mlua::SerializeOptions::new()
+            .serialize_none_to_null(false)
+            .serialize_unit_to_null(false);
+        lua.to_value_with(
+            &json!({
+                "workspaces": workspaces,
+                "windows": self.windows.values().collect::<Vec<_>>(),
+                "focused_window": self.focused_window(),
+            }),
+            options,
+        )
+    }
+}
+
+type Listener = Rc<dyn Fn(&NiriModel, &str, &JsonValue)>;
+
+struct EventStream {
+    model: NiriModel,
+    listeners: Vec<(u64, Listener)>,
+    next_id: u64,
+    task: Option<glib::JoinHandle<()>>,
+    /// Whether the stream is connected, so that `model` is current.
+    live: bool,
+    /// How long to wait before reconnecting after the stream closed.
+    retry_delay: Option<Duration>,
+}
+
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+thread_local! {
+    /// The single event stream shared by every watcher, running while there
+    /// is at least one.
+    static STREAM: RefCell<EventStream> = RefCell::new(EventStream {
+        model: NiriModel::default(),
+        listeners: Vec::new(),
+        next_id: 0,
+        task: None,
+        live: false,
+        retry_delay: None,
+    });
+}
+
+async fn run_event_stream() -> Result<(), NiriError> {
+    let mut stream = BufReader::new(UnixStream::connect(socket_path()?).await?);
+    stream.get_mut().write_all(b"\"EventStream\"\n").await?;
+    let mut lines = stream.lines();
+    if let Some(reply) = lines.next_line().await? {
+        unwrap_reply(serde_json::from_str(&reply)?)?;
+    }
+    STREAM.with_borrow_mut(|stream| stream.live = true);
+    while let Some(line) = lines.next_line().await? {
+        let (event, payload) = variant(serde_json::from_str(&line)?);
+        let (model, listeners) = STREAM.with_borrow_mut(|stream| {
+            stream.retry_delay = None;
+            stream.model.apply(&event, &payload);
+            let listeners: Vec<Listener> =
+                stream.listeners.iter().map(|(_, l)| l.clone()).collect();
+            (stream.model.clone(), listeners)
+        });
+        for listener in listeners {
+            listener(&model, &event, &payload);
+        }
+    }
+    Ok(())
+}
+
+/// Calls `listener(model, event, payload)` after every event has been
+/// applied to the model. The event stream starts with the first listener.
+pub fn subscribe(listener: impl Fn(&NiriModel, &str, &JsonValue) + 'static) -> u64 {
+    STREAM.with_borrow_mut(|stream| {
+        let id = stream.next_id;
+        stream.next_id += 1;
+        stream.listeners.push((id, Rc::new(listener)));
+        if stream.task.is_none() {
+            stream.task = Some(start_stream());
+        }
+        id
+    })
+}
+
+/// Runs the event stream, reconnecting with a growing delay if it closes
+/// while there are still listeners.
+fn start_stream() -> glib::JoinHandle<()> {
+    glib::MainContext::default().spawn_local(async {
+        loop {
+            if let Err(e) = run_event_stream().await {
+                eprintln!("Niri event stream error: {}", e);
+            }
+            let delay = STREAM.with_borrow_mut(|stream| {
+                stream.live = false;
+                stream.model = NiriModel::default();
+                let delay = stream.retry_delay.unwrap_or(RETRY_DELAY);
+                stream.retry_delay = Some((delay * 2).min(MAX_RETRY_DELAY));
+                delay
+            });
+            glib::timeout_future(delay).await;
+        }
+    })
+}
+
+/// Removes a listener, stopping the event stream after the last one.
+pub fn unsubscribe(id: u64) {
+    STREAM.with_borrow_mut(|stream| {
+        stream.listeners.retain(|(i, _)| *i != id);
+        if stream.listeners.is_empty() {
+            if let Some(task) = stream.task.take() {
+                task.abort();
+            }
+            stream.live = false;
+            stream.model = NiriModel::default();
+            stream.retry_delay = None;
+        }
+    });
+}
+
+pub fn model() -> Option<NiriModel> {
+    STREAM.with_borrow(|stream| stream.live.then(|| stream.model.clone()))
+}
+
+/// Requests the current state, for when no event stream keeps a model.
+pub async fn fetch_model() -> Result<NiriModel, NiriError> {
+    let requests = [json!("Workspaces"), json!("Windows")];
+    let (workspaces, windows) =
+        futures_util::try_join!(request(&requests[0]), request(&requests[1]))?;
+    Ok(NiriModel {
+        workspaces: by_id(&workspaces),
+        windows: by_id(&windows),
+    })
+}
+
+#[derive(Clone)]
+struct NiriSubscription {
+    id: u64,
+    active: Rc<Cell<bool>>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl NiriSubscription {
+    fn disconnect(&self) {
+        self.cleanup.remove();
+        if self.active.replace(false) {
+            unsubscribe(self.id);
+        }
+    }
+}
+
+impl UserData for NiriSubscription {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("disconnect", |_, this, ()| {
+            this.disconnect();
+            Ok(())
+        });
+    }
+}
+
+/// Sends `req` and calls `callback(result, err)` with the reply payload.
+fn query(lua: &Lua, req: JsonValue, callback: Option<Function>) {
+    let lua = lua.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let result = request(&req).await;
+        let Some(callback) = callback else {
+            if let Err(e) = result {
+                eprintln!("Niri IPC error: {}", e);
+            }
+            return;
+        };
+        let called = match result
+            .map_err(mlua::Error::from)
+            .and_then(|v| lua.to_value(&v))
+        {
+            Ok(value) => callback.call::<()>((value, Value::Nil)),
+            Err(e) => callback.call::<()>((Value::Nil, e.to_string())),
+        };
+        if let Err(e) = called {
+            eprintln!("Niri callback error: {}", e);
+        }
+    });
+}
+
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    let niri = lua.create_table()?;
+
+    for (name, req) in [
+        ("workspaces", "Workspaces"),
+        ("windows", "Windows"),
+        ("outputs", "Outputs"),
+        ("focused_window", "FocusedWindow"),
+        ("focused_output", "FocusedOutput"),
+        ("layers", "Layers"),
+        ("keyboard_layouts", "KeyboardLayouts"),
+        ("version", "Version"),
+    ] {
+        niri.set(
+            name,
+            lua.create_function(move |lua, callback: Function| {
+                query(lua, json!(req), Some(callback));
+                Ok(())
+            })?,
+        )?;
+    }
+
+    niri.set(
+        "request",
+        lua.create_function(|lua, (req, callback): (Value, Option<Function>)| {
+            query(
+                lua,
+                serde_json::to_value(&req).map_err(NiriError::from)?,
+                callback,
+            );
+            Ok(())
+        })?,
+    )?;
+
+    niri.set(
+        "action",
+        lua.create_function(
+            |lua, (name, args, callback): (String, Value, Option<Function>)| {
+                let args = match args {
+                    Value::Nil => json!({}),
+                    args => serde_json::to_value(&args).map_err(NiriError::from)?,
+                };
+                query(lua, json!({ "Action": { name: args } }), callback);
+                Ok(())
+            },
+        )?,
+    )?;
+
+    niri.set(
+        "watch",
+        lua.create_function(|lua, callback: Function| {
+            let lua = lua.clone();
+            let id = subscribe(move |model, event, payload| {
+                let called = model
+                    .to_lua(&lua)
+                    .and_then(|state| callback.call::<()>((state, event, lua.to_value(payload)?)));
+                if let Err(e) = called {
+                    eprintln!("Niri watch callback error: {}", e);
+                }
+            });
+            let subscription = NiriSubscription {
+                id,
+                active: Rc::new(Cell::new(true)),
+                cleanup: Default::default(),
+            };
+            let tracked = subscription.clone();
+            subscription
+                .cleanup
+                .set(lifecycle::track(move || tracked.disconnect()));
+            Ok(subscription)
+        })?,
+    )?;
+
+    niri.set(
+        "state",
+        lua.create_function(|lua, ()| match model() {
+            Some(model) => model.to_lua(lua),
+            None => Ok(Value::Nil),
+        })?,
+    )?;
+
+    niri.set("is_running", lua.create_function(|_, ()| Ok(is_running()))?)?;
+
+    lua.globals().set("Niri", niri)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NiriModel, variant};
+
+    /// Applies one line of the event stream, as sent by niri.
+    fn apply(model: &mut NiriModel, line: &str) {
+        let (event, payload) = variant(serde_json::from_str(line).unwrap());
+        model.apply(&event, &payload);
+    }
+
+    fn workspace(id: u64, idx: u64, output: &str, active: bool, focused: bool) -> String {
+        format!(
+            r#"{{"id":{id},"idx":{idx},"name":null,"output":"{output}","is_urgent":false,"is_active":{active},"is_focused":{focused},"active_window_id":null}}"#
+        )
+    }
+
+    fn window(id: u64, workspace: u64, focused: bool) -> String {
+        format!(
+            r#"{{"id":{id},"title":"Terminal","app_id":"kitty","pid":1234,"workspace_id":{workspace},"is_focused":{focused},"is_floating":false,"is_urgent":false}}"#
+        )
+    }
+
+    fn model() -> NiriModel {
+        let mut model = NiriModel::default();
+        apply(
+            &mut model,
+            &format!(
+                r#"{{"WorkspacesChanged":{{"workspaces":[{},{},{},{}]}}}}"#,
+                workspace(1, 1, "DP-1", true, true),
+                workspace(2, 2, "DP-1", false, false),
+                workspace(3, 1, "HDMI-A-1", true, false),
+                workspace(4, 2, "HDMI-A-1", false, false),
+            ),
+        );
+        apply(
+            &mut model,
+            &format!(
+                r#"{{"WindowsChanged":{{"windows":[{},{}]}}}}"#,
+                window(10, 1, true),
+                window(11, 3, false),
+            ),
+        );
+        model
+    }
+
+    fn flags(model: &NiriModel, key: &str) -> Vec<u64> {
+        model
+            .workspaces
+            .iter()
+            .filter(|(_, ws)| ws[key].as_bool() == Some(true))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    #[test]
+    fn workspace_activation_is_per_output() {
+        let mut model = model();
+        apply(
+            &mut model,
+            r#"{"WorkspaceActivated":{"id":4,"focused":false}}"#,
+        );
+        assert_eq!(flags(&model, "is_active"), vec![1, 4]);
+        assert_eq!(flags(&model, "is_focused"), vec![1]);
+
+        apply(
+            &mut model,
+            r#"{"WorkspaceActivated":{"id":2,"focused":true}}"#,
+        );
+        assert_eq!(flags(&model, "is_active"), vec![2, 4]);
+        assert_eq!(flags(&model, "is_focused"), vec![2]);
+    }
+
+    #[test]
+    fn opening_a_focused_window_clears_other_focus() {
+        let mut model = model();
+        apply(
+            &mut model,
+            &format!(
+                r#"{{"WindowOpenedOrChanged":{{"window":{}}}}}"#,
+                window(12, 1, false)
+            ),
+        );
+        assert_eq!(model.focused_window(), Some(10));
+
+        apply(
+            &mut model,
+            &format!(
+                r#"{{"WindowOpenedOrChanged":{{"window":{}}}}}"#,
+                window(13, 3, true)
+            ),
+        );
+        assert_eq!(model.focused_window(), Some(13));
+        assert_eq!(model.windows[&10]["is_focused"], false);
+        assert_eq!(model.windows.len(), 4);
+    }
+
+    #[test]
+    fn focus_can_move_to_no_window() {
+        let mut model = model();
+        apply(&mut model, r#"{"WindowFocusChanged":{"id":11}}"#);
+        assert_eq!(model.focused_window(), Some(11));
+
+        apply(&mut model, r#"{"WindowFocusChanged":{"id":null}}"#);
+        assert_eq!(model.focused_window(), None);
+        assert!(model.windows.values().all(|w| w["is_focused"] == false));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::ui::lifecycle;
+use gtk4::glib;
+use mlua::{Function, Lua, Result as LuaResult, Table, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Stdio};
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+#[derive(Debug)]
+pub enum ProcessError {
+    IoError(io::Error),
+    EmptyCommand,
+    InvalidCommand(String),
+    InvalidSignal(String),
+    StdinClosed,
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::IoError(e) => write!(f, "I/O error: {}", e),
+            ProcessError::EmptyCommand => write!(f, "Command is empty"),
+            ProcessError::InvalidCommand(t) => write!(
+                f,
+                "Invalid command: expected a string or an argv table, got {}",
+                t
+            ),
+            ProcessError::InvalidSignal(s) => write!(f, "Unknown signal '{}'", s),
+            ProcessError::StdinClosed => write!(
+                f,
+                "stdin is not open, pass `stdin = true` and do not call close_stdin() before writing"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+impl From<io::Error> for ProcessError {
+    fn from(err: io::Error) -> Self {
+        ProcessError::IoError(err)
+    }
+}
+
+impl From<ProcessError> for mlua::Error {
+    fn from(err: ProcessError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("TERM", libc::SIGTERM),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+];
+
+fn parse_signal(signal: Option<Value>) -> Result<i32, ProcessError> {
+    match signal {
+        None | Some(Value::Nil) => Ok(libc::SIGTERM),
+        Some(Value::Integer(n)) => Ok(n as i32),
+        Some(Value::String(s)) => {
+            let name = s.to_string_lossy().to_uppercase();
+            let name = name.strip_prefix("SIG").unwrap_or(&name);
+            SIGNALS
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, sig)| *sig)
+                .ok_or_else(|| ProcessError::InvalidSignal(s.to_string_lossy()))
+        }
+        Some(other) => Err(ProcessError::InvalidSignal(other.type_name().to_string())),
+    }
+}
+
+struct ProcessInner {
+    pid: u32,
+    exit: Option<ExitStatus>,
+    stdin: Option<UnboundedSender<Vec<u8>>>,
+    waiters: Vec<Function>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+/// A running child process. Its output is read on the main loop, so the
+/// callbacks run on the same thread as the rest of the config.
+#[derive(Clone)]
+struct ProcessHandle(Rc<RefCell<ProcessInner>>);
+
+impl ProcessHandle {
+    fn is_running(&self) -> bool {
+        self.0.borrow().exit.is_none()
+    }
+
+    /// Sends `signal` to the process. Returns false if it already exited, so
+    /// a recycled pid is never signalled.
+    fn kill(&self, signal: i32) -> Result<bool, ProcessError> {
+        let inner = self.0.borrow();
+        if inner.exit.is_some() {
+            return Ok(false);
+        }
+        if unsafe { libc::kill(inner.pid as libc::pid_t, signal) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(true)
+    }
+
+    fn finish(&self, status: ExitStatus, on_exit: Option<Function>) {
+        let waiters = {
+            let mut inner = self.0.borrow_mut();
+            inner.exit = Some(status);
+            inner.stdin = None;
+            inner.cleanup.remove();
+            std::mem::take(&mut inner.waiters)
+        };
+        for callback in on_exit.into_iter().chain(waiters) {
+            if let Err(e) = callback.call::<()>(exit_args(status)) {
+                eprintln!("Process exit callback error: {}", e);
+            }
+        }
+    }
+}
+
+/// The exit code, or nil and the signal number if the process was killed.
+fn exit_args(status: ExitStatus) -> (Option<i32>, Option<i32>) {
+    (status.code(), status.signal())
+}
+
+impl UserData for ProcessHandle {
+    fn add_fields<F: mlua::UserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("pid", |_, this| Ok(this.0.borrow().pid));
+    }
+
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("write", |_, this, data: mlua::String| {
+            let inner = this.0.borrow();
+            let stdin = inner.stdin.as_ref().ok_or(ProcessError::StdinClosed)?;
+            stdin
+                .send(data.as_bytes().to_vec())
+                .map_err(|_| ProcessError::StdinClosed)?;
+            Ok(())
+        });
+        methods.add_method("close_stdin", |_, this, ()| {
+            this.0.borrow_mut().stdin = None;
+            Ok(())
+        });
+        methods.add_method("kill", |_, this, signal: Option<Value>| {
+            Ok(this.kill(parse_signal(signal)?)?)
+        });
+        methods.add_method("is_running", |_, this, ()| Ok(this.is_running()));
+        methods.add_method("wait", |_, this, callback: Function| {
+            let exit = this.0.borrow().exit;
+            match exit {
+                Some(status) => callback.call::<()>(exit_args(status)),
+                None => {
+                    this.0.borrow_mut().waiters.push(callback);
+                    Ok(())
+                }
+            }
+        });
+    }
+}
+
+fn command_from(command: Value) -> Result<Command, ProcessError> {
+    match command {
+        Value::String(cmd) => {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(cmd.to_string_lossy());
+            Ok(command)
+        }
+        Value::Table(argv) => {
+            let argv = argv
+                .sequence_values::<String>()
+                .collect::<LuaResult<Vec<_>>>()
+                .map_err(|e| ProcessError::InvalidCommand(e.to_string()))?;
+            let (program, args) = argv.split_first().ok_or(ProcessError::EmptyCommand)?;
+            let mut command = Command::new(program);
+            command.args(args);
+            Ok(command)
+        }
+        other => Err(ProcessError::InvalidCommand(other.type_name().to_string())),
+    }
+}
+
+async fn read_lines(stream: Option<impl AsyncRead + Unpin>, callback: Option<Function>) {
+    let (Some(stream), Some(callback)) = (stream, callback) else {
+        return;
+    };
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Err(e) = callback.call::<()>(line) {
+            eprintln!("Process output callback error: {}", e);
+        }
+    }
+}
+
+fn spawn(command: Value, opts: Option<Table>) -> LuaResult<ProcessHandle> {
+    let mut command = command_from(command)?;
+    let opt =
+        |key: &str| -> LuaResult<Value> { opts.as_ref().map_or(Ok(Value::Nil), |t| t.get(key)) };
+
+    let on_stdout: Option<Function> = opt("stdout")?.as_function().cloned();
+    let on_stderr: Option<Function> = opt("stderr")?.as_function().cloned();
+    let on_exit: Option<Function> = opt("on_exit")?.as_function().cloned();
+    let pipe_stdin = opt("stdin")?.as_boolean().unwrap_or(false);
+    let timeout = opt("timeout")?.as_u32();
+
+    if let Some(cwd) = opt("cwd")?.as_string() {
+        command.current_dir(cwd.to_str()?.as_ref());
+    }
+    if let Value::Table(env) = opt("env")? {
+        for pair in env.pairs::<String, String>() {
+            let (key, value) = pair?;
+            command.env(key, value);
+        }
+    }
+
+    let piped = |on: bool| if on { Stdio::piped() } else { Stdio::null() };
+    command
+        .stdin(piped(pipe_stdin))
+        .stdout(piped(on_stdout.is_some()))
+        .stderr(piped(on_stderr.is_some()));
+
+    let mut child = command.spawn().map_err(ProcessError::from)?;
+    let pid = child.id().unwrap_or_default();
+
+    let stdin = child.stdin.take().map(|mut stdin| {
+        let (tx, mut rx) = unbounded_channel::<Vec<u8>>();
+        glib::MainContext::default().spawn_local(async move {
+            while let Some(data) = rx.recv().await {
+                if stdin.write_all(&data).await.is_err() {
+                    break;
+                }
+            }
+        });
+        tx
+    });
+
+    let handle = ProcessHandle(Rc::new(RefCell::new(ProcessInner {
+        pid,
+        exit: None,
+        stdin,
+        waiters: Vec::new(),
+        cleanup: Default::default(),
+    })));
+
+    glib::MainContext::default().spawn_local({
+        let handle = handle.clone();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        async move {
+            let (_, _, status) = futures_util::join!(
+                read_lines(stdout, on_stdout),
+                read_lines(stderr, on_stderr),
+                child.wait()
+            );
+            match status {
+                Ok(status) => handle.finish(status, on_exit),
+                Err(e) => eprintln!("Failed to wait for process {}: {}", pid, e),
+            }
+        }
+    });
+
+    if let Some(ms) = timeout {
+        let handle = handle.clone();
+        glib::timeout_add_local_once(Duration::from_millis(ms as u64), move || {
+            let _ = handle.kill(libc::SIGTERM);
+        });
+    }
+
+    let tracked = handle.clone();
+    let cleanup = lifecycle::track(move || {
+        let _ = tracked.kill(libc::SIGTERM);
+    });
+    handle.0.borrow().cleanup.set(cleanup);
+    Ok(handle)
+}
+
+pub fn register(lua: Rc<Lua>) -> LuaResult<()> {
+    let process = lua.create_table()?;
+    process.set(
+        "spawn",
+        lua.create_function(|_, (command, opts): (Value, Option<Table>)| spawn(command, opts))?,
+    )?;
+    lua.globals().set("Process", process)?;
+    Ok(())
+}
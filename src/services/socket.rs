@@ -0,0 +1,477 @@
+use crate::ui::lifecycle;
+use gtk4::glib;
+use mlua::{BString, Function, Lua, Result as LuaResult, Table, UserData, UserDataMethods};
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+#[derive(Debug)]
+pub enum SocketError {
+    IoError(io::Error),
+    InvalidFraming(String),
+    Closed,
+}
+
+impl fmt::Display for SocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketError::IoError(e) => write!(f, "I/O error: {}", e),
+            SocketError::InvalidFraming(s) => {
+                write!(f, "Invalid framing '{}', expected lines, raw or length", s)
+            }
+            SocketError::Closed => write!(f, "Socket is closed"),
+        }
+    }
+}
+
+impl std::error::Error for SocketError {}
+
+impl From<io::Error> for SocketError {
+    fn from(err: io::Error) -> Self {
+        SocketError::IoError(err)
+    }
+}
+
+impl From<SocketError> for mlua::Error {
+    fn from(err: SocketError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+/// A socket address: `unix:/path`, or any path starting with `/`, is a
+/// unix-domain socket, while `tcp:host:port` or `host:port` is TCP.
+enum Address {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Address {
+    fn parse(address: &str) -> Self {
+        if let Some(path) = address.strip_prefix("unix:") {
+            Address::Unix(PathBuf::from(path))
+        } else if let Some(host) = address.strip_prefix("tcp:") {
+            Address::Tcp(host.to_string())
+        } else if address.starts_with('/') {
+            Address::Unix(PathBuf::from(address))
+        } else {
+            Address::Tcp(address.to_string())
+        }
+    }
+}
+
+/// How the byte stream is split into messages.
+#[derive(Clone, Copy)]
+enum Framing {
+    /// Newline-terminated lines, without the terminator.
+    Lines,
+    /// Chunks as they are read.
+    Raw,
+    /// Messages prefixed with their length as a big-endian u32, up to the
+    /// given size.
+    Length(u32),
+}
+
+/// The largest length-prefixed message accepted unless `max_frame_size`
+/// says otherwise.
+const DEFAULT_MAX_FRAME_SIZE: u32 = 16 * 1024 * 1024;
+
+/// How long a server waits before accepting again after a failed accept,
+/// doubling up to `MAX_ACCEPT_DELAY` while it keeps failing.
+const ACCEPT_DELAY: Duration = Duration::from_millis(100);
+const MAX_ACCEPT_DELAY: Duration = Duration::from_secs(5);
+
+impl Framing {
+    fn from_opts(opts: &Option<Table>) -> LuaResult<Self> {
+        let (framing, max_frame_size) = match opts {
+            Some(opts) => (
+                opts.get::<Option<String>>("framing")?,
+                opts.get::<Option<u32>>("max_frame_size")?,
+            ),
+            None => (None, None),
+        };
+        match framing.as_deref() {
+            None | Some("lines") => Ok(Framing::Lines),
+            Some("raw") => Ok(Framing::Raw),
+            Some("length") => Ok(Framing::Length(
+                max_frame_size.unwrap_or(DEFAULT_MAX_FRAME_SIZE),
+            )),
+            Some(other) => Err(SocketError::InvalidFraming(other.to_string()).into()),
+        }
+    }
+
+    /// Reads the next message, or `None` at end of stream.
+    async fn read(
+        &self,
+        reader: &mut BufReader<impl AsyncRead + Unpin>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            Framing::Lines => {
+                let mut line = Vec::new();
+                if reader.read_until(b'\n', &mut line).await? == 0 {
+                    return Ok(None);
+                }
+                if line.ends_with(b"\n") {
+                    line.pop();
+                }
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                Ok(Some(line))
+            }
+            Framing::Raw => {
+                let mut chunk = vec![0; 8192];
+                let n = reader.read(&mut chunk).await?;
+                chunk.truncate(n);
+                Ok((n > 0).then_some(chunk))
+            }
+            Framing::Length(max) => {
+                let len = match reader.read_u32().await {
+                    Ok(len) => len,
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                if len > *max {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Frame of {} bytes exceeds max_frame_size {}", len, max),
+                    ));
+                }
+                let mut frame = vec![0; len as usize];
+                reader.read_exact(&mut frame).await?;
+                Ok(Some(frame))
+            }
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Framing::Length(_) => {
+                let mut frame = (data.len() as u32).to_be_bytes().to_vec();
+                frame.extend_from_slice(data);
+                frame
+            }
+            Framing::Lines | Framing::Raw => data.to_vec(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Callbacks {
+    connect: Option<Function>,
+    data: Option<Function>,
+    close: Option<Function>,
+    error: Option<Function>,
+}
+
+struct SocketInner {
+    callbacks: Callbacks,
+    open: bool,
+    writer: Option<UnboundedSender<Vec<u8>>>,
+    reader: Option<glib::JoinHandle<()>>,
+}
+
+/// One connection, either opened with `Socket.connect` or accepted by a
+/// server.
+#[derive(Clone)]
+struct LuaSocket {
+    inner: Rc<RefCell<SocketInner>>,
+    framing: Framing,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl LuaSocket {
+    fn new(framing: Framing, callbacks: Callbacks) -> (Self, UnboundedReceiver<Vec<u8>>) {
+        let (tx, rx) = unbounded_channel();
+        let socket = Self {
+            inner: Rc::new(RefCell::new(SocketInner {
+                callbacks,
+                open: true,
+                writer: Some(tx),
+                reader: None,
+            })),
+            framing,
+            cleanup: Default::default(),
+        };
+        (socket, rx)
+    }
+
+    /// Calls one of the callbacks without holding the borrow, so it may
+    /// write to or close the socket.
+    fn emit(&self, pick: fn(&Callbacks) -> &Option<Function>, args: impl mlua::IntoLuaMulti) {
+        let callback = pick(&self.inner.borrow().callbacks).clone();
+        if let Some(callback) = callback
+            && let Err(e) = callback.call::<()>(args)
+        {
+            eprintln!("Socket callback error: {}", e);
+        }
+    }
+
+    /// Starts reading from and writing to `stream`.
+    fn attach<S>(&self, stream: S, mut outgoing: UnboundedReceiver<Vec<u8>>)
+    where
+        S: AsyncRead + AsyncWrite + 'static,
+    {
+        let (read, mut write) = tokio::io::split(stream);
+        glib::MainContext::default().spawn_local(async move {
+            while let Some(data) = outgoing.recv().await {
+                if write.write_all(&data).await.is_err() {
+                    break;
+                }
+            }
+            let _ = write.shutdown().await;
+        });
+
+        let reader = glib::MainContext::default().spawn_local({
+            let socket = self.clone();
+            async move {
+                let mut reader = BufReader::new(read);
+                let result = loop {
+                    match socket.framing.read(&mut reader).await {
+                        Ok(Some(message)) => socket.emit(|c| &c.data, BString::from(message)),
+                        Ok(None) => break Ok(()),
+                        Err(e) => break Err(e),
+                    }
+                };
+                if let Err(e) = result {
+                    socket.emit(|c| &c.error, e.to_string());
+                }
+                socket.shutdown();
+                socket.emit(|c| &c.close, ());
+            }
+        });
+        self.inner.borrow_mut().reader = Some(reader);
+    }
+
+    fn shutdown(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.open = false;
+        inner.writer = None;
+        self.cleanup.remove();
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), SocketError> {
+        let inner = self.inner.borrow();
+        let writer = inner.writer.as_ref().ok_or(SocketError::Closed)?;
+        writer
+            .send(self.framing.encode(data))
+            .map_err(|_| SocketError::Closed)
+    }
+
+    fn close(&self) {
+        self.shutdown();
+        if let Some(reader) = self.inner.borrow_mut().reader.take() {
+            reader.abort();
+        }
+    }
+}
+
+impl UserData for LuaSocket {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("write", |_, this, data: mlua::String| {
+            Ok(this.write(&data.as_bytes())?)
+        });
+        methods.add_method("close", |_, this, ()| {
+            this.close();
+            Ok(())
+        });
+        methods.add_method("is_open", |_, this, ()| Ok(this.inner.borrow().open));
+        methods.add_method("on_data", |_, this, callback: Function| {
+            this.inner.borrow_mut().callbacks.data = Some(callback);
+            Ok(())
+        });
+        methods.add_method("on_close", |_, this, callback: Function| {
+            this.inner.borrow_mut().callbacks.close = Some(callback);
+            Ok(())
+        });
+        methods.add_method("on_error", |_, this, callback: Function| {
+            this.inner.borrow_mut().callbacks.error = Some(callback);
+            Ok(())
+        });
+    }
+}
+
+fn callbacks_from(opts: &Option<Table>) -> LuaResult<Callbacks> {
+    let Some(opts) = opts else {
+        return Ok(Callbacks::default());
+    };
+    Ok(Callbacks {
+        connect: opts.get("connect")?,
+        data: opts.get("data")?,
+        close: opts.get("close")?,
+        error: opts.get("error")?,
+    })
+}
+
+fn connect(address: String, opts: Option<Table>) -> LuaResult<LuaSocket> {
+    let framing = Framing::from_opts(&opts)?;
+    let (socket, outgoing) = LuaSocket::new(framing, callbacks_from(&opts)?);
+    let address = Address::parse(&address);
+
+    let task = glib::MainContext::default().spawn_local({
+        let socket = socket.clone();
+        async move {
+            let attached = match address {
+                Address::Unix(path) => UnixStream::connect(path)
+                    .await
+                    .map(|stream| socket.attach(stream, outgoing)),
+                Address::Tcp(host) => TcpStream::connect(host)
+                    .await
+                    .map(|stream| socket.attach(stream, outgoing)),
+            };
+            match attached {
+                Ok(()) => socket.emit(|c| &c.connect, ()),
+                Err(e) => {
+                    socket.shutdown();
+                    socket.emit(|c| &c.error, e.to_string());
+                    socket.emit(|c| &c.close, ());
+                }
+            }
+        }
+    });
+    socket.inner.borrow_mut().reader = Some(task);
+
+    let tracked = socket.clone();
+    socket
+        .cleanup
+        .set(lifecycle::track(move || tracked.close()));
+    Ok(socket)
+}
+
+enum Listener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+impl Listener {
+    fn bind(address: &Address) -> io::Result<Self> {
+        match address {
+            Address::Unix(path) => {
+                // A socket file left behind by a previous run that nothing
+                // answers on any more would make bind fail.
+                if path.exists() && std::os::unix::net::UnixStream::connect(path).is_err() {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            Address::Tcp(host) => {
+                let listener = std::net::TcpListener::bind(host)?;
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Tcp(TcpListener::from_std(listener)?))
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct LuaSocketServer {
+    task: Rc<RefCell<Option<glib::JoinHandle<()>>>>,
+    path: Option<PathBuf>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl LuaSocketServer {
+    fn close(&self) {
+        self.cleanup.remove();
+        if let Some(task) = self.task.borrow_mut().take() {
+            task.abort();
+            if let Some(path) = &self.path {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+impl UserData for LuaSocketServer {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("close", |_, this, ()| {
+            this.close();
+            Ok(())
+        });
+        methods.add_method("is_listening", |_, this, ()| {
+            Ok(this.task.borrow().is_some())
+        });
+    }
+}
+
+fn listen(address: String, opts: Option<Table>) -> LuaResult<LuaSocketServer> {
+    let framing = Framing::from_opts(&opts)?;
+    let address = Address::parse(&address);
+    let listener = Listener::bind(&address).map_err(SocketError::from)?;
+    let (on_accept, on_error) = match &opts {
+        Some(opts) => (
+            opts.get::<Option<Function>>("accept")?,
+            opts.get::<Option<Function>>("error")?,
+        ),
+        None => (None, None),
+    };
+
+    let task = glib::MainContext::default().spawn_local(async move {
+        let mut delay = ACCEPT_DELAY;
+        loop {
+            let (socket, outgoing) = LuaSocket::new(framing, Callbacks::default());
+            let accepted = match &listener {
+                Listener::Unix(l) => l
+                    .accept()
+                    .await
+                    .map(|(stream, _)| socket.attach(stream, outgoing)),
+                Listener::Tcp(l) => l
+                    .accept()
+                    .await
+                    .map(|(stream, _)| socket.attach(stream, outgoing)),
+            };
+            // Reading starts once this task yields, so `accept` can set the
+            // connection's callbacks before any data arrives.
+            let result = match &accepted {
+                Ok(()) => on_accept.as_ref().map(|accept| accept.call::<()>(socket)),
+                Err(e) => on_error
+                    .as_ref()
+                    .map(|error| error.call::<()>(e.to_string())),
+            };
+            if let Some(Err(e)) = result {
+                eprintln!("Socket callback error: {}", e);
+            }
+            // Errors such as running out of file descriptors tend to repeat
+            // right away, so back off instead of spinning.
+            if accepted.is_err() {
+                glib::timeout_future(delay).await;
+                delay = (delay * 2).min(MAX_ACCEPT_DELAY);
+            } else {
+                delay = ACCEPT_DELAY;
+            }
+        }
+    });
+
+    let server = LuaSocketServer {
+        task: Rc::new(RefCell::new(Some(task))),
+        path: match address {
+            Address::Unix(path) => Some(path),
+            Address::Tcp(_) => None,
+        },
+        cleanup: Default::default(),
+    };
+    let tracked = server.clone();
+    server
+        .cleanup
+        .set(lifecycle::track(move || tracked.close()));
+    Ok(server)
+}
+
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    let socket = lua.create_table()?;
+    socket.set(
+        "connect",
+        lua.create_function(|_, (address, opts): (String, Option<Table>)| connect(address, opts))?,
+    )?;
+    socket.set(
+        "listen",
+        lua.create_function(|_, (address, opts): (String, Option<Table>)| listen(address, opts))?,
+    )?;
+    lua.globals().set("Socket", socket)?;
+    Ok(())
+}
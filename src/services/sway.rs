@@ -0,0 +1,310 @@
+use crate::ui::lifecycle;
+use gtk4::glib;
+use mlua::{Function, Lua, LuaSerdeExt, Result as LuaResult, UserData, UserDataMethods, Value};
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+#[derive(Debug)]
+pub enum SwayError {
+    IoError(io::Error),
+    JsonError(serde_json::Error),
+    NotRunning,
+    InvalidReply(String),
+    UnknownEvent(String),
+}
+
+impl fmt::Display for SwayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwayError::IoError(e) => write!(f, "Sway IPC error: {}", e),
+            SwayError::JsonError(e) => write!(f, "Invalid Sway reply: {}", e),
+            SwayError::NotRunning => write!(f, "Neither SWAYSOCK nor I3SOCK is set"),
+            SwayError::InvalidReply(e) => write!(f, "Invalid Sway reply: {}", e),
+            SwayError::UnknownEvent(e) => write!(f, "Unknown Sway event '{}'", e),
+        }
+    }
+}
+
+impl std::error::Error for SwayError {}
+
+impl From<io::Error> for SwayError {
+    fn from(err: io::Error) -> Self {
+        SwayError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for SwayError {
+    fn from(err: serde_json::Error) -> Self {
+        SwayError::JsonError(err)
+    }
+}
+
+impl From<SwayError> for mlua::Error {
+    fn from(err: SwayError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+
+pub const RUN_COMMAND: u32 = 0;
+pub const GET_WORKSPACES: u32 = 1;
+const SUBSCRIBE: u32 = 2;
+pub const GET_OUTPUTS: u32 = 3;
+pub const GET_TREE: u32 = 4;
+
+/// Event replies have the high bit of their type set.
+const EVENT_BIT: u32 = 1 << 31;
+
+const EVENTS: &[(&str, u32)] = &[
+    ("workspace", 0),
+    ("output", 1),
+    ("mode", 2),
+    ("window", 3),
+    ("barconfig_update", 4),
+    ("binding", 5),
+    ("shutdown", 6),
+    ("tick", 7),
+    ("bar_state_update", 0x14),
+    ("input", 0x15),
+];
+
+fn socket_path() -> Result<PathBuf, SwayError> {
+    env::var("SWAYSOCK")
+        .or_else(|_| env::var("I3SOCK"))
+        .map(PathBuf::from)
+        .map_err(|_| SwayError::NotRunning)
+}
+
+pub fn is_running() -> bool {
+    socket_path().is_ok_and(|path| path.exists())
+}
+
+async fn write_message(stream: &mut UnixStream, kind: u32, payload: &[u8]) -> io::Result<()> {
+    let mut message = Vec::with_capacity(14 + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload);
+    stream.write_all(&message).await
+}
+
+async fn read_message(stream: &mut UnixStream) -> Result<(u32, serde_json::Value), SwayError> {
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header).await?;
+    if &header[..6] != MAGIC {
+        return Err(SwayError::InvalidReply("bad magic string".to_string()));
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap_or_default());
+    let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap_or_default());
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload).await?;
+    Ok((kind, serde_json::from_slice(&payload)?))
+}
+
+/// Sends one message of type `kind` and returns the decoded reply.
+pub async fn query(kind: u32, payload: &str) -> Result<serde_json::Value, SwayError> {
+    let mut stream = UnixStream::connect(socket_path()?).await?;
+    write_message(&mut stream, kind, payload.as_bytes()).await?;
+    Ok(read_message(&mut stream).await?.1)
+}
+
+/// Calls `on_event(name, payload)` for each of the named events until the
+/// returned task is aborted or the connection closes, in which case
+/// `on_close` is called.
+pub fn subscribe(
+    events: &[String],
+    on_event: impl Fn(&str, serde_json::Value) + 'static,
+    on_close: impl FnOnce() + 'static,
+) -> Result<glib::JoinHandle<()>, SwayError> {
+    for event in events {
+        if !EVENTS.iter().any(|(name, _)| name == event) {
+            return Err(SwayError::UnknownEvent(event.clone()));
+        }
+    }
+    let path = socket_path()?;
+    let payload = serde_json::to_vec(events)?;
+    Ok(glib::MainContext::default().spawn_local(async move {
+        let result: Result<(), SwayError> = async {
+            let mut stream = UnixStream::connect(&path).await?;
+            write_message(&mut stream, SUBSCRIBE, &payload).await?;
+            let (_, reply) = read_message(&mut stream).await?;
+            if reply["success"] != serde_json::Value::Bool(true) {
+                return Err(SwayError::InvalidReply(reply.to_string()));
+            }
+            loop {
+                let (kind, payload) = read_message(&mut stream).await?;
+                let kind = kind & !EVENT_BIT;
+                if let Some((name, _)) = EVENTS.iter().find(|(_, k)| *k == kind) {
+                    on_event(name, payload);
+                }
+            }
+        }
+        .await;
+        if let Err(e) = result {
+            eprintln!("Sway event error: {}", e);
+        }
+        on_close();
+    }))
+}
+
+#[derive(Clone)]
+struct SwaySubscription(
+    Rc<RefCell<Option<glib::JoinHandle<()>>>>,
+    lifecycle::CleanupSlot,
+);
+
+impl SwaySubscription {
+    fn disconnect(&self) {
+        self.1.remove();
+        if let Some(task) = self.0.borrow_mut().take() {
+            task.abort();
+        }
+    }
+}
+
+impl UserData for SwaySubscription {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("disconnect", |_, this, ()| {
+            this.disconnect();
+            Ok(())
+        });
+    }
+}
+
+/// Sends a message and calls `callback(result, err)` with the reply.
+fn dispatch(lua: &Lua, kind: u32, payload: String, callback: Option<Function>) {
+    let lua = lua.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let result = query(kind, &payload).await;
+        let Some(callback) = callback else {
+            if let Err(e) = result {
+                eprintln!("Sway IPC error: {}", e);
+            }
+            return;
+        };
+        let called = match result
+            .map_err(mlua::Error::from)
+            .and_then(|v| lua.to_value(&v))
+        {
+            Ok(value) => callback.call::<()>((value, Value::Nil)),
+            Err(e) => callback.call::<()>((Value::Nil, e.to_string())),
+        };
+        if let Err(e) = called {
+            eprintln!("Sway callback error: {}", e);
+        }
+    });
+}
+
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    let sway = lua.create_table()?;
+
+    for (name, kind) in [
+        ("get_workspaces", GET_WORKSPACES),
+        ("get_outputs", GET_OUTPUTS),
+        ("get_tree", GET_TREE),
+        ("get_marks", 5),
+        ("get_bar_config", 6),
+        ("get_version", 7),
+        ("get_binding_modes", 8),
+        ("get_config", 9),
+        ("get_binding_state", 12),
+        ("get_inputs", 100),
+        ("get_seats", 101),
+    ] {
+        sway.set(
+            name,
+            lua.create_function(move |lua, callback: Function| {
+                dispatch(lua, kind, String::new(), Some(callback));
+                Ok(())
+            })?,
+        )?;
+    }
+
+    sway.set(
+        "run_command",
+        lua.create_function(|lua, (command, callback): (String, Option<Function>)| {
+            dispatch(lua, RUN_COMMAND, command, callback);
+            Ok(())
+        })?,
+    )?;
+
+    sway.set(
+        "subscribe",
+        lua.create_function(|lua, (events, callback): (Vec<String>, Function)| {
+            let lua = lua.clone();
+            let cleanup = lifecycle::CleanupSlot::default();
+            let closed = cleanup.clone();
+            let task = subscribe(
+                &events,
+                move |name, payload| {
+                    let called = lua
+                        .to_value(&payload)
+                        .and_then(|payload| callback.call::<()>((name, payload)));
+                    if let Err(e) = called {
+                        eprintln!("Sway event callback error: {}", e);
+                    }
+                },
+                move || closed.remove(),
+            )?;
+            let subscription = SwaySubscription(Rc::new(RefCell::new(Some(task))), cleanup);
+            let tracked = subscription.clone();
+            subscription
+                .1
+                .set(lifecycle::track(move || tracked.disconnect()));
+            Ok(subscription)
+        })?,
+    )?;
+
+    sway.set("is_running", lua.create_function(|_, ()| Ok(is_running()))?)?;
+
+    lua.globals().set("Sway", sway)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EVENT_BIT, RUN_COMMAND, SUBSCRIBE, SwayError, read_message, write_message};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixStream;
+
+    #[tokio::test]
+    async fn messages_round_trip() {
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        write_message(&mut a, SUBSCRIBE, br#"["workspace","window"]"#)
+            .await
+            .unwrap();
+        write_message(&mut a, RUN_COMMAND, br#"[{"success":true}]"#)
+            .await
+            .unwrap();
+        write_message(&mut a, EVENT_BIT | 3, br#"{"change":"focus"}"#)
+            .await
+            .unwrap();
+
+        let (kind, payload) = read_message(&mut b).await.unwrap();
+        assert_eq!(kind, SUBSCRIBE);
+        assert_eq!(payload, serde_json::json!(["workspace", "window"]));
+        let (kind, payload) = read_message(&mut b).await.unwrap();
+        assert_eq!(kind, RUN_COMMAND);
+        assert_eq!(payload[0]["success"], true);
+        let (kind, payload) = read_message(&mut b).await.unwrap();
+        assert_eq!(kind, EVENT_BIT | 3);
+        assert_eq!(payload["change"], "focus");
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_magic() {
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        a.write_all(b"i3-ipx\0\0\0\0\0\0\0\0").await.unwrap();
+        assert!(matches!(
+            read_message(&mut b).await,
+            Err(SwayError::InvalidReply(e)) if e == "bad magic string"
+        ));
+    }
+}
@@ -0,0 +1,363 @@
+use crate::ui::lifecycle;
+use futures_util::StreamExt;
+use mlua::{Function, Lua, Result as LuaResult, Table, UserData, UserDataMethods};
+use serde::Deserialize;
+use std::fmt;
+use std::rc::Rc;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{OwnedObjectPath, Type};
+use zbus::{Connection, proxy};
+
+use super::dbus_client::{Bus, connection};
+
+#[derive(Debug)]
+pub enum SystemdError {
+    DbusError(zbus::Error),
+    InvalidBus(String),
+}
+
+impl fmt::Display for SystemdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemdError::DbusError(e) => write!(f, "DBus error: {}", e),
+            SystemdError::InvalidBus(e) => {
+                write!(f, "Invalid bus '{}', expected 'user' or 'system'", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SystemdError {}
+
+impl From<zbus::Error> for SystemdError {
+    fn from(err: zbus::Error) -> Self {
+        SystemdError::DbusError(err)
+    }
+}
+
+impl From<zbus::fdo::Error> for SystemdError {
+    fn from(err: zbus::fdo::Error) -> Self {
+        SystemdError::DbusError(err.into())
+    }
+}
+
+impl From<SystemdError> for mlua::Error {
+    fn from(err: SystemdError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+fn parse_bus(name: &str) -> std::result::Result<Bus, SystemdError> {
+    Bus::from_name(name).ok_or_else(|| SystemdError::InvalidBus(name.to_string()))
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Type)]
+pub struct ListedUnit {
+    pub name: String,
+    pub description: String,
+    pub load_state: String,
+    pub active_state: String,
+    pub sub_state: String,
+    pub followed: String,
+    pub path: OwnedObjectPath,
+    pub job_id: u32,
+    pub job_type: String,
+    pub job_path: OwnedObjectPath,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnitState {
+    pub name: String,
+    pub description: String,
+    pub load_state: String,
+    pub active_state: String,
+    pub sub_state: String,
+}
+
+impl UnitState {
+    fn to_lua_table(&self, lua: &Lua) -> LuaResult<Table> {
+        let table = lua.create_table_with_capacity(0, 5)?;
+        table.set("name", self.name.as_str())?;
+        table.set("description", self.description.as_str())?;
+        table.set("load_state", self.load_state.as_str())?;
+        table.set("active_state", self.active_state.as_str())?;
+        table.set("sub_state", self.sub_state.as_str())?;
+        Ok(table)
+    }
+}
+
+impl From<ListedUnit> for UnitState {
+    fn from(unit: ListedUnit) -> Self {
+        Self {
+            name: unit.name,
+            description: unit.description,
+            load_state: unit.load_state,
+            active_state: unit.active_state,
+            sub_state: unit.sub_state,
+        }
+    }
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait Manager {
+    fn list_units(&self) -> zbus::Result<Vec<ListedUnit>>;
+    fn load_unit(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+    fn start_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+    fn stop_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+    fn restart_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+    fn subscribe(&self) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Unit",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Unit {
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn description(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn load_state(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn active_state(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn sub_state(&self) -> zbus::Result<String>;
+}
+
+#[derive(Clone, Copy)]
+pub enum UnitJob {
+    Start,
+    Stop,
+    Restart,
+}
+
+pub struct SystemdClient {
+    conn: Connection,
+}
+
+impl SystemdClient {
+    pub async fn new(bus: Bus) -> std::result::Result<Self, SystemdError> {
+        Ok(Self {
+            conn: connection(bus).await?,
+        })
+    }
+
+    async fn manager(&self) -> std::result::Result<ManagerProxy<'_>, SystemdError> {
+        Ok(ManagerProxy::new(&self.conn).await?)
+    }
+
+    async fn unit(&self, name: &str) -> std::result::Result<UnitProxy<'_>, SystemdError> {
+        let path = self.manager().await?.load_unit(name).await?;
+        let unit = UnitProxy::builder(&self.conn)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        Ok(unit)
+    }
+
+    pub async fn list_units(&self) -> std::result::Result<Vec<UnitState>, SystemdError> {
+        let units = self.manager().await?.list_units().await?;
+        Ok(units.into_iter().map(UnitState::from).collect())
+    }
+
+    pub async fn get_unit(&self, name: &str) -> std::result::Result<UnitState, SystemdError> {
+        let unit = self.unit(name).await?;
+        Self::read_state(&unit).await
+    }
+
+    async fn read_state(unit: &UnitProxy<'_>) -> std::result::Result<UnitState, SystemdError> {
+        Ok(UnitState {
+            name: unit.id().await?,
+            description: unit.description().await?,
+            load_state: unit.load_state().await?,
+            active_state: unit.active_state().await?,
+            sub_state: unit.sub_state().await?,
+        })
+    }
+
+    pub async fn run_job(&self, name: &str, job: UnitJob) -> std::result::Result<(), SystemdError> {
+        let manager = self.manager().await?;
+        match job {
+            UnitJob::Start => manager.start_unit(name, "replace").await?,
+            UnitJob::Stop => manager.stop_unit(name, "replace").await?,
+            UnitJob::Restart => manager.restart_unit(name, "replace").await?,
+        };
+        Ok(())
+    }
+
+    pub async fn watch_unit<F>(
+        &self,
+        name: &str,
+        mut on_change: F,
+    ) -> std::result::Result<(), SystemdError>
+    where
+        F: FnMut(UnitState),
+    {
+        self.manager().await?.subscribe().await?;
+        let unit = self.unit(name).await?;
+        let properties = zbus::fdo::PropertiesProxy::builder(&self.conn)
+            .destination("org.freedesktop.systemd1")?
+            .path(unit.inner().path().to_owned())?
+            .build()
+            .await?;
+        let mut changes = properties.receive_properties_changed().await?;
+
+        let mut last = Self::read_state(&unit).await?;
+        on_change(last.clone());
+
+        while let Some(signal) = changes.next().await {
+            let Ok(args) = signal.args() else {
+                continue;
+            };
+            if args.interface_name() != "org.freedesktop.systemd1.Unit" {
+                continue;
+            }
+            let state = Self::read_state(&unit).await?;
+            if state.active_state != last.active_state || state.sub_state != last.sub_state {
+                on_change(state.clone());
+            }
+            last = state;
+        }
+        Ok(())
+    }
+}
+
+struct UnitWatcher {
+    handle: glib::JoinHandle<()>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl UserData for UnitWatcher {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("disconnect", |_, this, ()| {
+            this.cleanup.remove();
+            this.handle.abort();
+            Ok(())
+        });
+    }
+}
+
+fn call_lua_callback<A: mlua::IntoLuaMulti>(lua: &Lua, key: &mlua::RegistryKey, args: A) {
+    if let Ok(func) = lua.registry_value::<Function>(key)
+        && let Err(e) = func.call::<()>(args)
+    {
+        eprintln!("Systemd callback error: {}", e);
+    }
+}
+
+fn register_job(lua: &Lua, systemd: &Table, name: &str, job: UnitJob) -> LuaResult<()> {
+    systemd.set(
+        name,
+        lua.create_function(move |lua, (unit, callback): (String, Option<Function>)| {
+            let lua = lua.clone();
+            let cb_key = callback.map(|f| lua.create_registry_value(f)).transpose()?;
+            glib::MainContext::default().spawn_local(async move {
+                let result = match SystemdClient::new(Bus::Session).await {
+                    Ok(client) => client.run_job(&unit, job).await,
+                    Err(e) => Err(e),
+                };
+                match (cb_key, result) {
+                    (Some(key), Ok(())) => call_lua_callback(&lua, &key, true),
+                    (Some(key), Err(e)) => call_lua_callback(&lua, &key, (false, e.to_string())),
+                    (None, Err(e)) => eprintln!("Systemd job for '{}' failed: {}", unit, e),
+                    (None, Ok(())) => {}
+                }
+            });
+            Ok(())
+        })?,
+    )
+}
+
+pub fn register(lua: Rc<Lua>) -> LuaResult<()> {
+    let systemd = lua.create_table()?;
+
+    systemd.set(
+        "list_units",
+        lua.create_function(|lua, (bus, callback): (String, Function)| {
+            let bus = parse_bus(&bus)?;
+            let lua = lua.clone();
+            let cb_key = lua.create_registry_value(callback)?;
+            glib::MainContext::default().spawn_local(async move {
+                let result = match SystemdClient::new(bus).await {
+                    Ok(client) => client.list_units().await,
+                    Err(e) => Err(e),
+                };
+                let lua_result = result.map_err(|e| e.to_string()).and_then(|units| {
+                    let table = lua.create_table().map_err(|e| e.to_string())?;
+                    for (i, unit) in units.iter().enumerate() {
+                        let unit_table = unit.to_lua_table(&lua).map_err(|e| e.to_string())?;
+                        table.set(i + 1, unit_table).map_err(|e| e.to_string())?;
+                    }
+                    Ok(table)
+                });
+                call_lua_callback(&lua, &cb_key, lua_result);
+            });
+            Ok(())
+        })?,
+    )?;
+
+    systemd.set(
+        "get_unit",
+        lua.create_function(|lua, (bus, name, callback): (String, String, Function)| {
+            let bus = parse_bus(&bus)?;
+            let lua = lua.clone();
+            let cb_key = lua.create_registry_value(callback)?;
+            glib::MainContext::default().spawn_local(async move {
+                let result = match SystemdClient::new(bus).await {
+                    Ok(client) => client.get_unit(&name).await,
+                    Err(e) => Err(e),
+                };
+                let lua_result = result
+                    .map_err(|e| e.to_string())
+                    .and_then(|unit| unit.to_lua_table(&lua).map_err(|e| e.to_string()));
+                call_lua_callback(&lua, &cb_key, lua_result);
+            });
+            Ok(())
+        })?,
+    )?;
+
+    systemd.set(
+        "watch",
+        lua.create_function(|lua, (bus, name, callback): (String, String, Function)| {
+            let bus = parse_bus(&bus)?;
+            let lua = lua.clone();
+            let cb_key = lua.create_registry_value(callback)?;
+            let cleanup = lifecycle::CleanupSlot::default();
+            let finished = cleanup.clone();
+            let handle = glib::MainContext::default().spawn_local(async move {
+                let result = match SystemdClient::new(bus).await {
+                    Ok(client) => {
+                        client
+                            .watch_unit(&name, |state| match state.to_lua_table(&lua) {
+                                Ok(table) => call_lua_callback(&lua, &cb_key, table),
+                                Err(e) => eprintln!("Systemd watch error: {}", e),
+                            })
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    eprintln!("Systemd watch for '{}' stopped: {}", name, e);
+                }
+                finished.remove();
+            });
+            let source = handle.source().clone();
+            cleanup.set(lifecycle::track(move || source.destroy()));
+            Ok(UnitWatcher { handle, cleanup })
+        })?,
+    )?;
+
+    register_job(&lua, &systemd, "start", UnitJob::Start)?;
+    register_job(&lua, &systemd, "stop", UnitJob::Stop)?;
+    register_job(&lua, &systemd, "restart", UnitJob::Restart)?;
+
+    lua.globals().set("Systemd", systemd)?;
+    Ok(())
+}
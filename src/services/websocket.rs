@@ -0,0 +1,366 @@
+use crate::ui::lifecycle;
+use futures_util::{SinkExt, StreamExt};
+use gtk4::glib;
+use mlua::{BString, Function, Lua, Result as LuaResult, Table, UserData, UserDataMethods, Value};
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::{HeaderMap, HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    ConnectionError(tungstenite::Error),
+    InvalidUrl(String),
+    InvalidHeader(String),
+    Closed,
+}
+
+impl fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebSocketError::ConnectionError(e) => write!(f, "WebSocket error: {}", e),
+            WebSocketError::InvalidUrl(u) => write!(f, "Invalid WebSocket url '{}'", u),
+            WebSocketError::InvalidHeader(h) => write!(f, "Invalid header '{}'", h),
+            WebSocketError::Closed => write!(f, "WebSocket is closed"),
+        }
+    }
+}
+
+impl std::error::Error for WebSocketError {}
+
+impl From<tungstenite::Error> for WebSocketError {
+    fn from(err: tungstenite::Error) -> Self {
+        WebSocketError::ConnectionError(err)
+    }
+}
+
+impl From<WebSocketError> for mlua::Error {
+    fn from(err: WebSocketError) -> Self {
+        mlua::Error::external(err)
+    }
+}
+
+/// The Lua callbacks of a connection, all optional.
+struct Callbacks {
+    open: Option<Function>,
+    message: Option<Function>,
+    pong: Option<Function>,
+    close: Option<Function>,
+    error: Option<Function>,
+}
+
+impl Callbacks {
+    fn from_table(table: &Table) -> LuaResult<Self> {
+        Ok(Self {
+            open: table.get("open")?,
+            message: table.get("message")?,
+            pong: table.get("pong")?,
+            close: table.get("close")?,
+            error: table.get("error")?,
+        })
+    }
+
+    fn call(callback: &Option<Function>, args: impl mlua::IntoLuaMulti) {
+        if let Some(callback) = callback
+            && let Err(e) = callback.call::<()>(args)
+        {
+            eprintln!("WebSocket callback error: {}", e);
+        }
+    }
+}
+
+/// Reconnect delays, doubling from `delay` up to `max_delay`.
+#[derive(Clone, Copy)]
+struct Backoff {
+    delay: Duration,
+    max_delay: Duration,
+}
+
+impl Backoff {
+    fn from_value(value: Value) -> LuaResult<Option<Self>> {
+        let mut backoff = Self {
+            delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        };
+        match value {
+            Value::Boolean(false) => return Ok(None),
+            Value::Table(t) => {
+                if let Some(ms) = t.get::<Option<u64>>("delay")? {
+                    backoff.delay = Duration::from_millis(ms);
+                }
+                if let Some(ms) = t.get::<Option<u64>>("max_delay")? {
+                    backoff.max_delay = Duration::from_millis(ms);
+                }
+            }
+            _ => {}
+        }
+        Ok(Some(backoff))
+    }
+}
+
+enum Command {
+    Send(Message),
+    Close(u16, String),
+}
+
+#[derive(Clone)]
+struct LuaWebSocket {
+    commands: UnboundedSender<Command>,
+    open: Rc<Cell<bool>>,
+    closed: Rc<Cell<bool>>,
+    cleanup: lifecycle::CleanupSlot,
+}
+
+impl LuaWebSocket {
+    fn send(&self, message: Message) -> Result<(), WebSocketError> {
+        if self.closed.get() {
+            return Err(WebSocketError::Closed);
+        }
+        self.commands
+            .send(Command::Send(message))
+            .map_err(|_| WebSocketError::Closed)
+    }
+
+    fn close(&self, code: u16, reason: String) {
+        self.cleanup.remove();
+        if !self.closed.replace(true) {
+            let _ = self.commands.send(Command::Close(code, reason));
+        }
+    }
+}
+
+impl UserData for LuaWebSocket {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("send", |_, this, text: String| {
+            Ok(this.send(Message::text(text))?)
+        });
+        methods.add_method("send_binary", |_, this, data: mlua::String| {
+            Ok(this.send(Message::binary(data.as_bytes().to_vec()))?)
+        });
+        methods.add_method("ping", |_, this, data: Option<mlua::String>| {
+            let data = data.map(|d| d.as_bytes().to_vec()).unwrap_or_default();
+            Ok(this.send(Message::Ping(data.into()))?)
+        });
+        methods.add_method(
+            "close",
+            |_, this, (code, reason): (Option<u16>, Option<String>)| {
+                this.close(code.unwrap_or(1000), reason.unwrap_or_default());
+                Ok(())
+            },
+        );
+        methods.add_method("is_open", |_, this, ()| Ok(this.open.get()));
+    }
+}
+
+/// The handshake request, rebuilt for every reconnect.
+struct Target {
+    url: String,
+    headers: HeaderMap,
+}
+
+impl Target {
+    fn new(url: String, headers: Option<Table>) -> LuaResult<Self> {
+        url.as_str()
+            .into_client_request()
+            .map_err(|_| WebSocketError::InvalidUrl(url.clone()))?;
+        let mut map = HeaderMap::new();
+        if let Some(headers) = headers {
+            for pair in headers.pairs::<String, String>() {
+                let (name, value) = pair?;
+                let header = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| WebSocketError::InvalidHeader(name.clone()))?;
+                let value = HeaderValue::from_str(&value)
+                    .map_err(|_| WebSocketError::InvalidHeader(name))?;
+                map.insert(header, value);
+            }
+        }
+        Ok(Self { url, headers: map })
+    }
+
+    fn request(&self) -> Result<Request, WebSocketError> {
+        let mut request = self.url.as_str().into_client_request()?;
+        request.headers_mut().extend(self.headers.clone());
+        Ok(request)
+    }
+}
+
+enum Disconnect {
+    /// The connection ended and may be retried.
+    Lost,
+    /// `close()` was called, or the handle went away.
+    Closed,
+}
+
+/// Runs one connection until it ends, dispatching messages to the callbacks
+/// and forwarding commands from the handle. `connected` is set once the
+/// handshake succeeds.
+async fn run_connection(
+    target: &Target,
+    callbacks: &Callbacks,
+    commands: &mut UnboundedReceiver<Command>,
+    open: &Cell<bool>,
+    connected: &mut bool,
+    ping_interval: Option<Duration>,
+) -> Result<Disconnect, WebSocketError> {
+    let (socket, _) = tokio_tungstenite::connect_async(target.request()?).await?;
+    let (mut sink, mut stream) = socket.split();
+    *connected = true;
+    open.set(true);
+    Callbacks::call(&callbacks.open, ());
+
+    let mut pings = ping_interval.map(glib::interval_stream);
+    let mut closing = false;
+    loop {
+        tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    Callbacks::call(&callbacks.message, (text.as_str(), false));
+                }
+                Some(Ok(Message::Binary(data))) => {
+                    Callbacks::call(&callbacks.message, (BString::from(data.to_vec()), true));
+                }
+                Some(Ok(Message::Pong(data))) => {
+                    Callbacks::call(&callbacks.pong, BString::from(data.to_vec()));
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    open.set(false);
+                    let (code, reason) = frame
+                        .map(|f| (u16::from(f.code), f.reason.to_string()))
+                        .unwrap_or((1005, String::new()));
+                    Callbacks::call(&callbacks.close, (code, reason));
+                    return Ok(if closing { Disconnect::Closed } else { Disconnect::Lost });
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    open.set(false);
+                    Callbacks::call(&callbacks.close, (1006, ""));
+                    return Err(e.into());
+                }
+                None => {
+                    open.set(false);
+                    Callbacks::call(&callbacks.close, (1006, ""));
+                    return Ok(if closing { Disconnect::Closed } else { Disconnect::Lost });
+                }
+            },
+            command = commands.recv(), if !closing => match command {
+                Some(Command::Send(message)) => sink.send(message).await?,
+                Some(Command::Close(code, reason)) => {
+                    closing = true;
+                    let frame = CloseFrame {
+                        code: CloseCode::from(code),
+                        reason: reason.into(),
+                    };
+                    sink.send(Message::Close(Some(frame))).await?;
+                }
+                None => {
+                    open.set(false);
+                    return Ok(Disconnect::Closed);
+                }
+            },
+            Some(()) = async {
+                match pings.as_mut() {
+                    Some(pings) => pings.next().await,
+                    None => std::future::pending().await,
+                }
+            } => sink.send(Message::Ping(Vec::new().into())).await?,
+        }
+    }
+}
+
+fn connect(
+    lua: &Lua,
+    url: String,
+    headers: Option<Table>,
+    opts: Option<Table>,
+) -> LuaResult<LuaWebSocket> {
+    let target = Target::new(url, headers)?;
+    let opts = match opts {
+        Some(opts) => opts,
+        None => lua.create_table()?,
+    };
+    let callbacks = Callbacks::from_table(&opts)?;
+    let backoff = Backoff::from_value(opts.get("reconnect")?)?;
+    let ping_interval = opts
+        .get::<Option<u64>>("ping_interval")?
+        .map(Duration::from_millis);
+
+    let (tx, mut rx) = unbounded_channel();
+    let socket = LuaWebSocket {
+        commands: tx,
+        open: Rc::new(Cell::new(false)),
+        closed: Rc::new(Cell::new(false)),
+        cleanup: Default::default(),
+    };
+
+    glib::MainContext::default().spawn_local({
+        let open = socket.open.clone();
+        let closed = socket.closed.clone();
+        let cleanup = socket.cleanup.clone();
+        async move {
+            let mut delay = backoff.map(|b| b.delay);
+            loop {
+                let mut connected = false;
+                let result = run_connection(
+                    &target,
+                    &callbacks,
+                    &mut rx,
+                    &open,
+                    &mut connected,
+                    ping_interval,
+                )
+                .await;
+                open.set(false);
+                match result {
+                    Ok(Disconnect::Closed) => break,
+                    Ok(Disconnect::Lost) => {}
+                    Err(e) => Callbacks::call(&callbacks.error, e.to_string()),
+                }
+                let Some(backoff) = backoff else {
+                    break;
+                };
+                // A connection that was established starts the backoff over.
+                if connected {
+                    delay = Some(backoff.delay);
+                }
+                let wait = delay.unwrap_or(backoff.delay);
+                if closed.get() {
+                    break;
+                }
+                glib::timeout_future(wait).await;
+                if closed.get() {
+                    break;
+                }
+                delay = Some((wait * 2).min(backoff.max_delay));
+            }
+            closed.set(true);
+            cleanup.remove();
+        }
+    });
+
+    let tracked = socket.clone();
+    socket
+        .cleanup
+        .set(lifecycle::track(move || tracked.close(1001, String::new())));
+    Ok(socket)
+}
+
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    let websocket = lua.create_table()?;
+    websocket.set(
+        "connect",
+        lua.create_function(
+            |lua, (url, headers, opts): (String, Option<Table>, Option<Table>)| {
+                connect(lua, url, headers, opts)
+            },
+        )?,
+    )?;
+    lua.globals().set("WebSocket", websocket)?;
+    Ok(())
+}
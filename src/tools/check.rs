@@ -0,0 +1,350 @@
+use crate::app::prepare_lua_environment;
+use crate::core::context::AppContext;
+use crate::core::control;
+use crate::core::error::AppError;
+use crate::interop::converter::GenericConverter;
+use crate::scripting::globals;
+use crate::scripting::lua_driver::LuaWrapper;
+use crate::scripting::state::LuaBinding;
+use crate::ui::builder::UiBuilder;
+use crate::ui::registry::Registry;
+use crate::ui::schema::{CONTAINER_PROPERTIES, ConfigSchema, SchemaError, WidgetSchema, suggest};
+use gtk4::glib::object::ObjectClass;
+use gtk4::glib::subclass::SignalId;
+use gtk4::glib::{ParamFlags, Type};
+use gtk4::prelude::*;
+use mlua::{Function, Lua, Table, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub location: Option<SourceLocation>,
+    pub node: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}:{}: ", location.file, location.line)?;
+        }
+        write!(f, "{} ({})", self.message, self.node)
+    }
+}
+
+fn function_location(func: &Function) -> Option<SourceLocation> {
+    let info = func.info();
+    let file = info.source?.strip_prefix('@')?.to_string();
+    Some(SourceLocation {
+        file,
+        line: info.line_defined?,
+    })
+}
+
+/// Tables don't record where they were constructed, so diagnostics point at
+/// the closest function defined inside the widget, or have no line at all.
+struct Checker {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    fn report(&mut self, node: &str, location: Option<SourceLocation>, message: String) {
+        self.diagnostics.push(Diagnostic {
+            location,
+            node: node.to_string(),
+            message,
+        });
+    }
+
+    fn report_schema_errors(
+        &mut self,
+        node: &str,
+        anchor: Option<&SourceLocation>,
+        errors: Vec<SchemaError>,
+    ) {
+        for error in errors {
+            self.report(node, anchor.cloned(), error.message);
+        }
+    }
+
+    /// Finds a function defined inside the widget table, whose debug info
+    /// gives the closest known source line for the table itself.
+    fn anchor_of(table: &Table) -> Option<SourceLocation> {
+        for (_, value) in table.pairs::<Value, Value>().flatten() {
+            match value {
+                Value::Function(f) => return function_location(&f),
+                Value::Table(t) => {
+                    if let Some(location) = t
+                        .pairs::<Value, Value>()
+                        .flatten()
+                        .find_map(|(_, v)| v.as_function().and_then(function_location))
+                    {
+                        return Some(location);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn check_config(&mut self, table: &Table) {
+        let is_array = matches!(
+            table.raw_get::<Value>(1),
+            Ok(Value::Table(_) | Value::Function(_))
+        );
+        if let Err(errors) = ConfigSchema::from_script(&LuaWrapper(Value::Table(table.clone()))) {
+            self.report_schema_errors("config", None, errors);
+        }
+
+        if is_array {
+            for (i, window) in table.sequence_values::<Value>().flatten().enumerate() {
+                let node = format!("window[{}]", i + 1);
+                match window {
+                    Value::Table(window) => self.check_widget(&window, node, None, true),
+                    // Built per monitor at runtime, so there is no table to check.
+                    Value::Function(_) => {}
+                    other => self.report(
+                        &node,
+                        None,
+                        format!("Expected a window table, got {}", other.type_name()),
+                    ),
+                }
+            }
+        } else {
+            self.check_widget(table, "window".to_string(), None, true);
+        }
+    }
+
+    fn check_widget(
+        &mut self,
+        table: &Table,
+        parent: String,
+        anchor: Option<SourceLocation>,
+        top_level: bool,
+    ) {
+        let anchor = Self::anchor_of(table).or(anchor);
+        let anchor = anchor.as_ref();
+
+        let type_value = table.get::<Value>("type").unwrap_or(Value::Nil);
+        let type_name = match &type_value {
+            Value::Nil if top_level => "GtkWindow".to_string(),
+            Value::Nil => "?".to_string(),
+            Value::String(s) => s.to_string_lossy(),
+            other => {
+                self.report(
+                    &parent,
+                    anchor.cloned(),
+                    format!("'type' must be a string, got {}", other.type_name()),
+                );
+                return;
+            }
+        };
+        let mut node = format!("{} > {}", parent, type_name);
+        if let Ok(id) = table.get::<String>("id") {
+            node = format!("{}#{}", node, id);
+        }
+
+        let data = LuaWrapper(Value::Table(table.clone()));
+        let schema = if top_level {
+            WidgetSchema::from_script(&data)
+        } else {
+            WidgetSchema::from_child_script(&data)
+        };
+        if let Err(errors) = schema {
+            self.report_schema_errors(&node, anchor, errors);
+        }
+
+        let Some(gtype) = Registry::get_type(&type_name) else {
+            return;
+        };
+
+        self.check_properties(table, gtype, &node, anchor);
+        self.check_signals(table, gtype, &node, anchor);
+
+        match table.get::<Value>("children") {
+            Ok(Value::Table(children)) => {
+                for (i, child) in children.sequence_values::<Value>().flatten().enumerate() {
+                    match child {
+                        Value::Table(child) => {
+                            self.check_widget(&child, node.clone(), anchor.cloned(), false)
+                        }
+                        other => self.report(
+                            &node,
+                            anchor.cloned(),
+                            format!("Child {} must be a table, got {}", i + 1, other.type_name()),
+                        ),
+                    }
+                }
+            }
+            Ok(Value::Nil) => {}
+            Ok(other) => self.report(
+                &node,
+                anchor.cloned(),
+                format!("'children' must be a table, got {}", other.type_name()),
+            ),
+            Err(_) => {}
+        }
+    }
+
+    fn check_properties(
+        &mut self,
+        table: &Table,
+        gtype: Type,
+        node: &str,
+        anchor: Option<&SourceLocation>,
+    ) {
+        let Ok(properties) = table.get::<Table>("properties") else {
+            return;
+        };
+        let Some(class) = ObjectClass::from_type(gtype) else {
+            return;
+        };
+        for (key, value) in properties.pairs::<String, Value>().flatten() {
+            if CONTAINER_PROPERTIES.contains(&key.as_str()) {
+                continue;
+            }
+            let Some(pspec) = class.find_property(&key.replace('_', "-")) else {
+                let names: Vec<String> = class
+                    .list_properties()
+                    .iter()
+                    .map(|p| p.name().replace('-', "_"))
+                    .collect();
+                let suggestion = suggest(&key, names.iter().map(String::as_str))
+                    .map(|s| format!(". Did you mean '{}'?", s))
+                    .unwrap_or_default();
+                self.report(
+                    node,
+                    anchor.cloned(),
+                    format!(
+                        "Property '{}' not found on type '{}'{}",
+                        key,
+                        gtype.name(),
+                        suggestion
+                    ),
+                );
+                continue;
+            };
+            let flags = pspec.flags();
+            if !flags.contains(ParamFlags::WRITABLE) || flags.contains(ParamFlags::CONSTRUCT_ONLY) {
+                self.report(
+                    node,
+                    anchor.cloned(),
+                    format!(
+                        "Property '{}' on type '{}' is not writable",
+                        key,
+                        gtype.name()
+                    ),
+                );
+                continue;
+            }
+            let current = match LuaBinding::from_value(&value) {
+                Some(binding) => match binding.value() {
+                    Ok(current) => current,
+                    Err(e) => {
+                        self.report(
+                            node,
+                            anchor.cloned(),
+                            format!("Binding for property '{}' failed: {}", key, e),
+                        );
+                        continue;
+                    }
+                },
+                None => value.clone(),
+            };
+            if let Err(e) = GenericConverter::to_gvalue(&LuaWrapper(current), pspec.value_type()) {
+                self.report(
+                    node,
+                    anchor.cloned(),
+                    format!(
+                        "Invalid value for property '{}' ({}): {}",
+                        key,
+                        pspec.value_type().name(),
+                        e
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_signals(
+        &mut self,
+        table: &Table,
+        gtype: Type,
+        node: &str,
+        anchor: Option<&SourceLocation>,
+    ) {
+        let Ok(signals) = table.get::<Table>("signals") else {
+            return;
+        };
+        for (name, value) in signals.pairs::<String, Value>().flatten() {
+            let location = value.as_function().and_then(function_location);
+            let signal = name.split("::").next().unwrap_or(&name).replace('_', "-");
+            if SignalId::lookup(&signal, gtype).is_none() {
+                let location = location.or_else(|| anchor.cloned());
+                self.report(
+                    node,
+                    location,
+                    format!(
+                        "Signal '{}' does not exist on type '{}'",
+                        name,
+                        gtype.name()
+                    ),
+                );
+            } else if !value.is_function() {
+                self.report(
+                    node,
+                    anchor.cloned(),
+                    format!("Handler for signal '{}' must be a function", name),
+                );
+            }
+        }
+    }
+}
+
+fn load_config(lua: &Lua, main_file: &Path) -> mlua::Result<Table> {
+    prepare_lua_environment(lua, main_file)?;
+    let code = fs::read_to_string(main_file).map_err(mlua::Error::external)?;
+    let value = lua
+        .load(&code)
+        .set_name(format!("@{}", main_file.display()))
+        .eval::<Value>()?;
+    match value {
+        Value::Table(table) => Ok(table),
+        _ => Err(mlua::Error::runtime("Lua script must return a UI Table")),
+    }
+}
+
+/// Loads the config without building windows or running the main loop, and
+/// returns every problem found in the returned UI table.
+pub fn run(main_file: &Path, instance: &str) -> Result<Vec<Diagnostic>, AppError> {
+    let _ = gtk4::init();
+    let app = gtk4::Application::builder()
+        .application_id(control::application_id(instance))
+        .build();
+    let lua = Rc::new(Lua::new());
+    let ui_builder = Rc::new(RefCell::new(UiBuilder::new(lua.clone())));
+    let context = Arc::new(AppContext::new(
+        main_file.to_path_buf(),
+        instance.to_string(),
+    ));
+    globals::register(lua.clone(), app, context, ui_builder)?;
+
+    let config = load_config(&lua, main_file)?;
+    let mut checker = Checker {
+        diagnostics: Vec::new(),
+    };
+    checker.check_config(&config);
+    Ok(checker.diagnostics)
+}
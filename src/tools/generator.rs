@@ -1,4 +1,5 @@
 use crate::ui::registry::Registry;
+use crate::ui::schema::{KeyScope, keys_in};
 use gtk4::glib::object::ObjectClass;
 use gtk4::prelude::*;
 use include_dir::{Dir, include_dir};
@@ -61,11 +62,26 @@ fn generate_definitions(path: &Path) -> std::io::Result<()> {
 
         widgets_data.push(serde_json::json!({
             "name": name,
+            "is_window": t.is_a(gtk4::Window::static_type()),
             "properties": properties,
         }));
     }
 
+    let keys = |scopes: &[KeyScope]| {
+        keys_in(scopes)
+            .map(|k| {
+                serde_json::json!({
+                    "name": k.name,
+                    "type": k.lua_type,
+                    "description": k.description,
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+
     context.insert("widgets", &widgets_data);
+    context.insert("widget_keys", &keys(&[KeyScope::Widget]));
+    context.insert("window_keys", &keys(&[KeyScope::Window, KeyScope::Config]));
 
     let rendered = tera
         .render("definitions.lua.tera", &context)
@@ -1 +1,3 @@
+pub mod check;
 pub mod generator;
+pub mod repl;
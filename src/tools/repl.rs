@@ -0,0 +1,118 @@
+use crate::core::config::Message;
+use crate::core::control::{ControlClient, ControlError, completion_word};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+struct ReplHelper {
+    client: Rc<RefCell<ControlClient>>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let word = completion_word(line);
+        let message = Message::Complete {
+            line: line.to_string(),
+        };
+        let candidates = match self.client.borrow_mut().request(&message) {
+            Ok(Some(JsonValue::Array(names))) => names
+                .into_iter()
+                .filter_map(|n| n.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok((pos - word.len(), candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+fn history_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(home).join(".cache/ink/repl_history")
+}
+
+fn is_incomplete(error: &ControlError) -> bool {
+    matches!(error, ControlError::Remote(e) if e.contains("<eof>"))
+}
+
+pub fn run(instance: &str) -> Result<(), ControlError> {
+    let client = Rc::new(RefCell::new(ControlClient::connect(instance)?));
+    let mut editor: Editor<ReplHelper, DefaultHistory> =
+        Editor::new().map_err(std::io::Error::other)?;
+    editor.set_helper(Some(ReplHelper {
+        client: client.clone(),
+    }));
+
+    let history = history_path();
+    if let Some(parent) = history.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let _ = editor.load_history(&history);
+
+    println!(
+        "Connected to ink instance '{}'. Press Ctrl-D to exit.",
+        instance
+    );
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() { "> " } else { ">> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(std::io::Error::other(e).into()),
+        };
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+        if buffer.trim().is_empty() {
+            buffer.clear();
+            continue;
+        }
+
+        let message = Message::Repl {
+            code: buffer.clone(),
+        };
+        match client.borrow_mut().request(&message) {
+            Ok(Some(JsonValue::String(output))) if !output.is_empty() => println!("{}", output),
+            Ok(_) => {}
+            Err(e) if is_incomplete(&e) => continue,
+            Err(e @ ControlError::Remote(_)) => eprintln!("{}", e),
+            Err(e) => return Err(e),
+        }
+        let _ = editor.add_history_entry(buffer.as_str());
+        buffer.clear();
+    }
+
+    let _ = editor.save_history(&history);
+    Ok(())
+}
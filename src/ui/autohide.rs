@@ -0,0 +1,217 @@
+use crate::services::compositor::{self, Snapshot};
+use crate::ui::lifecycle;
+use crate::ui::schema::{Autohide, AutohideWhen};
+use gtk4::glib;
+use gtk4::prelude::*;
+use gtk4_layer_shell::{Edge, LayerShell};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Slides a layer shell window off `edge` by lowering its margin there,
+/// leaving `size` pixels on screen that reveal it again when hovered.
+struct Controller {
+    window: glib::WeakRef<gtk4::Window>,
+    options: Autohide,
+    edge: Edge,
+    margin: i32,
+    hovered: Cell<bool>,
+    fullscreen: Cell<bool>,
+    /// 0 when shown, 1 when hidden.
+    progress: Cell<f64>,
+    target: Cell<f64>,
+    timer: RefCell<Option<glib::SourceId>>,
+    tick: RefCell<Option<gtk4::TickCallbackId>>,
+    last_frame: Cell<Option<i64>>,
+}
+
+impl Controller {
+    fn should_hide(&self) -> bool {
+        !self.hovered.get() && (self.options.when == AutohideWhen::Always || self.fullscreen.get())
+    }
+
+    /// Moves towards the wanted state after the show or hide delay.
+    fn update(self: &Rc<Self>) {
+        if let Some(timer) = self.timer.borrow_mut().take() {
+            timer.remove();
+        }
+        let hide = self.should_hide();
+        let target = if hide { 1.0 } else { 0.0 };
+        if target == self.target.get() {
+            return;
+        }
+        let delay = match (hide, self.hovered.get()) {
+            (true, _) => self.options.hide_delay,
+            (false, true) => self.options.show_delay,
+            (false, false) => 0,
+        };
+        if delay == 0 {
+            self.animate(target);
+            return;
+        }
+        let controller = Rc::downgrade(self);
+        let timer = glib::timeout_add_local_once(Duration::from_millis(delay as u64), move || {
+            if let Some(controller) = controller.upgrade() {
+                controller.timer.borrow_mut().take();
+                controller.animate(target);
+            }
+        });
+        *self.timer.borrow_mut() = Some(timer);
+    }
+
+    fn animate(self: &Rc<Self>, target: f64) {
+        self.target.set(target);
+        let Some(window) = self.window.upgrade() else {
+            return;
+        };
+        if self.options.duration == 0 || !window.is_mapped() {
+            self.progress.set(target);
+            self.apply(&window);
+            return;
+        }
+        if self.tick.borrow().is_some() {
+            return;
+        }
+        self.last_frame.set(None);
+        let controller = Rc::downgrade(self);
+        let tick = window.add_tick_callback(move |window, clock| {
+            let Some(controller) = controller.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            let now = clock.frame_time();
+            let elapsed = controller
+                .last_frame
+                .replace(Some(now))
+                .map_or(0, |last| now - last);
+            let step = elapsed as f64 / (controller.options.duration as f64 * 1000.0);
+            let progress = controller.progress.get();
+            let target = controller.target.get();
+            let next = if target > progress {
+                (progress + step).min(target)
+            } else {
+                (progress - step).max(target)
+            };
+            controller.progress.set(next);
+            controller.apply(window);
+            if next == target {
+                controller.tick.borrow_mut().take();
+                return glib::ControlFlow::Break;
+            }
+            glib::ControlFlow::Continue
+        });
+        *self.tick.borrow_mut() = Some(tick);
+    }
+
+    fn apply(&self, window: &gtk4::Window) {
+        let extent = match self.edge {
+            Edge::Top | Edge::Bottom => window.height(),
+            _ => window.width(),
+        };
+        let hidden = (extent - self.options.size).max(0) as f64;
+        let offset = (hidden * ease(self.progress.get())).round() as i32;
+        window.set_margin(self.edge, self.margin - offset);
+    }
+
+    fn on_snapshot(self: &Rc<Self>, snapshot: &Snapshot) {
+        let Some(window) = self.window.upgrade() else {
+            return;
+        };
+        let output = window
+            .surface()
+            .and_then(|surface| surface.display().monitor_at_surface(&surface))
+            .and_then(|monitor| monitor.connector())
+            .map(|c| c.to_string());
+        let shown: Vec<i64> = snapshot
+            .workspaces
+            .iter()
+            .filter(|ws| ws.active && (output.is_none() || ws.output == output))
+            .map(|ws| ws.id)
+            .collect();
+        let fullscreen = snapshot
+            .toplevels
+            .iter()
+            .any(|t| t.fullscreen && t.workspace.is_some_and(|id| shown.contains(&id)));
+        if self.fullscreen.replace(fullscreen) != fullscreen {
+            self.update();
+        }
+    }
+}
+
+fn ease(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Makes `window` hide itself on `edge`, whose configured margin is `margin`.
+pub fn attach(window: &gtk4::Window, options: Autohide, edge: Edge, margin: i32) {
+    let controller = Rc::new(Controller {
+        window: window.downgrade(),
+        options,
+        edge,
+        margin,
+        hovered: Cell::new(false),
+        fullscreen: Cell::new(false),
+        progress: Cell::new(0.0),
+        target: Cell::new(0.0),
+        timer: RefCell::new(None),
+        tick: RefCell::new(None),
+        last_frame: Cell::new(None),
+    });
+
+    let motion = gtk4::EventControllerMotion::new();
+    motion.connect_enter({
+        let controller = controller.clone();
+        move |_, _, _| {
+            controller.hovered.set(true);
+            controller.update();
+        }
+    });
+    motion.connect_leave({
+        let controller = controller.clone();
+        move |_| {
+            controller.hovered.set(false);
+            controller.update();
+        }
+    });
+    window.add_controller(motion);
+
+    window.connect_map({
+        let controller = controller.clone();
+        move |_| controller.update()
+    });
+    if window.is_mapped() {
+        controller.update();
+    }
+
+    // Only fullscreen windows matter, so a window that always hides does
+    // not need the compositor.
+    let wants_compositor = options.when == AutohideWhen::Fullscreen;
+    let subscription = if wants_compositor && compositor::backend().is_ok() {
+        let weak = Rc::downgrade(&controller);
+        let subscribed = compositor::subscribe(move |snapshot| {
+            if let Some(controller) = weak.upgrade() {
+                controller.on_snapshot(snapshot);
+            }
+        });
+        match subscribed {
+            Ok(id) => Some(id),
+            Err(e) => {
+                eprintln!("Autohide: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if subscription.is_none() && wants_compositor {
+        eprintln!("Warn: autohide = \"fullscreen\" needs a supported compositor");
+    }
+
+    lifecycle::on_destroy(window.upcast_ref(), move |_| {
+        if let Some(id) = subscription {
+            compositor::unsubscribe(id);
+        }
+        if let Some(timer) = controller.timer.borrow_mut().take() {
+            timer.remove();
+        }
+    });
+}
@@ -1,6 +1,8 @@
 use crate::interop::{converter::GenericConverter, signals::SignalConnector};
 use crate::scripting::traits::ScriptValue;
+use crate::ui::lifecycle;
 use crate::ui::registry::Registry;
+use crate::ui::schema::{CONTAINER_PROPERTIES, WidgetSchema, join_errors, suggest};
 use crate::ui::traits::WidgetBehavior;
 use gtk4::Widget;
 use gtk4::glib::{GString, Object};
@@ -11,31 +13,12 @@ use std::collections::HashMap;
 use std::path::Path;
 use std::rc::Rc;
 
+use crate::scripting::drawing;
 use crate::scripting::globals::get_core_context;
 use crate::scripting::lua_driver::LuaWrapper;
+use crate::scripting::state::LuaBinding;
 use crate::scripting::widget_wrapper::LuaWidget as WidgetWrapper;
 
-const ALLOWED_TOP_LEVEL_KEYS: &[&str] = &[
-    "id",
-    "type",
-    "properties",
-    "signals",
-    "children",
-    "window_mode",
-    "layer",
-    "anchors",
-    "margins",
-    "auto_exclusive_zone",
-    "keyboard_mode",
-    "css",
-    "css_path",
-    "realize",
-    "actions",
-    "menu",
-    "draw",
-    "keymaps",
-];
-
 pub struct UiBuilder {
     behaviors: HashMap<String, Box<dyn WidgetBehavior<LuaWrapper>>>,
     lua: Rc<Lua>,
@@ -61,16 +44,30 @@ impl UiBuilder {
     }
 
     pub fn build(&self, data: &LuaWrapper, config_dir: &Path) -> Result<Widget, String> {
-        let root_widget = self.build_recursive(data, config_dir)?;
+        let root_widget = self.build_recursive(data, config_dir, true)?;
         Ok(root_widget)
     }
 
+    /// Builds a widget to be placed inside another one, which has to name a
+    /// type that is not a window.
+    pub fn build_child(&self, data: &LuaWrapper, config_dir: &Path) -> Result<Widget, String> {
+        self.build_recursive(data, config_dir, false)
+    }
+
     pub fn get_widget_by_id(&self, id: &str) -> Option<Widget> {
         let widget = self.widgets_by_id.borrow().get(id).cloned();
 
         widget
     }
 
+    pub fn get_widget_id(&self, widget: &Widget) -> Option<String> {
+        self.widgets_by_id
+            .borrow()
+            .iter()
+            .find(|(_, w)| *w == widget)
+            .map(|(id, _)| id.clone())
+    }
+
     pub fn register_get_widget_by_id_lua_function(
         lua: &Rc<Lua>,
         app_global: &Table,
@@ -90,156 +87,144 @@ impl UiBuilder {
         Ok(())
     }
 
-    fn build_recursive(&self, data: &LuaWrapper, config_dir: &Path) -> Result<Widget, String> {
-        let (widget, type_name) = self.instantiate_widget(data)?;
-
-        self.register_widget_id(data, &widget);
+    fn build_recursive(
+        &self,
+        data: &LuaWrapper,
+        config_dir: &Path,
+        top_level: bool,
+    ) -> Result<Widget, String> {
+        let schema = if top_level {
+            WidgetSchema::from_script(data)
+        } else {
+            WidgetSchema::from_child_script(data)
+        };
+        let schema = schema.map_err(|errors| {
+            format!(
+                "Invalid definition for widget type '{}':\n{}",
+                data.get_property("type")
+                    .and_then(|v| v.as_string())
+                    .unwrap_or_else(|| "GtkWindow".to_string()),
+                join_errors(&errors)
+            )
+        })?;
+
+        let widget = Object::with_type(schema.gtype)
+            .downcast::<Widget>()
+            .map_err(|_| "Not a widget".to_string())?;
 
-        self.set_widget_properties(data, &widget, &type_name, config_dir)?;
+        if let Some(id) = &schema.id {
+            self.widgets_by_id
+                .borrow_mut()
+                .insert(id.clone(), widget.clone());
+        }
 
-        self.connect_widget_signals(data, &widget);
+        self.set_widget_properties(&schema, &widget, config_dir)?;
 
-        self.add_widget_children(data, &widget, &type_name, config_dir)?;
+        self.connect_widget_signals(&schema, &widget);
 
-        self.apply_widget_behavior(&widget, &type_name, data);
+        self.add_widget_children(&schema, &widget, config_dir)?;
 
-        if let Some(entries) = data.get_map_entries() {
-            for (key, _) in entries {
-                if !ALLOWED_TOP_LEVEL_KEYS.contains(&key.as_str()) {
-                    return Err(format!(
-                        "Unknown top-level configuration property: '{}' for widget type '{}'. \
-                        Did you mean to put it inside 'properties = {{}}'?",
-                        key, type_name
-                    ));
-                }
-            }
+        if let Some(mlua::Value::Function(draw)) = schema.draw.clone().map(|d| d.0)
+            && let Some(area) = widget.downcast_ref::<gtk4::DrawingArea>()
+        {
+            drawing::set_draw_func(area, draw);
         }
 
-        Ok(widget)
-    }
+        lifecycle::connect_hooks(&widget, &schema.hooks);
 
-    fn instantiate_widget(&self, data: &LuaWrapper) -> Result<(Widget, String), String> {
-        let type_name = data
-            .get_property("type")
-            .and_then(|v| v.as_string())
-            .unwrap_or_else(|| "GtkWindow".to_string());
+        self.apply_widget_behavior(&widget, &schema.type_name, data);
 
-        let gtype =
-            Registry::get_type(&type_name).ok_or_else(|| format!("Unknown type: {}", type_name))?;
-
-        let object = Object::with_type(gtype);
-        let widget = object
-            .downcast::<Widget>()
-            .map_err(|_| "Not a widget".to_string())?;
-
-        Ok((widget, type_name))
-    }
-
-    fn register_widget_id(&self, data: &LuaWrapper, widget: &Widget) {
-        if let Some(id_val) = data.get_property("id")
-            && let Some(id_str) = id_val.as_string()
-        {
-            self.widgets_by_id
-                .borrow_mut()
-                .insert(id_str, widget.clone());
-        };
+        Ok(widget)
     }
 
     fn set_widget_properties(
         &self,
-        data: &LuaWrapper,
+        schema: &WidgetSchema<LuaWrapper>,
         widget: &Widget,
-        type_name: &str,
         config_dir: &Path,
     ) -> Result<(), String> {
-        if let Some(props) = data
-            .get_property("properties")
-            .and_then(|v| v.get_map_entries())
-        {
-            const CONTAINER_PROPERTIES: &[&str] =
-                &["grid_col", "grid_row", "grid_width", "grid_height"];
+        let type_name = &schema.type_name;
+        for (k, v) in &schema.properties {
+            let gtk_property_name = k.replace("_", "-");
 
-            for (k, v) in props {
-                let gtk_property_name = k.replace("_", "-");
+            if CONTAINER_PROPERTIES.contains(&k.as_str()) {
+                continue;
+            }
 
-                if CONTAINER_PROPERTIES.contains(&k.as_str()) {
+            if let Some(pspec) = widget.find_property(&gtk_property_name) {
+                if let Some(binding) = LuaBinding::from_value(&v.0) {
+                    binding.install(widget, &gtk_property_name, pspec.value_type())?;
                     continue;
                 }
-
-                if let Some(pspec) = widget.find_property(&gtk_property_name) {
-                    let is_path_prop = (k == "file" || k == "icon-name" || k == "file-name")
-                        && pspec.value_type() == GString::static_type();
-                    if is_path_prop {
-                        if let Some(path_str) = v.as_string() {
-                            let path = Path::new(&path_str);
-                            let final_path = if path.is_absolute() {
-                                path.to_path_buf()
-                            } else {
-                                config_dir.join(path)
-                            };
-
-                            let lua_string = self
-                                .lua
-                                .create_string(&*final_path.to_string_lossy())
-                                .map_err(|e| format!("Failed to create Lua string: {}", e))?;
-                            let resolved_path_wrapper = LuaWrapper(mlua::Value::String(lua_string));
-
-                            let gval = GenericConverter::to_gvalue(
-                                &resolved_path_wrapper,
-                                pspec.value_type(),
-                            )
-                            .map_err(|e| format!("Failed to convert path property: {}", e))?;
-                            widget.set_property(&gtk_property_name, gval);
+                let is_path_prop = (k == "file" || k == "icon-name" || k == "file-name")
+                    && pspec.value_type() == GString::static_type();
+                if is_path_prop {
+                    if let Some(path_str) = v.as_string() {
+                        let path = Path::new(&path_str);
+                        let final_path = if path.is_absolute() {
+                            path.to_path_buf()
                         } else {
-                            return Err(format!(
-                                "Property '{}' on type '{}' expects a string, but got non-string value",
-                                k, type_name
-                            ));
-                        }
-                    } else {
-                        let gval = GenericConverter::to_gvalue(&v, pspec.value_type())
-                            .map_err(|e| format!("Failed to convert property: {}", e))?;
+                            config_dir.join(path)
+                        };
+
+                        let lua_string = self
+                            .lua
+                            .create_string(&*final_path.to_string_lossy())
+                            .map_err(|e| format!("Failed to create Lua string: {}", e))?;
+                        let resolved_path_wrapper = LuaWrapper(mlua::Value::String(lua_string));
+
+                        let gval =
+                            GenericConverter::to_gvalue(&resolved_path_wrapper, pspec.value_type())
+                                .map_err(|e| format!("Failed to convert path property: {}", e))?;
                         widget.set_property(&gtk_property_name, gval);
+                    } else {
+                        return Err(format!(
+                            "Property '{}' on type '{}' expects a string, but got non-string value",
+                            k, type_name
+                        ));
                     }
                 } else {
-                    return Err(format!(
-                        "Property '{}' not found on type '{}'",
-                        k, type_name
-                    ));
+                    let gval = GenericConverter::to_gvalue(v, pspec.value_type())
+                        .map_err(|e| format!("Failed to convert property: {}", e))?;
+                    widget.set_property(&gtk_property_name, gval);
                 }
+            } else {
+                let names: Vec<String> = widget
+                    .list_properties()
+                    .iter()
+                    .map(|p| p.name().replace('-', "_"))
+                    .collect();
+                let suggestion = suggest(k, names.iter().map(String::as_str))
+                    .map(|s| format!(" Did you mean '{}'?", s))
+                    .unwrap_or_default();
+                return Err(format!(
+                    "Property '{}' not found on type '{}'.{}",
+                    k, type_name, suggestion
+                ));
             }
         }
         Ok(())
     }
 
-    fn connect_widget_signals(&self, data: &LuaWrapper, widget: &Widget) {
-        if let Some(sigs) = data
-            .get_property("signals")
-            .and_then(|v| v.get_map_entries())
-        {
-            for (name, func) in sigs {
-                if func.is_function() {
-                    SignalConnector::connect(widget.upcast_ref(), &name, func);
-                }
+    fn connect_widget_signals(&self, schema: &WidgetSchema<LuaWrapper>, widget: &Widget) {
+        for (name, func) in &schema.signals {
+            if func.is_function() {
+                SignalConnector::connect(widget.upcast_ref(), name, func.clone());
             }
         }
     }
 
     fn add_widget_children(
         &self,
-        data: &LuaWrapper,
+        schema: &WidgetSchema<LuaWrapper>,
         widget: &Widget,
-        type_name: &str,
         config_dir: &Path,
     ) -> Result<(), String> {
-        if let Some(children) = data
-            .get_property("children")
-            .and_then(|v| v.get_array_items())
-        {
-            let strategy = Registry::get_strategy(type_name, widget);
-            for child_data in children {
-                let child_widget = self.build_recursive(&child_data, config_dir)?;
-                strategy.add_child(&child_widget, &child_data)?;
+        if !schema.children.is_empty() {
+            let strategy = Registry::get_strategy(&schema.type_name, widget);
+            for child_data in &schema.children {
+                let child_widget = self.build_recursive(child_data, config_dir, false)?;
+                strategy.add_child(&child_widget, child_data)?;
             }
         }
         Ok(())
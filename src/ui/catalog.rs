@@ -73,5 +73,6 @@ pub fn init() {
         gtk4::LockButton,
         gtk4::PopoverMenu,
         gtk4::PopoverMenuBar,
+        crate::ui::workspaces::InkWorkspaces,
     );
 }
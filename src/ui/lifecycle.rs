@@ -0,0 +1,172 @@
+use crate::scripting::traits::{ScriptArg, ScriptValue};
+use gtk4::Widget;
+use gtk4::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::rc::Rc;
+
+/// Lifecycle hooks accepted on any widget definition.
+pub const HOOKS: &[&str] = &["realize", "map", "unmap", "destroy"];
+
+type Cleanup = Box<dyn FnOnce(&Widget)>;
+
+thread_local! {
+    static CLEANUPS: RefCell<HashMap<usize, Vec<(u64, Cleanup)>>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u64> = const { Cell::new(0) };
+    static OWNERS: RefCell<Vec<Option<Widget>>> = const { RefCell::new(Vec::new()) };
+}
+
+fn key(widget: &Widget) -> usize {
+    widget.as_ptr() as usize
+}
+
+/// A cleanup registered with `on_destroy`. Removing it drops the cleanup
+/// without running it, for resources that ended before their widget.
+#[derive(Clone, Copy, Debug)]
+pub struct CleanupHandle {
+    widget: usize,
+    id: u64,
+}
+
+impl CleanupHandle {
+    pub fn remove(self) {
+        CLEANUPS.with_borrow_mut(|cleanups| {
+            // The (possibly empty) entry is kept, since the widget's destroy
+            // handler is only connected once.
+            if let Some(list) = cleanups.get_mut(&self.widget) {
+                list.retain(|(id, _)| *id != self.id);
+            }
+        });
+    }
+}
+
+/// Shared slot for the handle returned by `track`, kept by resources that can
+/// be released before their owner is destroyed.
+#[derive(Clone, Default)]
+pub struct CleanupSlot(Rc<Cell<Option<CleanupHandle>>>);
+
+impl CleanupSlot {
+    pub fn set(&self, handle: Option<CleanupHandle>) {
+        if let Some(old) = self.0.replace(handle) {
+            old.remove();
+        }
+    }
+
+    pub fn remove(&self) {
+        self.set(None);
+    }
+}
+
+/// Runs `cleanup` once when the widget is destroyed, either by GTK or by
+/// `destroy_tree`.
+pub fn on_destroy(widget: &Widget, cleanup: impl FnOnce(&Widget) + 'static) -> CleanupHandle {
+    let id = NEXT_ID.get();
+    NEXT_ID.set(id + 1);
+    let handle = CleanupHandle {
+        widget: key(widget),
+        id,
+    };
+    let entry = (id, Box::new(cleanup) as Cleanup);
+    let first = CLEANUPS.with_borrow_mut(|cleanups| match cleanups.entry(handle.widget) {
+        Entry::Occupied(mut e) => {
+            e.get_mut().push(entry);
+            false
+        }
+        Entry::Vacant(e) => {
+            e.insert(vec![entry]);
+            true
+        }
+    });
+    if first {
+        widget.connect_destroy(run_cleanups);
+    }
+    handle
+}
+
+fn run_cleanups(widget: &Widget) {
+    let cleanups = CLEANUPS.with_borrow_mut(|cleanups| cleanups.remove(&key(widget)));
+    detached(|| {
+        for (_, cleanup) in cleanups.into_iter().flatten() {
+            cleanup(widget);
+        }
+    });
+}
+
+/// Runs the destroy hooks and cleanups of `widget` and all of its
+/// descendants, children first. Used before a widget tree is removed, since
+/// widgets that are still referenced (e.g. by id) are not disposed by GTK.
+pub fn destroy_tree(widget: &Widget) {
+    let mut child = widget.first_child();
+    while let Some(c) = child {
+        destroy_tree(&c);
+        child = c.next_sibling();
+    }
+    run_cleanups(widget);
+}
+
+/// Calls `f` with `widget` as the owner of any resource created meanwhile.
+pub fn with_owner<R>(widget: &Widget, f: impl FnOnce() -> R) -> R {
+    scoped(Some(widget.clone()), f)
+}
+
+/// Calls `f` without an owner, for resources that outlive the widget whose
+/// handler happens to trigger them, such as shared state sources.
+pub fn detached<R>(f: impl FnOnce() -> R) -> R {
+    scoped(None, f)
+}
+
+fn scoped<R>(owner: Option<Widget>, f: impl FnOnce() -> R) -> R {
+    OWNERS.with_borrow_mut(|owners| owners.push(owner));
+    let result = f();
+    OWNERS.with_borrow_mut(|owners| owners.pop());
+    result
+}
+
+/// Ties a resource to the widget whose hook or signal handler is currently
+/// running, so that `release` is called when that widget is destroyed.
+/// Resources created outside of a widget are left alone.
+///
+/// The returned handle should be removed once the resource ends on its own
+/// or is cancelled, so that long-lived widgets don't accumulate cleanups.
+pub fn track(release: impl FnOnce() + 'static) -> Option<CleanupHandle> {
+    OWNERS
+        .with_borrow(|owners| owners.last().cloned().flatten())
+        .map(|owner| on_destroy(&owner, move |_| release()))
+}
+
+pub fn connect_hooks<T: ScriptValue + 'static>(widget: &Widget, hooks: &[(String, T)]) {
+    for (name, func) in hooks {
+        let hook = name.clone();
+        let func = func.clone();
+        let call = move |w: &Widget| {
+            let run = || {
+                if let Err(e) = func.call(vec![ScriptArg::Widget(w.clone())]) {
+                    eprintln!("Lifecycle Error [{}]: {}", hook, e);
+                }
+            };
+            // The widget's cleanups are already running, so nothing created
+            // by its destroy hook can be tied to it.
+            if hook == "destroy" {
+                detached(run)
+            } else {
+                with_owner(w, run)
+            }
+        };
+        match name.as_str() {
+            "realize" => {
+                widget.connect_realize(call);
+            }
+            "map" => {
+                widget.connect_map(call);
+            }
+            "unmap" => {
+                widget.connect_unmap(call);
+            }
+            "destroy" => {
+                on_destroy(widget, call);
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,5 +1,11 @@
+pub mod autohide;
 pub mod builder;
 pub mod catalog;
+pub mod lifecycle;
+pub mod monitors;
+pub mod popup;
 pub mod registry;
+pub mod schema;
 pub mod strategy;
 pub mod traits;
+pub mod workspaces;
@@ -0,0 +1,49 @@
+use crate::ui::schema::MonitorSpec;
+use gtk4::gdk;
+use gtk4::prelude::*;
+use mlua::{Lua, Result as LuaResult, Table};
+
+/// The display's monitors, in the order of its list model.
+pub fn list(display: &gdk::Display) -> Vec<gdk::Monitor> {
+    display
+        .monitors()
+        .iter::<gdk::Monitor>()
+        .flatten()
+        .collect()
+}
+
+pub fn resolve(display: &gdk::Display, spec: &MonitorSpec) -> Option<gdk::Monitor> {
+    let monitors = list(display);
+    match spec {
+        MonitorSpec::Index(i) => monitors.into_iter().nth(*i as usize - 1),
+        MonitorSpec::Connector(name) => monitors
+            .into_iter()
+            .find(|m| m.connector().is_some_and(|c| c == name.as_str())),
+        MonitorSpec::All => None,
+    }
+}
+
+/// Describes a monitor for Lua. `index` is its 1-based position in the
+/// display's monitor list.
+pub fn info(lua: &Lua, index: usize, monitor: &gdk::Monitor) -> LuaResult<Table> {
+    let table = lua.create_table()?;
+    table.set("index", index)?;
+    table.set("connector", monitor.connector().map(|s| s.to_string()))?;
+    table.set(
+        "manufacturer",
+        monitor.manufacturer().map(|s| s.to_string()),
+    )?;
+    table.set("model", monitor.model().map(|s| s.to_string()))?;
+
+    let rect = monitor.geometry();
+    let geometry = lua.create_table()?;
+    geometry.set("x", rect.x())?;
+    geometry.set("y", rect.y())?;
+    geometry.set("width", rect.width())?;
+    geometry.set("height", rect.height())?;
+    table.set("geometry", geometry)?;
+
+    table.set("scale_factor", monitor.scale_factor())?;
+    table.set("refresh_rate", monitor.refresh_rate() as f64 / 1000.0)?;
+    Ok(table)
+}
@@ -0,0 +1,296 @@
+use crate::ui::lifecycle;
+use gtk4::prelude::*;
+use gtk4::{Widget, gdk, glib};
+use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// Below or above the widget, whichever has more room, or beside it for
+    /// widgets on vertical bars.
+    Auto,
+    Below,
+    Above,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+pub const SIDES: &[(&str, Side)] = &[
+    ("auto", Side::Auto),
+    ("below", Side::Below),
+    ("above", Side::Above),
+    ("left", Side::Left),
+    ("right", Side::Right),
+];
+
+pub const ALIGNS: &[(&str, Align)] = &[
+    ("start", Align::Start),
+    ("center", Align::Center),
+    ("end", Align::End),
+];
+
+pub struct PopupOptions {
+    pub side: Side,
+    pub align: Align,
+    /// Added to the position of the popup.
+    pub offset: (i32, i32),
+    pub close_on_focus_loss: bool,
+    pub close_on_escape: bool,
+    /// Opening a popup closes the open one of the same group.
+    pub group: Option<String>,
+    pub on_close: Option<Box<dyn FnOnce()>>,
+}
+
+impl Default for PopupOptions {
+    fn default() -> Self {
+        Self {
+            side: Side::Auto,
+            align: Align::Start,
+            offset: (0, 0),
+            close_on_focus_loss: true,
+            close_on_escape: true,
+            group: Some("default".to_string()),
+            on_close: None,
+        }
+    }
+}
+
+struct OpenPopup {
+    window: glib::WeakRef<gtk4::Window>,
+    anchor: glib::WeakRef<Widget>,
+    closed_at: Cell<Option<Instant>>,
+}
+
+/// Clicking the anchor of an open popup takes focus from the popup, closing
+/// it just before the click. A popup closed this recently still toggles.
+const TOGGLE_GRACE: Duration = Duration::from_millis(300);
+
+thread_local! {
+    static GROUPS: RefCell<HashMap<String, OpenPopup>> = RefCell::new(HashMap::new());
+    /// Popups by the address of their anchor, which gets a single cleanup
+    /// closing them however often it opens one.
+    static ANCHORED: RefCell<HashMap<usize, Vec<glib::WeakRef<gtk4::Window>>>> =
+        RefCell::new(HashMap::new());
+}
+
+pub fn close(window: &gtk4::Window) {
+    lifecycle::destroy_tree(window.upcast_ref());
+    window.destroy();
+}
+
+/// Closes the open popup of `group`, returning whether it was anchored to
+/// `anchor`.
+fn close_group(group: &str, anchor: &Widget) -> bool {
+    let Some(open) = GROUPS.with_borrow_mut(|groups| groups.remove(group)) else {
+        return false;
+    };
+    let same_anchor = open.anchor.upgrade().as_ref() == Some(anchor);
+    if let Some(closed_at) = open.closed_at.get() {
+        return same_anchor && closed_at.elapsed() < TOGGLE_GRACE;
+    }
+    if let Some(window) = open.window.upgrade() {
+        close(&window);
+    }
+    same_anchor
+}
+
+/// Closes `popup` along with `anchor`.
+fn tie_to_anchor(anchor: &Widget, popup: &gtk4::Window) {
+    let key = anchor.as_ptr() as usize;
+    let first = ANCHORED.with_borrow_mut(|anchored| match anchored.entry(key) {
+        Entry::Occupied(mut e) => {
+            e.get_mut().retain(|p| p.upgrade().is_some());
+            e.get_mut().push(popup.downgrade());
+            false
+        }
+        Entry::Vacant(e) => {
+            e.insert(vec![popup.downgrade()]);
+            true
+        }
+    });
+    if first {
+        lifecycle::on_destroy(anchor, move |_| {
+            let popups = ANCHORED.with_borrow_mut(|anchored| anchored.remove(&key));
+            for popup in popups.into_iter().flatten() {
+                if let Some(popup) = popup.upgrade() {
+                    close(&popup);
+                }
+            }
+        });
+    }
+}
+
+/// Where the window's top-left corner is on its monitor, worked out from its
+/// layer shell anchors and margins.
+fn window_origin(window: &gtk4::Window, monitor: &gdk::Monitor) -> (i32, i32) {
+    if !window.is_layer_window() {
+        return (0, 0);
+    }
+    let geometry = monitor.geometry();
+    let axis = |start: Edge, end: Edge, size: i32, extent: i32| match (
+        window.is_anchor(start),
+        window.is_anchor(end),
+    ) {
+        (true, _) => window.margin(start),
+        (false, true) => extent - size - window.margin(end),
+        (false, false) => (extent - size) / 2,
+    };
+    (
+        axis(Edge::Left, Edge::Right, window.width(), geometry.width()),
+        axis(Edge::Top, Edge::Bottom, window.height(), geometry.height()),
+    )
+}
+
+fn align(align: Align, start: i32, length: i32, size: i32) -> i32 {
+    match align {
+        Align::Start => start,
+        Align::Center => start + (length - size) / 2,
+        Align::End => start + length - size,
+    }
+}
+
+/// Shows the widget made by `build` in an overlay layer shell window next to
+/// `anchor`. Returns `None` if `anchor` isn't shown, or if it already had the
+/// open popup of the group, which is closed instead so that popups toggle.
+pub fn open(
+    anchor: &Widget,
+    build: impl FnOnce() -> Result<Widget, String>,
+    mut options: PopupOptions,
+) -> Result<Option<gtk4::Window>, String> {
+    if let Some(group) = &options.group
+        && close_group(group, anchor)
+    {
+        return Ok(None);
+    }
+    let Some(root) = anchor.root().and_downcast::<gtk4::Window>() else {
+        return Ok(None);
+    };
+    let Some(monitor) = root
+        .surface()
+        .and_then(|surface| surface.display().monitor_at_surface(&surface))
+    else {
+        return Ok(None);
+    };
+    let Some(bounds) = anchor.compute_bounds(&root) else {
+        return Ok(None);
+    };
+
+    let popup = gtk4::Window::new();
+    popup.add_css_class("popup");
+    popup.set_application(root.application().as_ref());
+    popup.set_child(Some(&build()?));
+    popup.init_layer_shell();
+    popup.set_namespace(Some("ink-popup"));
+    popup.set_layer(Layer::Overlay);
+    popup.set_monitor(Some(&monitor));
+    popup.set_anchor(Edge::Top, true);
+    popup.set_anchor(Edge::Left, true);
+    // Positioned against the whole output, not the space left by bars.
+    popup.set_exclusive_zone(-1);
+    popup.set_keyboard_mode(KeyboardMode::OnDemand);
+
+    let geometry = monitor.geometry();
+    let (origin_x, origin_y) = window_origin(&root, &monitor);
+    let (x, y) = (origin_x + bounds.x() as i32, origin_y + bounds.y() as i32);
+    let (width, height) = (bounds.width() as i32, bounds.height() as i32);
+    let (_, natural) = popup.preferred_size();
+    let (popup_width, popup_height) = (natural.width(), natural.height());
+
+    let vertical_bar = root.is_layer_window()
+        && root.is_anchor(Edge::Top)
+        && root.is_anchor(Edge::Bottom)
+        && !(root.is_anchor(Edge::Left) && root.is_anchor(Edge::Right));
+    let side = match options.side {
+        Side::Auto if vertical_bar && x + width / 2 < geometry.width() / 2 => Side::Right,
+        Side::Auto if vertical_bar => Side::Left,
+        Side::Auto if y + height / 2 < geometry.height() / 2 => Side::Below,
+        Side::Auto => Side::Above,
+        side => side,
+    };
+    let (left, top) = match side {
+        Side::Left => (
+            x - popup_width,
+            align(options.align, y, height, popup_height),
+        ),
+        Side::Right => (x + width, align(options.align, y, height, popup_height)),
+        Side::Above => (
+            align(options.align, x, width, popup_width),
+            y - popup_height,
+        ),
+        _ => (align(options.align, x, width, popup_width), y + height),
+    };
+    let (left, top) = (left + options.offset.0, top + options.offset.1);
+    let clamp = |v: i32, size: i32, extent: i32| v.min(extent - size).max(0);
+    popup.set_margin(Edge::Left, clamp(left, popup_width, geometry.width()));
+    popup.set_margin(Edge::Top, clamp(top, popup_height, geometry.height()));
+
+    if options.close_on_escape {
+        let controller = gtk4::EventControllerKey::new();
+        controller.connect_key_pressed(|controller, key, _, _| {
+            if key != gdk::Key::Escape {
+                return glib::Propagation::Proceed;
+            }
+            if let Some(window) = controller.widget().and_downcast::<gtk4::Window>() {
+                close(&window);
+            }
+            glib::Propagation::Stop
+        });
+        popup.add_controller(controller);
+    }
+
+    if options.close_on_focus_loss {
+        // Only once focused, since not every compositor focuses new surfaces.
+        let focused = Cell::new(false);
+        popup.connect_is_active_notify(move |window| {
+            if window.is_active() {
+                focused.set(true);
+            } else if focused.get() {
+                close(window);
+            }
+        });
+    }
+
+    let on_close = RefCell::new(options.on_close.take());
+    let group = options.group.clone();
+    popup.connect_destroy(move |window| {
+        if let Some(group) = &group {
+            GROUPS.with_borrow(|groups| {
+                if let Some(open) = groups.get(group)
+                    && open.window.upgrade().as_ref() == Some(window)
+                {
+                    open.closed_at.set(Some(Instant::now()));
+                }
+            });
+        }
+        if let Some(on_close) = on_close.borrow_mut().take() {
+            on_close();
+        }
+    });
+
+    tie_to_anchor(anchor, &popup);
+
+    if let Some(group) = options.group {
+        GROUPS.with_borrow_mut(|groups| {
+            groups.insert(
+                group,
+                OpenPopup {
+                    window: popup.downgrade(),
+                    anchor: anchor.downgrade(),
+                    closed_at: Cell::new(None),
+                },
+            )
+        });
+    }
+    popup.present();
+    Ok(Some(popup))
+}
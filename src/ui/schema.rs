@@ -0,0 +1,914 @@
+use crate::scripting::traits::ScriptValue;
+use crate::ui::lifecycle::HOOKS;
+use crate::ui::registry::Registry;
+use gtk4::gdk;
+use gtk4::glib::Type;
+use gtk4::glib::object::ObjectClass;
+use gtk4::prelude::*;
+use gtk4_layer_shell::{Edge, KeyboardMode, Layer};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyScope {
+    /// Keys read from the table returned by the config file.
+    Config,
+    /// Keys read from the root widget of a window.
+    Window,
+    /// Keys read from any widget definition.
+    Widget,
+}
+
+pub struct KeySpec {
+    pub name: &'static str,
+    pub scope: KeyScope,
+    pub lua_type: &'static str,
+    pub description: &'static str,
+}
+
+const fn key(
+    name: &'static str,
+    scope: KeyScope,
+    lua_type: &'static str,
+    description: &'static str,
+) -> KeySpec {
+    KeySpec {
+        name,
+        scope,
+        lua_type,
+        description,
+    }
+}
+
+pub const KEYS: &[KeySpec] = &[
+    key(
+        "type",
+        KeyScope::Widget,
+        "string",
+        "GTK type name of the widget; top-level definitions default to GtkWindow",
+    ),
+    key(
+        "id",
+        KeyScope::Widget,
+        "string",
+        "Identifier for app.get_widget_by_id",
+    ),
+    key(
+        "properties",
+        KeyScope::Widget,
+        "table",
+        "GObject properties to set",
+    ),
+    key(
+        "signals",
+        KeyScope::Widget,
+        "table<string, function>",
+        "Signal handlers by signal name",
+    ),
+    key(
+        "children",
+        KeyScope::Widget,
+        "WidgetConfig[]",
+        "Child widget definitions",
+    ),
+    key(
+        "realize",
+        KeyScope::Widget,
+        "fun(widget: any)",
+        "Called when the widget is realized",
+    ),
+    key(
+        "map",
+        KeyScope::Widget,
+        "fun(widget: any)",
+        "Called when the widget is shown on screen",
+    ),
+    key(
+        "unmap",
+        KeyScope::Widget,
+        "fun(widget: any)",
+        "Called when the widget is hidden from screen",
+    ),
+    key(
+        "destroy",
+        KeyScope::Widget,
+        "fun(widget: any)",
+        "Called when the widget is destroyed",
+    ),
+    key(
+        "draw",
+        KeyScope::Widget,
+        "fun(cr: any, width: integer, height: integer)",
+        "Draw function for GtkDrawingArea",
+    ),
+    key(
+        "window_mode",
+        KeyScope::Window,
+        "\"layer_shell\" | \"normal\"",
+        "Whether the window uses layer shell",
+    ),
+    key(
+        "layer",
+        KeyScope::Window,
+        "\"background\" | \"bottom\" | \"top\" | \"overlay\"",
+        "Layer shell layer",
+    ),
+    key(
+        "anchors",
+        KeyScope::Window,
+        "{ top?: boolean, bottom?: boolean, left?: boolean, right?: boolean }",
+        "Screen edges the window is anchored to",
+    ),
+    key(
+        "margins",
+        KeyScope::Window,
+        "{ top?: integer, bottom?: integer, left?: integer, right?: integer }",
+        "Margins from the anchored edges",
+    ),
+    key(
+        "exclusive_zone",
+        KeyScope::Window,
+        "integer",
+        "Space reserved on the screen edge",
+    ),
+    key(
+        "auto_exclusive_zone",
+        KeyScope::Window,
+        "boolean",
+        "Reserve space matching the window size",
+    ),
+    key(
+        "exclusive_edge",
+        KeyScope::Window,
+        "\"top\" | \"bottom\" | \"left\" | \"right\"",
+        "Edge the exclusive zone applies to when anchored to several",
+    ),
+    key(
+        "namespace",
+        KeyScope::Window,
+        "string",
+        "Layer shell namespace for compositor layer rules",
+    ),
+    key(
+        "click_through",
+        KeyScope::Window,
+        "boolean",
+        "Let pointer input pass through the window",
+    ),
+    key(
+        "input_region",
+        KeyScope::Window,
+        "{ x: integer, y: integer, width: integer, height: integer }[]",
+        "Areas of the window that receive pointer input",
+    ),
+    key(
+        "autohide",
+        KeyScope::Window,
+        "boolean | \"fullscreen\" | { when?: \"always\" | \"fullscreen\", size?: integer, show_delay?: integer, hide_delay?: integer, duration?: integer }",
+        "Slide the window off its edge until hovered",
+    ),
+    key(
+        "keyboard_mode",
+        KeyScope::Window,
+        "\"none\" | \"exclusive\" | \"on_demand\"",
+        "Keyboard interactivity",
+    ),
+    key(
+        "monitor",
+        KeyScope::Window,
+        "integer | string | \"all\"",
+        "Monitor index (from 1) or connector name, or \"all\" for a copy on each",
+    ),
+    key(
+        "keymaps",
+        KeyScope::Window,
+        "table<string, function>",
+        "Key bindings by accelerator",
+    ),
+    key(
+        "css",
+        KeyScope::Config,
+        "string",
+        "CSS applied to the application",
+    ),
+    key("css_path", KeyScope::Config, "string", "Path to a CSS file"),
+    key(
+        "actions",
+        KeyScope::Config,
+        "{ name: string, callback: function }[]",
+        "Application actions",
+    ),
+    key("menu", KeyScope::Config, "table", "Application menu bar"),
+];
+
+/// Grid placement keys that are read by the parent grid instead of being set
+/// as properties.
+pub const CONTAINER_PROPERTIES: &[&str] = &["grid_col", "grid_row", "grid_width", "grid_height"];
+
+const WINDOW_MODES: &[(&str, WindowMode)] = &[
+    ("layer_shell", WindowMode::LayerShell),
+    ("normal", WindowMode::Normal),
+];
+
+const LAYERS: &[(&str, Layer)] = &[
+    ("background", Layer::Background),
+    ("bottom", Layer::Bottom),
+    ("top", Layer::Top),
+    ("overlay", Layer::Overlay),
+];
+
+const KEYBOARD_MODES: &[(&str, KeyboardMode)] = &[
+    ("none", KeyboardMode::None),
+    ("exclusive", KeyboardMode::Exclusive),
+    ("on_demand", KeyboardMode::OnDemand),
+];
+
+const EDGES: &[&str] = &["top", "bottom", "left", "right"];
+
+const LAYER_EDGES: &[(&str, Edge)] = &[
+    ("top", Edge::Top),
+    ("bottom", Edge::Bottom),
+    ("left", Edge::Left),
+    ("right", Edge::Right),
+];
+
+const AUTOHIDE_WHEN: &[(&str, AutohideWhen)] = &[
+    ("always", AutohideWhen::Always),
+    ("fullscreen", AutohideWhen::Fullscreen),
+];
+
+const AUTOHIDE_FIELDS: &[&str] = &["when", "size", "show_delay", "hide_delay", "duration"];
+
+const RECT_FIELDS: &[&str] = &["x", "y", "width", "height"];
+
+pub fn keys_in(scopes: &[KeyScope]) -> impl Iterator<Item = &'static KeySpec> {
+    KEYS.iter().filter(move |k| scopes.contains(&k.scope))
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { prev } else { prev + 1 };
+            prev = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Returns the candidate closest to `input`, if it is close enough to be a typo.
+pub fn suggest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (input.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(input, c), c))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+fn did_you_mean<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    match suggest(input, candidates) {
+        Some(s) => format!(" Did you mean '{}'?", s),
+        None => String::new(),
+    }
+}
+
+fn literal<T: ScriptValue>(value: &T) -> Option<String> {
+    value
+        .as_string()
+        .or_else(|| value.as_bool().map(|b| b.to_string()))
+        .or_else(|| {
+            value.as_number().map(|n| {
+                if n.fract() == 0.0 {
+                    (n as i64).to_string()
+                } else {
+                    n.to_string()
+                }
+            })
+        })
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaError {
+    pub message: String,
+}
+
+impl SchemaError {
+    fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+pub fn join_errors(errors: &[SchemaError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn entries<T: ScriptValue>(data: &T) -> Vec<(String, T)> {
+    data.get_map_entries()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(k, _)| k.parse::<i64>().is_err())
+        .collect()
+}
+
+fn expect_map<T: ScriptValue>(
+    data: &T,
+    key: &str,
+    errors: &mut Vec<SchemaError>,
+) -> Vec<(String, T)> {
+    match data.get_property(key) {
+        Some(v) if v.get_map_entries().is_some() => v.get_map_entries().unwrap_or_default(),
+        Some(v) if is_nil(&v) => Vec::new(),
+        Some(_) => {
+            errors.push(SchemaError::new(format!(
+                "'{}' must be a table of named entries",
+                key
+            )));
+            Vec::new()
+        }
+        None => Vec::new(),
+    }
+}
+
+fn is_nil<T: ScriptValue>(value: &T) -> bool {
+    !value.is_string()
+        && !value.is_number()
+        && !value.is_bool()
+        && !value.is_function()
+        && value.get_array_items().is_none()
+        && value.get_map_entries().is_none()
+}
+
+fn optional<T: ScriptValue>(data: &T, key: &str) -> Option<T> {
+    data.get_property(key).filter(|v| !is_nil(v))
+}
+
+fn optional_string<T: ScriptValue>(
+    data: &T,
+    key: &str,
+    errors: &mut Vec<SchemaError>,
+) -> Option<String> {
+    let value = optional(data, key)?;
+    let s = value.as_string();
+    if s.is_none() {
+        errors.push(SchemaError::new(format!("'{}' must be a string", key)));
+    }
+    s
+}
+
+fn optional_function<T: ScriptValue>(
+    data: &T,
+    key: &str,
+    errors: &mut Vec<SchemaError>,
+) -> Option<T> {
+    let value = optional(data, key)?;
+    if !value.is_function() {
+        errors.push(SchemaError::new(format!("'{}' must be a function", key)));
+        return None;
+    }
+    Some(value)
+}
+
+fn choice<T: ScriptValue, V: Copy>(
+    data: &T,
+    key: &str,
+    choices: &[(&'static str, V)],
+    default: V,
+    errors: &mut Vec<SchemaError>,
+) -> V {
+    let Some(value) = optional(data, key) else {
+        return default;
+    };
+    let name = value.as_string().unwrap_or_default();
+    if let Some((_, v)) = choices.iter().find(|(n, _)| *n == name) {
+        return *v;
+    }
+    let names: Vec<&str> = choices.iter().map(|(n, _)| *n).collect();
+    errors.push(SchemaError::new(format!(
+        "Invalid value '{}' for '{}', expected one of: {}.{}",
+        literal(&value).unwrap_or_default(),
+        key,
+        names.join(", "),
+        did_you_mean(&name, names.iter().copied())
+    )));
+    default
+}
+
+fn check_unknown_keys<T: ScriptValue>(
+    data: &T,
+    scopes: &[KeyScope],
+    hint: impl Fn(&str) -> Option<String>,
+    errors: &mut Vec<SchemaError>,
+) {
+    for (k, _) in entries(data) {
+        if keys_in(scopes).any(|spec| spec.name == k) {
+            continue;
+        }
+        let suggestion = suggest(&k, keys_in(scopes).map(|spec| spec.name))
+            .map(|s| format!(" Did you mean '{}'?", s))
+            .or_else(|| hint(&k))
+            .unwrap_or_default();
+        errors.push(SchemaError::new(format!(
+            "Unknown configuration property '{}'.{}",
+            k, suggestion
+        )));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    LayerShell,
+    Normal,
+}
+
+/// Which output a window is placed on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorSpec {
+    /// A 1-based position in the display's monitor list.
+    Index(u32),
+    Connector(String),
+    /// One copy of the window per monitor.
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutohideWhen {
+    /// Whenever the pointer is not over the window.
+    Always,
+    /// While a fullscreen window is shown on the same output.
+    Fullscreen,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Autohide {
+    pub when: AutohideWhen,
+    /// Pixels left on screen to hover over while hidden.
+    pub size: i32,
+    pub show_delay: u32,
+    pub hide_delay: u32,
+    /// Length of the slide animation in milliseconds.
+    pub duration: u32,
+}
+
+impl Default for Autohide {
+    fn default() -> Self {
+        Self {
+            when: AutohideWhen::Always,
+            size: 2,
+            show_delay: 100,
+            hide_delay: 500,
+            duration: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Edges<V> {
+    pub top: V,
+    pub bottom: V,
+    pub left: V,
+    pub right: V,
+}
+
+fn edges<T: ScriptValue, V: Default>(
+    data: &T,
+    key: &str,
+    convert: impl Fn(&T) -> Option<V>,
+    expected: &str,
+    errors: &mut Vec<SchemaError>,
+) -> Option<Edges<V>> {
+    optional(data, key)?;
+    let mut result = Edges::default();
+    for (edge, value) in expect_map(data, key, errors) {
+        let slot = match edge.as_str() {
+            "top" => &mut result.top,
+            "bottom" => &mut result.bottom,
+            "left" => &mut result.left,
+            "right" => &mut result.right,
+            _ => {
+                errors.push(SchemaError::new(format!(
+                    "Unknown edge '{}' in '{}', expected one of: {}.{}",
+                    edge,
+                    key,
+                    EDGES.join(", "),
+                    did_you_mean(&edge, EDGES.iter().copied())
+                )));
+                continue;
+            }
+        };
+        match convert(&value) {
+            Some(v) => *slot = v,
+            None => errors.push(SchemaError::new(format!(
+                "'{}.{}' must be {}",
+                key, edge, expected
+            ))),
+        }
+    }
+    Some(result)
+}
+
+fn input_region<T: ScriptValue>(
+    data: &T,
+    errors: &mut Vec<SchemaError>,
+) -> Option<Vec<gdk::Rectangle>> {
+    let value = optional(data, "input_region")?;
+    let Some(items) = value.get_array_items() else {
+        errors.push(SchemaError::new(
+            "'input_region' must be an array of { x, y, width, height } tables".to_string(),
+        ));
+        return None;
+    };
+    let mut rects = Vec::new();
+    for item in items {
+        let fields: Vec<Option<i32>> = RECT_FIELDS
+            .iter()
+            .map(|f| {
+                item.get_property(f)
+                    .and_then(|v| v.as_integer())
+                    .map(|v| v as i32)
+            })
+            .collect();
+        match fields[..] {
+            [Some(x), Some(y), Some(width), Some(height)] => {
+                rects.push(gdk::Rectangle::new(x, y, width, height))
+            }
+            _ => errors.push(SchemaError::new(format!(
+                "Each 'input_region' entry needs integer {}",
+                RECT_FIELDS.join(", ")
+            ))),
+        }
+    }
+    Some(rects)
+}
+
+fn autohide<T: ScriptValue>(data: &T, errors: &mut Vec<SchemaError>) -> Option<Autohide> {
+    let value = optional(data, "autohide")?;
+    let mut result = Autohide::default();
+    if let Some(enabled) = value.as_bool() {
+        return enabled.then_some(result);
+    }
+    if value.as_string().as_deref() == Some("fullscreen") {
+        result.when = AutohideWhen::Fullscreen;
+        return Some(result);
+    }
+    if value.get_map_entries().is_none() {
+        errors.push(SchemaError::new(
+            "'autohide' must be a boolean, \"fullscreen\" or a table".to_string(),
+        ));
+        return None;
+    }
+    result.when = choice(&value, "when", AUTOHIDE_WHEN, AutohideWhen::Always, errors);
+    for (field, v) in expect_map(data, "autohide", errors) {
+        if !AUTOHIDE_FIELDS.contains(&field.as_str()) {
+            errors.push(SchemaError::new(format!(
+                "Unknown field '{}' in 'autohide', expected one of: {}.{}",
+                field,
+                AUTOHIDE_FIELDS.join(", "),
+                did_you_mean(&field, AUTOHIDE_FIELDS.iter().copied())
+            )));
+            continue;
+        }
+        if field == "when" {
+            continue;
+        }
+        let Some(n) = v.as_integer().filter(|n| *n >= 0) else {
+            errors.push(SchemaError::new(format!(
+                "'autohide.{}' must be a non-negative integer",
+                field
+            )));
+            continue;
+        };
+        match field.as_str() {
+            "size" => result.size = n as i32,
+            "show_delay" => result.show_delay = n as u32,
+            "hide_delay" => result.hide_delay = n as u32,
+            _ => result.duration = n as u32,
+        }
+    }
+    Some(result)
+}
+
+pub struct WindowSchema<T> {
+    pub window_mode: WindowMode,
+    pub layer: Layer,
+    pub anchors: Option<Edges<bool>>,
+    pub margins: Edges<Option<i32>>,
+    pub exclusive_zone: Option<i32>,
+    pub auto_exclusive_zone: bool,
+    pub exclusive_edge: Option<Edge>,
+    pub namespace: Option<String>,
+    /// Areas that receive pointer input, empty for a click-through window.
+    pub input_region: Option<Vec<gdk::Rectangle>>,
+    pub autohide: Option<Autohide>,
+    pub keyboard_mode: KeyboardMode,
+    pub monitor: Option<MonitorSpec>,
+    pub keymaps: Vec<(String, T)>,
+}
+
+impl<T: ScriptValue> WindowSchema<T> {
+    pub fn from_script(data: &T) -> Result<Self, Vec<SchemaError>> {
+        let mut errors = Vec::new();
+        let schema = Self::parse(data, &mut errors);
+        if errors.is_empty() {
+            Ok(schema)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn parse(data: &T, errors: &mut Vec<SchemaError>) -> Self {
+        let exclusive_zone = optional(data, "exclusive_zone").and_then(|v| {
+            if v.as_integer().is_none() {
+                errors.push(SchemaError::new(
+                    "'exclusive_zone' must be an integer".to_string(),
+                ));
+            }
+            v.as_integer().map(|z| z as i32)
+        });
+        let auto_exclusive_zone = optional(data, "auto_exclusive_zone").is_some_and(|v| {
+            if v.as_bool().is_none() {
+                errors.push(SchemaError::new(
+                    "'auto_exclusive_zone' must be a boolean".to_string(),
+                ));
+            }
+            v.as_bool().unwrap_or(false)
+        });
+
+        let exclusive_edge = optional(data, "exclusive_edge")
+            .map(|_| choice(data, "exclusive_edge", LAYER_EDGES, Edge::Top, errors));
+        let namespace = optional_string(data, "namespace", errors);
+        let click_through = optional(data, "click_through").is_some_and(|v| {
+            if v.as_bool().is_none() {
+                errors.push(SchemaError::new(
+                    "'click_through' must be a boolean".to_string(),
+                ));
+            }
+            v.as_bool().unwrap_or(false)
+        });
+        let input_region = if click_through {
+            Some(Vec::new())
+        } else {
+            input_region(data, errors)
+        };
+
+        let monitor = optional(data, "monitor").and_then(|v| {
+            let spec = match (v.as_integer(), v.as_string()) {
+                (Some(i), _) if i >= 1 => Some(MonitorSpec::Index(i as u32)),
+                (_, Some(s)) if s == "all" => Some(MonitorSpec::All),
+                (_, Some(s)) if !s.is_empty() => Some(MonitorSpec::Connector(s)),
+                _ => None,
+            };
+            if spec.is_none() {
+                errors.push(SchemaError::new(
+                    "'monitor' must be an index from 1, a connector name or \"all\"".to_string(),
+                ));
+            }
+            spec
+        });
+
+        let keymaps = expect_map(data, "keymaps", errors);
+        for (accel, func) in &keymaps {
+            if gtk4::is_initialized() && gtk4::accelerator_parse(accel).is_none() {
+                errors.push(SchemaError::new(format!(
+                    "Invalid keybind string '{}'",
+                    accel
+                )));
+            } else if !func.is_function() {
+                errors.push(SchemaError::new(format!(
+                    "Keymap '{}' must be a function",
+                    accel
+                )));
+            }
+        }
+
+        Self {
+            window_mode: choice(
+                data,
+                "window_mode",
+                WINDOW_MODES,
+                WindowMode::LayerShell,
+                errors,
+            ),
+            layer: choice(data, "layer", LAYERS, Layer::Top, errors),
+            anchors: edges(data, "anchors", |v| v.as_bool(), "a boolean", errors),
+            margins: edges(
+                data,
+                "margins",
+                |v| v.as_integer().map(|m| Some(m as i32)),
+                "an integer",
+                errors,
+            )
+            .unwrap_or_default(),
+            exclusive_zone,
+            auto_exclusive_zone,
+            exclusive_edge,
+            namespace,
+            input_region,
+            autohide: autohide(data, errors),
+            keyboard_mode: choice(
+                data,
+                "keyboard_mode",
+                KEYBOARD_MODES,
+                KeyboardMode::OnDemand,
+                errors,
+            ),
+            monitor,
+            keymaps: keymaps
+                .into_iter()
+                .filter(|(_, f)| f.is_function())
+                .collect(),
+        }
+    }
+}
+
+pub struct WidgetSchema<T> {
+    pub type_name: String,
+    pub gtype: Type,
+    pub id: Option<String>,
+    pub properties: Vec<(String, T)>,
+    pub signals: Vec<(String, T)>,
+    pub children: Vec<T>,
+    pub hooks: Vec<(String, T)>,
+    pub draw: Option<T>,
+}
+
+impl<T: ScriptValue> WidgetSchema<T> {
+    /// Reads a top-level definition, whose type defaults to `GtkWindow`.
+    pub fn from_script(data: &T) -> Result<Self, Vec<SchemaError>> {
+        Self::parse(data, true)
+    }
+
+    /// Reads the definition of a child widget, which has to name its type.
+    pub fn from_child_script(data: &T) -> Result<Self, Vec<SchemaError>> {
+        Self::parse(data, false)
+    }
+
+    fn parse(data: &T, top_level: bool) -> Result<Self, Vec<SchemaError>> {
+        let mut errors = Vec::new();
+
+        let type_value = optional(data, "type");
+        let type_name = match &type_value {
+            None if top_level => "GtkWindow".to_string(),
+            None => {
+                errors.push(SchemaError::new(
+                    "Child widgets must have a 'type'".to_string(),
+                ));
+                return Err(errors);
+            }
+            Some(v) => v.as_string().unwrap_or_default(),
+        };
+        let Some(gtype) = Registry::get_type(&type_name) else {
+            let known: Vec<String> = Registry::get_all_types()
+                .iter()
+                .map(|t| t.name().to_string())
+                .collect();
+            errors.push(SchemaError::new(format!(
+                "Unknown type: {}.{}",
+                type_name,
+                did_you_mean(&type_name, known.iter().map(String::as_str))
+            )));
+            return Err(errors);
+        };
+
+        let is_window = gtype.is_a(gtk4::Window::static_type());
+        if is_window && !top_level {
+            errors.push(SchemaError::new(format!(
+                "'{}' is a window and cannot be a child widget",
+                type_name
+            )));
+            return Err(errors);
+        }
+        let scopes: &[KeyScope] = if is_window {
+            &[KeyScope::Widget, KeyScope::Window, KeyScope::Config]
+        } else {
+            &[KeyScope::Widget]
+        };
+        let class = ObjectClass::from_type(gtype);
+        check_unknown_keys(
+            data,
+            scopes,
+            |k| {
+                class
+                    .as_ref()
+                    .and_then(|c| c.find_property(&k.replace('_', "-")))
+                    .map(|_| {
+                        format!(
+                            " '{}' is a property of {}, did you mean to put it inside 'properties = {{}}'?",
+                            k, type_name
+                        )
+                    })
+            },
+            &mut errors,
+        );
+
+        let id = optional_string(data, "id", &mut errors);
+        let properties = expect_map(data, "properties", &mut errors);
+        let signals = expect_map(data, "signals", &mut errors);
+        let children = match optional(data, "children") {
+            None => Vec::new(),
+            Some(v) => match v.get_array_items() {
+                Some(items) => items,
+                None => {
+                    errors.push(SchemaError::new(
+                        "'children' must be an array of widget definitions".to_string(),
+                    ));
+                    Vec::new()
+                }
+            },
+        };
+        let hooks = HOOKS
+            .iter()
+            .filter_map(|hook| {
+                optional_function(data, hook, &mut errors).map(|f| (hook.to_string(), f))
+            })
+            .collect();
+        let draw = optional_function(data, "draw", &mut errors);
+        if draw.is_some() && !gtype.is_a(gtk4::DrawingArea::static_type()) {
+            errors.push(SchemaError::new(format!(
+                "'draw' is only supported on GtkDrawingArea, not '{}'",
+                type_name
+            )));
+        }
+        if is_window {
+            WindowSchema::parse(data, &mut errors);
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(Self {
+            type_name,
+            gtype,
+            id,
+            properties,
+            signals,
+            children,
+            hooks,
+            draw,
+        })
+    }
+}
+
+pub struct ConfigSchema<T> {
+    pub css: Option<String>,
+    pub css_path: Option<String>,
+    pub windows: Vec<T>,
+}
+
+impl<T: ScriptValue> ConfigSchema<T> {
+    /// Reads the table returned by the config file. Keys of a single window
+    /// config are left to `WidgetSchema`, which validates them when building.
+    /// Windows may also be functions that build a definition per monitor.
+    pub fn from_script(data: &T) -> Result<Self, Vec<SchemaError>> {
+        let mut errors = Vec::new();
+        let windows = data
+            .get_array_items()
+            .filter(|items| {
+                items
+                    .first()
+                    .is_some_and(|first| first.get_map_entries().is_some() || first.is_function())
+            })
+            .unwrap_or_default();
+        let is_array = !windows.is_empty();
+        if is_array {
+            check_unknown_keys(data, &[KeyScope::Config], |_| None, &mut errors);
+        }
+
+        let css = optional_string(data, "css", &mut errors);
+        let css_path = optional_string(data, "css_path", &mut errors);
+        if let Some(v) = optional(data, "actions")
+            && v.get_array_items().is_none()
+        {
+            errors.push(SchemaError::new(
+                "'actions' must be an array of { name, callback } tables".to_string(),
+            ));
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(Self {
+            css,
+            css_path,
+            windows: if is_array {
+                windows
+            } else {
+                vec![data.clone()]
+            },
+        })
+    }
+}
@@ -1,8 +1,14 @@
 use crate::scripting::traits::ScriptValue;
+use crate::ui::autohide;
+use crate::ui::monitors;
+use crate::ui::schema::{AutohideWhen, Edges, MonitorSpec, WindowMode, WindowSchema, join_errors};
 use crate::ui::traits::{WidgetBehavior, WidgetContainer};
-use gtk4::Widget;
+use gtk4::cairo::{RectangleInt, Region};
+use gtk4::glib::translate::{IntoGlib, ToGlibPtr};
 use gtk4::prelude::*;
-use gtk4_layer_shell::{Edge, Layer, LayerShell};
+use gtk4::{Widget, gdk};
+use gtk4_layer_shell::{Edge, LayerShell};
+use std::ffi::c_int;
 pub struct WindowStrategy {
     pub force_windowed: bool,
 }
@@ -11,109 +17,181 @@ impl WindowStrategy {
     pub fn new(force_windowed: bool) -> Self {
         Self { force_windowed }
     }
-    fn set_anchor<T: ScriptValue>(w: &gtk4::Window, data: &T, key: &str, edge: Edge) {
-        if let Some(val) = data.get_property(key).and_then(|v| v.as_bool()) {
-            w.set_anchor(edge, val);
+    fn connect_keybindings<T: ScriptValue + 'static>(
+        window: &gtk4::Window,
+        keymaps: Vec<(String, T)>,
+    ) {
+        if keymaps.is_empty() {
+            return;
         }
-    }
-    fn connect_keybindings<T: ScriptValue + 'static>(window: &gtk4::Window, data: &T) {
-        if let Some(maps) = data
-            .get_property("keymaps")
-            .and_then(|v| v.get_map_entries())
-        {
-            let controller = gtk4::EventControllerKey::new();
-            controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
-            let mut bindings = Vec::new();
-            for (key_str, func) in maps {
-                if let Some((keyval, mods)) = gtk4::accelerator_parse(&key_str) {
-                    bindings.push((keyval, mods, func));
-                } else {
-                    eprintln!("Warn: Invalid keybind string '{}'", key_str);
-                }
+        let controller = gtk4::EventControllerKey::new();
+        controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        let mut bindings = Vec::new();
+        for (key_str, func) in keymaps {
+            if let Some((keyval, mods)) = gtk4::accelerator_parse(&key_str) {
+                bindings.push((keyval, mods, func));
+            } else {
+                eprintln!("Warn: Invalid keybind string '{}'", key_str);
             }
-            controller.connect_key_pressed(move |_, keyval, _keycode, state| {
-                for (bind_key, bind_mods, func) in &bindings {
-                    if keyval == *bind_key && state.contains(*bind_mods) {
-                        if let Err(e) = func.call(vec![]) {
-                            eprintln!("Keybind Error: {}", e);
-                        }
-                        return gtk4::glib::Propagation::Stop;
+        }
+        controller.connect_key_pressed(move |_, keyval, _keycode, state| {
+            for (bind_key, bind_mods, func) in &bindings {
+                if keyval == *bind_key && state.contains(*bind_mods) {
+                    if let Err(e) = func.call(vec![]) {
+                        eprintln!("Keybind Error: {}", e);
                     }
+                    return gtk4::glib::Propagation::Stop;
                 }
-                gtk4::glib::Propagation::Proceed
-            });
-            window.add_controller(controller);
-        }
+            }
+            gtk4::glib::Propagation::Proceed
+        });
+        window.add_controller(controller);
     }
 
-    fn apply_window_mode<T: ScriptValue>(
+    fn apply_window_mode<T>(
         strategy: &WindowStrategy,
         window: &gtk4::Window,
-        data: &T,
-    ) {
-        let mode = data
-            .get_property("window_mode")
-            .and_then(|v| v.as_string())
-            .unwrap_or_else(|| "layer_shell".into());
-
-        if strategy.force_windowed || mode == "normal" {
+        schema: &WindowSchema<T>,
+    ) -> bool {
+        if strategy.force_windowed || schema.window_mode == WindowMode::Normal {
             if window.is_visible() {
                 window.present();
             }
-            return;
+            return false;
         }
         window.init_layer_shell();
+        // The namespace can't change once the surface is mapped. Without one,
+        // gtk4-layer-shell's default is kept.
+        if let Some(namespace) = &schema.namespace {
+            window.set_namespace(Some(namespace));
+        }
         if window.is_visible() {
             window.present();
         }
+        true
     }
 
-    fn apply_layer_shell_properties<T: ScriptValue>(window: &gtk4::Window, data: &T) {
-        let layer = match data
-            .get_property("layer")
-            .and_then(|v| v.as_string())
-            .as_deref()
-        {
-            Some("bottom") => Layer::Bottom,
-            Some("overlay") => Layer::Overlay,
-            Some("background") => Layer::Background,
-            _ => Layer::Top,
+    /// `gtk_layer_set_exclusive_edge` is only in gtk4-layer-shell 1.1 and up
+    /// and not wrapped by the bindings, so it is looked up at runtime.
+    fn set_exclusive_edge(window: &gtk4::Window, edge: Edge) {
+        type SetExclusiveEdge = unsafe extern "C" fn(*mut gtk4::ffi::GtkWindow, c_int);
+        let symbol =
+            unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"gtk_layer_set_exclusive_edge".as_ptr()) };
+        if symbol.is_null() {
+            eprintln!("Warn: 'exclusive_edge' needs gtk4-layer-shell 1.1 or newer");
+            return;
+        }
+        // SAFETY: the symbol has this signature in every release that has it.
+        unsafe {
+            let set: SetExclusiveEdge = std::mem::transmute(symbol);
+            set(window.to_glib_none().0, edge.into_glib());
+        }
+    }
+
+    /// Limits pointer input to `rects`, or lets it all through if there are
+    /// none. GTK recomputes the region on resize, so it is set again after
+    /// every layout.
+    fn apply_input_region(window: &gtk4::Window, rects: Vec<gdk::Rectangle>) {
+        let rects: Vec<RectangleInt> = rects
+            .iter()
+            .map(|r| RectangleInt::new(r.x(), r.y(), r.width(), r.height()))
+            .collect();
+        let region = Region::create_rectangles(&rects);
+        let track = move |surface: &gdk::Surface| {
+            surface.set_input_region(&region);
+            let region = region.clone();
+            surface.connect_layout(move |surface, _, _| surface.set_input_region(&region));
         };
-        window.set_layer(layer);
+        if let Some(surface) = window.surface() {
+            track(&surface);
+        }
+        window.connect_realize(move |window| {
+            if let Some(surface) = window.surface() {
+                track(&surface);
+            }
+        });
+    }
+
+    fn apply_layer_shell_properties<T>(window: &gtk4::Window, schema: &WindowSchema<T>) {
+        window.set_layer(schema.layer);
 
-        if let Some(anchors) = data.get_property("anchors") {
-            WindowStrategy::set_anchor(window, &anchors, "top", Edge::Top);
-            WindowStrategy::set_anchor(window, &anchors, "bottom", Edge::Bottom);
-            WindowStrategy::set_anchor(window, &anchors, "left", Edge::Left);
-            WindowStrategy::set_anchor(window, &anchors, "right", Edge::Right);
-        } else {
-            window.set_anchor(Edge::Top, true);
-            window.set_anchor(Edge::Left, true);
-            window.set_anchor(Edge::Right, true);
+        let anchors = schema.anchors.unwrap_or(Edges {
+            top: true,
+            bottom: false,
+            left: true,
+            right: true,
+        });
+        window.set_anchor(Edge::Top, anchors.top);
+        window.set_anchor(Edge::Bottom, anchors.bottom);
+        window.set_anchor(Edge::Left, anchors.left);
+        window.set_anchor(Edge::Right, anchors.right);
+
+        let margins = [
+            (Edge::Top, schema.margins.top),
+            (Edge::Bottom, schema.margins.bottom),
+            (Edge::Left, schema.margins.left),
+            (Edge::Right, schema.margins.right),
+        ];
+        for (edge, margin) in margins {
+            if let Some(m) = margin {
+                window.set_margin(edge, m);
+            }
         }
 
-        if let Some(z) = data
-            .get_property("exclusive_zone")
-            .and_then(|v| v.as_integer())
-        {
-            window.set_exclusive_zone(z as i32);
-        } else if let Some(true) = data
-            .get_property("auto_exclusive_zone")
-            .and_then(|v| v.as_bool())
-        {
+        // A window that is hidden most of the time shouldn't reserve space.
+        let always_hidden = schema
+            .autohide
+            .is_some_and(|a| a.when == AutohideWhen::Always);
+        if let Some(z) = schema.exclusive_zone {
+            window.set_exclusive_zone(z);
+        } else if schema.auto_exclusive_zone && !always_hidden {
             window.auto_exclusive_zone_enable();
         }
+        if let Some(edge) = schema.exclusive_edge {
+            Self::set_exclusive_edge(window, edge);
+        }
 
-        let kb_mode = data
-            .get_property("keyboard_mode")
-            .and_then(|v| v.as_string())
-            .unwrap_or_else(|| "on_demand".to_string());
-        let mode = match kb_mode.as_str() {
-            "exclusive" => gtk4_layer_shell::KeyboardMode::Exclusive,
-            "none" => gtk4_layer_shell::KeyboardMode::None,
-            _ => gtk4_layer_shell::KeyboardMode::OnDemand,
-        };
-        window.set_keyboard_mode(mode);
+        window.set_keyboard_mode(schema.keyboard_mode);
+
+        if let Some(options) = schema.autohide {
+            let edge = schema.exclusive_edge.unwrap_or(match anchors {
+                Edges {
+                    bottom: true,
+                    top: false,
+                    ..
+                } => Edge::Bottom,
+                Edges {
+                    left: true,
+                    right: false,
+                    top: true,
+                    bottom: true,
+                } => Edge::Left,
+                Edges {
+                    right: true,
+                    left: false,
+                    top: true,
+                    bottom: true,
+                } => Edge::Right,
+                _ => Edge::Top,
+            });
+            let margin = match edge {
+                Edge::Top => schema.margins.top,
+                Edge::Bottom => schema.margins.bottom,
+                Edge::Left => schema.margins.left,
+                _ => schema.margins.right,
+            };
+            autohide::attach(window, options, edge, margin.unwrap_or(0));
+        }
+
+        if let Some(spec) = &schema.monitor {
+            match monitors::resolve(&WidgetExt::display(window), spec) {
+                Some(monitor) => window.set_monitor(Some(&monitor)),
+                None if *spec != MonitorSpec::All => {
+                    eprintln!("Warn: No monitor matches {:?}", spec)
+                }
+                None => {}
+            }
+        }
     }
 }
 impl<T: ScriptValue + 'static> WidgetBehavior<T> for WindowStrategy {
@@ -126,9 +204,21 @@ impl<T: ScriptValue + 'static> WidgetBehavior<T> for WindowStrategy {
             return;
         }
 
-        Self::connect_keybindings(window, data);
-        Self::apply_window_mode(self, window, data);
-        Self::apply_layer_shell_properties(window, data);
+        let schema = match WindowSchema::from_script(data) {
+            Ok(schema) => schema,
+            Err(errors) => {
+                eprintln!("Invalid window definition:\n{}", join_errors(&errors));
+                return;
+            }
+        };
+
+        Self::connect_keybindings(window, schema.keymaps.clone());
+        if let Some(rects) = schema.input_region.clone() {
+            Self::apply_input_region(window, rects);
+        }
+        if Self::apply_window_mode(self, window, &schema) {
+            Self::apply_layer_shell_properties(window, &schema);
+        }
     }
 }
 #[derive(Clone)]
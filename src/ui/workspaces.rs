@@ -0,0 +1,153 @@
+use crate::services::compositor::{self, Snapshot};
+use gtk4::prelude::*;
+use gtk4::subclass::prelude::*;
+use gtk4::{gdk, glib};
+
+mod imp {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    #[derive(Default, glib::Properties)]
+    #[properties(wrapper_type = super::InkWorkspaces)]
+    pub struct InkWorkspaces {
+        /// The output whose workspaces are shown: empty for the one the
+        /// widget is on, or `all` for every output.
+        #[property(get, set = Self::set_monitor)]
+        monitor: RefCell<String>,
+        pub(super) subscription: Cell<Option<u64>>,
+        pub(super) snapshot: RefCell<Option<Snapshot>>,
+        pub(super) enter_monitor: RefCell<Option<(gdk::Surface, glib::SignalHandlerId)>>,
+    }
+
+    impl InkWorkspaces {
+        fn set_monitor(&self, monitor: String) {
+            self.monitor.replace(monitor);
+            self.obj().render();
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for InkWorkspaces {
+        const NAME: &'static str = "InkWorkspaces";
+        type Type = super::InkWorkspaces;
+        type ParentType = gtk4::Box;
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for InkWorkspaces {
+        fn constructed(&self) {
+            self.parent_constructed();
+            self.obj().add_css_class("workspaces");
+        }
+    }
+
+    impl WidgetImpl for InkWorkspaces {
+        fn map(&self) {
+            self.parent_map();
+            self.obj().watch();
+        }
+
+        fn unmap(&self) {
+            if let Some(id) = self.subscription.take() {
+                compositor::unsubscribe(id);
+            }
+            if let Some((surface, handler)) = self.enter_monitor.take() {
+                surface.disconnect(handler);
+            }
+            self.parent_unmap();
+        }
+    }
+
+    impl BoxImpl for InkWorkspaces {}
+}
+
+glib::wrapper! {
+    /// A row of buttons for the workspaces of one output, kept in sync with
+    /// the compositor while the widget is mapped.
+    pub struct InkWorkspaces(ObjectSubclass<imp::InkWorkspaces>)
+        @extends gtk4::Box, gtk4::Widget,
+        @implements gtk4::Accessible, gtk4::Buildable, gtk4::ConstraintTarget, gtk4::Orientable;
+}
+
+impl InkWorkspaces {
+    fn watch(&self) {
+        if self.imp().enter_monitor.borrow().is_none()
+            && let Some(surface) = self.native().and_then(|native| native.surface())
+        {
+            let weak = self.downgrade();
+            let handler = surface.connect_enter_monitor(move |_, _| {
+                if let Some(widget) = weak.upgrade() {
+                    widget.render();
+                }
+            });
+            self.imp().enter_monitor.replace(Some((surface, handler)));
+        }
+        if self.imp().subscription.get().is_some() {
+            return;
+        }
+        let weak = self.downgrade();
+        let subscribed = compositor::subscribe(move |snapshot| {
+            if let Some(widget) = weak.upgrade() {
+                widget.imp().snapshot.replace(Some(snapshot.clone()));
+                widget.render();
+            }
+        });
+        match subscribed {
+            Ok(id) => self.imp().subscription.set(Some(id)),
+            Err(e) => eprintln!("InkWorkspaces: {}", e),
+        }
+    }
+
+    /// The connector name of the monitor the widget is shown on.
+    fn own_output(&self) -> Option<String> {
+        let surface = self.native()?.surface()?;
+        let monitor = surface.display().monitor_at_surface(&surface)?;
+        monitor.connector().map(|c| c.to_string())
+    }
+
+    fn render(&self) {
+        while let Some(child) = self.first_child() {
+            self.remove(&child);
+        }
+        let snapshot = self.imp().snapshot.borrow();
+        let Some(snapshot) = snapshot.as_ref() else {
+            return;
+        };
+        let output = match self.monitor().as_str() {
+            // Until the surface is on a monitor, there is nothing to show.
+            "" => match self.own_output() {
+                Some(output) => Some(output),
+                None => return,
+            },
+            "all" => None,
+            name => Some(name.to_string()),
+        };
+        for workspace in &snapshot.workspaces {
+            if output.is_some() && workspace.output != output {
+                continue;
+            }
+            let button = gtk4::Button::with_label(&workspace.name);
+            button.add_css_class("workspace");
+            for (class, set) in [
+                ("active", workspace.active),
+                ("focused", workspace.focused),
+                ("urgent", workspace.urgent),
+                ("occupied", workspace.windows > 0),
+                ("empty", workspace.windows == 0),
+            ] {
+                if set {
+                    button.add_css_class(class);
+                }
+            }
+            let id = workspace.id;
+            button.connect_clicked(move |_| {
+                glib::MainContext::default().spawn_local(async move {
+                    if let Err(e) = compositor::focus_workspace(id).await {
+                        eprintln!("Compositor error: {}", e);
+                    }
+                });
+            });
+            self.append(&button);
+        }
+    }
+}